@@ -0,0 +1,428 @@
+//! A `.zip`, `.tar`, `.tar.gz` or `.tgz` root: the archive's regular files
+//! are unpacked into a private temporary directory (a random name, only
+//! accessible to the user), which is then walked
+//! like any other root, so every filter and check applies unchanged.
+//!
+//! Entries are checked by name before anything is written: those the walk
+//! would leave out anyway (excludes, the built-in list, `--ext`, ...) are
+//! never unpacked, and those over `--max-file-size` only get an empty
+//! placeholder of their size, so a huge or hostile archive doesn't fill
+//! the disk with files the dump won't print. Without `--max-file-size`,
+//! entries over [`MAX_ENTRY_SIZE`] are skipped, and once
+//! [`MAX_TOTAL_SIZE`] has been written the remaining entries are too, so
+//! a small archive can't unpack to gigabytes. Links, devices, encrypted
+//! entries, names escaping the archive and names clashing with another
+//! entry's (a file `a` and a file `a/b`) are skipped.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use flate2::read::GzDecoder;
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+fn kind(path: &Path) -> Option<Kind> {
+    let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+    if name.ends_with(".zip") {
+        Some(Kind::Zip)
+    } else if name.ends_with(".tar") {
+        Some(Kind::Tar)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(Kind::TarGz)
+    } else {
+        None
+    }
+}
+
+/// True if `root` is an archive file to unpack rather than a directory.
+pub fn is_archive(root: &Path) -> bool {
+    root.is_file() && kind(root).is_some()
+}
+
+/// The unpacked archive; the directory is removed on drop.
+pub struct Unpacked {
+    pub dir: PathBuf,
+    _temp: tempfile::TempDir,
+}
+
+/// Entries larger than this are skipped when there is no `max_file_size`.
+pub const MAX_ENTRY_SIZE: u64 = 256 << 20;
+
+/// Entries that would take the unpacked total past this are skipped.
+pub const MAX_TOTAL_SIZE: u64 = 1 << 30;
+
+/// Which entries to unpack, and how.
+pub struct Filter<'a> {
+    /// Whether the walk would keep a file at this path, relative to the
+    /// archive's root.
+    pub wanted: &'a dyn Fn(&Path) -> bool,
+    /// Entries larger than this are written as placeholders of their
+    /// size, for the dump to report as too large.
+    pub max_file_size: Option<u64>,
+    /// Entries larger than this are skipped (without `max_file_size`).
+    pub max_entry_size: u64,
+    /// Bytes to write at most, over all entries.
+    pub max_total_size: u64,
+}
+
+/// Unpack `archive` into a new temporary directory. Skipped entries are
+/// reported through `skipped` with the reason.
+pub fn unpack(
+    archive: &Path,
+    filter: &Filter,
+    mut skipped: impl FnMut(&str, &str),
+) -> io::Result<Unpacked> {
+    let mut builder = tempfile::Builder::new();
+    builder.prefix("dir2prompt-");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        builder.permissions(std::fs::Permissions::from_mode(0o700));
+    }
+    let temp = builder.tempdir()?;
+    let unpacked = Unpacked {
+        dir: temp.path().to_path_buf(),
+        _temp: temp,
+    };
+
+    let err = |e: io::Error| io::Error::new(e.kind(), format!("{}: {e}", archive.display()));
+    let file = File::open(archive).map_err(err)?;
+    let mut out = Writer {
+        dir: &unpacked.dir,
+        filter,
+        skipped: &mut skipped,
+        written: 0,
+    };
+    match kind(archive).expect("checked by is_archive") {
+        Kind::Zip => unpack_zip(file, &mut out),
+        Kind::Tar => unpack_tar(BufReader::new(file), &mut out),
+        Kind::TarGz => unpack_tar(GzDecoder::new(BufReader::new(file)), &mut out),
+    }
+    .map_err(err)?;
+    Ok(unpacked)
+}
+
+/// Where entries are written, and what decides which ones are.
+struct Writer<'a, F> {
+    dir: &'a Path,
+    filter: &'a Filter<'a>,
+    skipped: &'a mut F,
+    /// Bytes of content written so far.
+    written: u64,
+}
+
+impl<F: FnMut(&str, &str)> Writer<'_, F> {
+    /// Write one regular file of `size` bytes, creating its parent
+    /// directories, unless it is filtered out or can't be placed.
+    fn entry(
+        &mut self,
+        name: &str,
+        size: u64,
+        data: impl Read,
+        mtime: Option<SystemTime>,
+    ) -> io::Result<()> {
+        let Some(rel) = entry_path(name) else {
+            (self.skipped)(name, "path escapes the archive");
+            return Ok(());
+        };
+        if !(self.filter.wanted)(&rel) {
+            return Ok(());
+        }
+        let placeholder = self.filter.max_file_size.is_some_and(|max| size > max);
+        if !placeholder {
+            if self.filter.max_file_size.is_none() && size > self.filter.max_entry_size {
+                (self.skipped)(name, "larger than the unpacking limit for an entry");
+                return Ok(());
+            }
+            if self.written + size > self.filter.max_total_size {
+                (self.skipped)(name, "past the unpacking limit for the archive");
+                return Ok(());
+            }
+        }
+        let path = self.dir.join(&rel);
+        if clashes(self.dir, &rel) {
+            (self.skipped)(name, "clashes with another entry's path");
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = File::create(&path)?;
+        if placeholder {
+            // Sparse on most filesystems: the size without the bytes.
+            file.set_len(size)?;
+        } else {
+            self.written += io::copy(&mut data.take(size), &mut file)?;
+        }
+        if let Some(mtime) = mtime {
+            let _ = file.set_modified(mtime);
+        }
+        Ok(())
+    }
+}
+
+/// Entry `name` relative to the archive's root; `None` for names that are
+/// empty or would escape it (`..`, absolute paths).
+fn entry_path(name: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!path.as_os_str().is_empty()).then_some(path)
+}
+
+/// True if an earlier entry left a file where `rel` needs a directory, or
+/// a directory where it needs a file.
+fn clashes(dir: &Path, rel: &Path) -> bool {
+    let mut path = dir.to_path_buf();
+    let mut parts = rel.components().peekable();
+    while let Some(part) = parts.next() {
+        path.push(part);
+        let Ok(meta) = std::fs::symlink_metadata(&path) else {
+            return false;
+        };
+        let last = parts.peek().is_none();
+        if meta.is_dir() == last {
+            return true;
+        }
+    }
+    false
+}
+
+fn unpack_zip<F: FnMut(&str, &str)>(file: File, out: &mut Writer<F>) -> io::Result<()> {
+    let mut zip = zip::ZipArchive::new(BufReader::new(file)).map_err(io::Error::other)?;
+    for i in 0..zip.len() {
+        let name = match zip.name_for_index(i) {
+            Some(Ok(name)) => name.into_owned(),
+            _ => format!("entry {i}"),
+        };
+        let entry = match zip.by_index(i) {
+            Ok(entry) => entry,
+            Err(e) => {
+                (out.skipped)(&name, &e.to_string());
+                continue;
+            }
+        };
+        if entry.is_dir() {
+            continue;
+        }
+        if entry.is_symlink() {
+            (out.skipped)(&name, "link");
+            continue;
+        }
+        let size = entry.size();
+        let mtime = entry.last_modified().and_then(zip_mtime);
+        out.entry(&name, size, entry, mtime)?;
+    }
+    Ok(())
+}
+
+/// A zip entry's modification time. Zip stores a date and time without a
+/// time zone; it is taken as UTC.
+fn zip_mtime(time: zip::DateTime) -> Option<SystemTime> {
+    if !time.is_valid() {
+        return None;
+    }
+    let (year, month, day) = (
+        u64::from(time.year()),
+        u64::from(time.month()),
+        u64::from(time.day()),
+    );
+    // Days since 1970-01-01, counting years from March so the leap day
+    // comes last (zip years start at 1980, so nothing goes negative).
+    let year = if month <= 2 { year - 1 } else { year };
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let days = year * 365 + year / 4 - year / 100 + year / 400 + day_of_year - 719_468;
+    let secs = days * 86_400
+        + u64::from(time.hour()) * 3600
+        + u64::from(time.minute()) * 60
+        + u64::from(time.second());
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+fn unpack_tar<F: FnMut(&str, &str)>(input: impl Read, out: &mut Writer<F>) -> io::Result<()> {
+    let mut tar = tar::Archive::new(input);
+    for entry in tar.entries()? {
+        let entry = entry?;
+        let name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+        let header = entry.header();
+        match header.entry_type() {
+            tar::EntryType::Regular | tar::EntryType::Continuous => {
+                let mtime = header
+                    .mtime()
+                    .ok()
+                    .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+                let size = entry.size();
+                out.entry(&name, size, entry, mtime)?;
+            }
+            tar::EntryType::Directory => {}
+            tar::EntryType::Symlink | tar::EntryType::Link => (out.skipped)(&name, "link"),
+            _ => (out.skipped)(&name, "not a regular file"),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn tar_of(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, data) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *data).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn zip_of(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+        let time = zip::DateTime::from_date_and_time(2024, 2, 29, 13, 45, 10).unwrap();
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .last_modified_time(time);
+        for (name, data) in entries {
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    /// Unpack `bytes` saved as `name`, keeping what `wanted` keeps.
+    /// Returns the directory and the skipped entries' names.
+    fn unpack_bytes(
+        name: &str,
+        bytes: &[u8],
+        wanted: &dyn Fn(&Path) -> bool,
+        max_file_size: Option<u64>,
+    ) -> (Unpacked, Vec<String>) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        std::fs::write(&path, bytes).unwrap();
+        let mut skipped = Vec::new();
+        let filter = Filter {
+            wanted,
+            max_file_size,
+            max_entry_size: 64,
+            max_total_size: 100,
+        };
+        let unpacked = unpack(&path, &filter, |name, _| skipped.push(name.to_string())).unwrap();
+        (unpacked, skipped)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unpacks_where_only_the_user_can_look() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (unpacked, _) = unpack_bytes("a.zip", &zip_of(&[("a", b"x")]), &|_| true, None);
+        let mode = std::fs::metadata(&unpacked.dir)
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o700);
+        let dir = unpacked.dir.clone();
+        drop(unpacked);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn unpacks_zip_and_tar() {
+        let entries: &[(&str, &[u8])] = &[("src/main.rs", b"fn main() {}\n"), ("README", b"hi\n")];
+        for (name, bytes) in [("a.zip", zip_of(entries)), ("a.tar", tar_of(entries))] {
+            let (unpacked, skipped) = unpack_bytes(name, &bytes, &|_| true, None);
+            assert!(skipped.is_empty(), "{name}");
+            let main = std::fs::read_to_string(unpacked.dir.join("src/main.rs")).unwrap();
+            assert_eq!(main, "fn main() {}\n", "{name}");
+        }
+    }
+
+    #[test]
+    fn zip_entries_keep_their_time() {
+        let (unpacked, _) = unpack_bytes("a.zip", &zip_of(&[("a", b"x")]), &|_| true, None);
+        let mtime = std::fs::metadata(unpacked.dir.join("a"))
+            .unwrap()
+            .modified()
+            .unwrap();
+        // 2024-02-29 13:45:10 UTC.
+        let expected = SystemTime::UNIX_EPOCH + Duration::from_secs(1_709_214_310);
+        assert_eq!(mtime, expected);
+    }
+
+    #[test]
+    fn unwanted_entries_are_never_written() {
+        let bytes = zip_of(&[("node_modules/x.js", b"x"), ("index.js", b"y")]);
+        let wanted = |rel: &Path| !rel.starts_with("node_modules");
+        let (unpacked, _) = unpack_bytes("a.zip", &bytes, &wanted, None);
+        assert!(!unpacked.dir.join("node_modules").exists());
+        assert!(unpacked.dir.join("index.js").exists());
+    }
+
+    #[test]
+    fn oversized_entries_become_placeholders() {
+        let big = vec![b'x'; 4096];
+        let (unpacked, _) =
+            unpack_bytes("a.tar", &tar_of(&[("big.txt", &big)]), &|_| true, Some(100));
+        let meta = std::fs::metadata(unpacked.dir.join("big.txt")).unwrap();
+        assert_eq!(meta.len(), 4096);
+        let text = std::fs::read(unpacked.dir.join("big.txt")).unwrap();
+        assert!(text.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn unpacking_stops_at_the_limits() {
+        let big = vec![b'x'; 65];
+        let part = vec![b'x'; 40];
+        let bytes = zip_of(&[("big", &big), ("a", &part), ("b", &part), ("c", &part)]);
+        let (unpacked, skipped) = unpack_bytes("a.zip", &bytes, &|_| true, None);
+        assert_eq!(skipped, ["big", "c"]);
+        assert!(unpacked.dir.join("b").exists());
+        assert!(!unpacked.dir.join("c").exists());
+
+        // With --max-file-size, a big entry is a placeholder instead, which
+        // writes nothing.
+        let (unpacked, skipped) = unpack_bytes("a.zip", &bytes, &|_| true, Some(50));
+        assert_eq!(skipped, ["c"]);
+        assert_eq!(
+            std::fs::metadata(unpacked.dir.join("big")).unwrap().len(),
+            65
+        );
+    }
+
+    #[test]
+    fn clashing_entries_are_skipped() {
+        let bytes = tar_of(&[
+            ("a", b"file"),
+            ("a/b", b"nested"),
+            ("c/d", b"x"),
+            ("c", b"y"),
+        ]);
+        let (unpacked, skipped) = unpack_bytes("a.tar", &bytes, &|_| true, None);
+        assert_eq!(skipped, ["a/b", "c"]);
+        assert_eq!(
+            std::fs::read_to_string(unpacked.dir.join("a")).unwrap(),
+            "file"
+        );
+        assert_eq!(
+            std::fs::read_to_string(unpacked.dir.join("c/d")).unwrap(),
+            "x"
+        );
+    }
+}
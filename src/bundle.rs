@@ -0,0 +1,90 @@
+//! The `bundle` output format: a plain-text archive that can be turned back
+//! into files.
+//!
+//! ```text
+//! *** Begin Bundle
+//! *** Begin File: src/main.rs
+//! fn main() {}
+//! *** End File
+//! *** Begin File: notes.txt
+//! *** Truncated: 200000 bytes
+//! no trailing newline here
+//! *** No newline at end of file
+//! *** End File
+//! *** Skipped File: logo.png (looks like a binary file)
+//! *** End Bundle
+//! ```
+//!
+//! Every marker line starts with `***`. Content lines that would collide
+//! with a marker (any line matching `\*\*\*` preceded by zero or more
+//! backslashes) get one extra leading backslash, which the reader strips.
+//! That keeps the escaping reversible and leaves ordinary lines untouched,
+//! so the body stays readable and editable by hand (or by a model).
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::{Config, FileContent, Truncation};
+
+pub const BEGIN_BUNDLE: &str = "*** Begin Bundle";
+pub const END_BUNDLE: &str = "*** End Bundle";
+pub const BEGIN_FILE: &str = "*** Begin File: ";
+pub const END_FILE: &str = "*** End File";
+pub const SKIPPED_FILE: &str = "*** Skipped File: ";
+pub const TRUNCATED: &str = "*** Truncated: ";
+pub const NOTE: &str = "*** Note: ";
+pub const NO_EOL: &str = "*** No newline at end of file";
+
+pub fn write_header(out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "{BEGIN_BUNDLE}")
+}
+
+pub fn write_footer(out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "{END_BUNDLE}")
+}
+
+pub fn write_file(
+    out: &mut impl Write,
+    rel: &Path,
+    content: &FileContent,
+    config: &Config,
+) -> io::Result<()> {
+    let FileContent::Text {
+        text,
+        truncated,
+        encoding_note,
+    } = content
+    else {
+        let reason = content.skip_reason().unwrap_or_default();
+        return writeln!(out, "{SKIPPED_FILE}{} ({reason})", rel.display());
+    };
+
+    writeln!(out, "{BEGIN_FILE}{}", rel.display())?;
+    match truncated {
+        Some(Truncation::Lines) => {
+            writeln!(out, "{TRUNCATED}{} lines", config.max_lines.unwrap_or(0))?
+        }
+        Some(Truncation::Bytes { limit, .. }) => writeln!(out, "{TRUNCATED}{limit} bytes")?,
+        None => {}
+    }
+    if let Some(note) = encoding_note {
+        writeln!(out, "{NOTE}{note}")?;
+    }
+    for line in text.split_inclusive('\n') {
+        if needs_escape(line) {
+            out.write_all(b"\\")?;
+        }
+        out.write_all(line.as_bytes())?;
+    }
+    if !text.is_empty() && !text.ends_with('\n') {
+        writeln!(out)?;
+        writeln!(out, "{NO_EOL}")?;
+    }
+    writeln!(out, "{END_FILE}")
+}
+
+/// True for lines that, read back, would look like a marker or like an
+/// already-escaped marker.
+pub fn needs_escape(line: &str) -> bool {
+    line.trim_start_matches('\\').starts_with("***")
+}
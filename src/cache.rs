@@ -0,0 +1,379 @@
+//! `--cache`: each file's emitted content kept on disk between runs, so an
+//! unchanged file isn't decoded and transformed again.
+//!
+//! An entry is keyed by the file's path and everything in the config that
+//! shapes its content (limits, decoding, transforms, ...), and records the
+//! file's size, modification time and SHA-256. A file whose size and time
+//! still match is a hit without reading it; otherwise it is hashed, and a
+//! matching hash is still a hit. Changing any of those flags changes every
+//! key, so stale entries are simply never looked up again; so does a new
+//! [`CONTENT_VERSION`].
+//!
+//! ```text
+//! dir2prompt cache 1
+//! size 1423
+//! mtime 1760486400123456789
+//! sha256 3b4c...e1f0
+//! content text bytes 1024 1423 399
+//! note decoded as windows-1252
+//! stats 0 0 0 0 0 0
+//!
+//! (the text)
+//! ```
+//!
+//! Streamed files and read errors aren't cached.
+
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::parallel::OpenFiles;
+use crate::{Config, FileContent, Truncation, sha256, transform};
+
+const MAGIC: &str = "dir2prompt cache 1";
+
+/// Part of every key. Bump it whenever a change makes the same file and
+/// flags produce different content (as stripping the UTF-8 BOM did), so
+/// entries from older builds of the same release aren't served.
+const CONTENT_VERSION: u32 = 2;
+
+pub struct Cache {
+    dir: PathBuf,
+    /// The content-shaping flags, folded into every key.
+    flags: String,
+}
+
+/// A file as it was when it was looked up.
+struct Stamp {
+    size: u64,
+    mtime: u128,
+}
+
+impl Cache {
+    /// Use (and create) `dir` for the run configured by `config`, whose
+    /// transforms are `pipeline`.
+    pub fn open(dir: &Path, config: &Config, pipeline: &transform::Pipeline) -> io::Result<Cache> {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| io::Error::other(format!("--cache {}: {e}", dir.display())))?;
+        let flags = format!(
+            "{} {CONTENT_VERSION} {:?}",
+            env!("CARGO_PKG_VERSION"),
+            (
+                (
+                    config.max_bytes,
+                    &config.max_bytes_ext,
+                    config.soft_max_bytes,
+                    config.truncate,
+                    config.max_lines,
+                    config.max_file_size,
+                    config.content_max_depth,
+                ),
+                (
+                    &config.encoding,
+                    config.strict_utf8,
+                    crate::keeps_empty(config),
+                    config.sniff_lockfiles,
+                    config.include_lockfiles,
+                    config.skip_minified,
+                    &config.exclude_content,
+                ),
+                (
+                    pipeline,
+                    config.tabs_to_spaces,
+                    config.wrap_long_lines,
+                    config.window,
+                ),
+            )
+        );
+        Ok(Cache {
+            dir: dir.to_path_buf(),
+            flags,
+        })
+    }
+
+    /// `path`'s content from the cache, or else from `load` (then stored).
+    /// `language` and `anchors` are the file's own inputs to its content;
+    /// it is hashed under a permit from `open_files`.
+    /// Write failures only cost the next run a miss, so they are logged
+    /// and otherwise ignored.
+    pub fn load(
+        &self,
+        config: &Config,
+        path: &Path,
+        language: &str,
+        anchors: Option<&[usize]>,
+        open_files: &OpenFiles,
+        load: impl FnOnce() -> (FileContent, transform::Stats),
+    ) -> (FileContent, transform::Stats) {
+        let key = sha256::hex_digest(
+            format!(
+                "{}\0{}\0{language}\0{anchors:?}",
+                self.flags,
+                path.display()
+            )
+            .as_bytes(),
+        )
+        .expect("hashing a string can't fail");
+        let entry = self.dir.join(&key[..32]);
+        let Some(stamp) = stamp(path) else {
+            return load();
+        };
+        // Hashed before the file is loaded, so a change in between leaves
+        // an entry that won't match next time rather than a wrong one.
+        let mut digest = None;
+        if let Ok(cached) = std::fs::read_to_string(&entry)
+            && let Some(parsed) = parse(&cached)
+        {
+            let fresh = parsed.size == stamp.size && parsed.mtime == stamp.mtime;
+            if !fresh {
+                digest = file_digest(path, open_files);
+            }
+            if (fresh || digest.as_deref() == Some(parsed.sha256))
+                && let Some((content, stats)) = parsed.content()
+            {
+                if !fresh {
+                    // Touched but unchanged: record the new time.
+                    self.store(config, &entry, &stamp, parsed.sha256, &content, &stats);
+                }
+                stats.cache_hits.set(1);
+                return (content, stats);
+            }
+        }
+        let digest = match digest {
+            Some(digest) => Some(digest),
+            None => file_digest(path, open_files),
+        };
+        let (content, stats) = load();
+        if let Some(digest) = digest {
+            self.store(config, &entry, &stamp, &digest, &content, &stats);
+        }
+        stats.cache_misses.set(1);
+        (content, stats)
+    }
+
+    fn store(
+        &self,
+        config: &Config,
+        entry: &Path,
+        stamp: &Stamp,
+        digest: &str,
+        content: &FileContent,
+        stats: &transform::Stats,
+    ) {
+        let Some((kind, note, text)) = describe(content) else {
+            return;
+        };
+        let mut out = format!(
+            "{MAGIC}\nsize {}\nmtime {}\nsha256 {digest}\ncontent {kind}\n",
+            stamp.size, stamp.mtime
+        );
+        if let Some(note) = note {
+            out.push_str(&format!("note {note}\n"));
+        }
+        out.push_str(&format!(
+            "stats {} {} {} {} {} {}\n\n",
+            stats.control_chars_removed.get(),
+            stats.redactions.get(),
+            stats.comment_bytes_removed.get(),
+            stats.blobs_collapsed.get(),
+            stats.eol_normalized.get(),
+            stats.not_outlined.get()
+        ));
+        out.push_str(text);
+        // Written aside and renamed into place, so a reader never sees
+        // half an entry.
+        let tmp = entry.with_extension(format!("{}.tmp", std::process::id()));
+        let written = std::fs::write(&tmp, out).and_then(|()| std::fs::rename(&tmp, entry));
+        if let Err(err) = written {
+            let _ = std::fs::remove_file(&tmp);
+            crate::log::detail(
+                config,
+                format_args!("cache entry {}: {err}", entry.display()),
+            );
+        }
+    }
+}
+
+fn stamp(path: &Path) -> Option<Stamp> {
+    let meta = std::fs::metadata(path).ok()?;
+    let mtime = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(Stamp {
+        size: meta.len(),
+        mtime: mtime.as_nanos(),
+    })
+}
+
+fn file_digest(path: &Path, open_files: &OpenFiles) -> Option<String> {
+    let _permit = open_files.permit();
+    sha256::hex_digest(File::open(path).ok()?).ok()
+}
+
+/// The `content` line, note and text an entry stores for `content`;
+/// `None` for what isn't cached.
+fn describe(content: &FileContent) -> Option<(String, Option<&str>, &str)> {
+    let kind = match content {
+        FileContent::Text {
+            text,
+            truncated,
+            encoding_note,
+        } => {
+            let kind = match truncated {
+                None => "text".to_string(),
+                Some(Truncation::Lines) => "text lines".to_string(),
+                Some(Truncation::Bytes {
+                    limit,
+                    original,
+                    omitted,
+                }) => format!("text bytes {limit} {original} {omitted}"),
+            };
+            return Some((kind, encoding_note.as_deref(), text));
+        }
+        FileContent::Binary => "binary",
+        FileContent::InvalidUtf8 => "invalid-utf8",
+        FileContent::Lockfile => "lockfile",
+        FileContent::Minified => "minified",
+        FileContent::ContentExcluded => "content-excluded",
+        FileContent::TooLarge => "too-large",
+        FileContent::Empty => "empty",
+        FileContent::BeyondDepth => "beyond-depth",
+        _ => return None,
+    };
+    Some((kind.to_string(), None, ""))
+}
+
+/// An entry as read back.
+struct Parsed<'a> {
+    size: u64,
+    mtime: u128,
+    sha256: &'a str,
+    kind: &'a str,
+    note: Option<&'a str>,
+    stats: [usize; 6],
+    text: &'a str,
+}
+
+/// Read an entry; `None` if it isn't one this version wrote.
+fn parse(entry: &str) -> Option<Parsed<'_>> {
+    let (head, text) = entry.split_once("\n\n")?;
+    let mut lines = head.lines();
+    if lines.next()? != MAGIC {
+        return None;
+    }
+    let mut field = |name: &str| lines.next()?.strip_prefix(name)?.strip_prefix(' ');
+    let size = field("size")?.parse().ok()?;
+    let mtime = field("mtime")?.parse().ok()?;
+    let sha256 = field("sha256")?;
+    let kind = field("content")?;
+    let mut rest = lines.next()?;
+    let note = match rest.strip_prefix("note ") {
+        Some(note) => {
+            rest = lines.next()?;
+            Some(note)
+        }
+        None => None,
+    };
+    let mut stats = [0; 6];
+    let mut counts = rest.strip_prefix("stats ")?.split(' ');
+    for n in &mut stats {
+        *n = counts.next()?.parse().ok()?;
+    }
+    Some(Parsed {
+        size,
+        mtime,
+        sha256,
+        kind,
+        note,
+        stats,
+        text,
+    })
+}
+
+impl Parsed<'_> {
+    /// The cached content; `None` for a `content` line this version
+    /// doesn't know.
+    fn content(&self) -> Option<(FileContent, transform::Stats)> {
+        let content = match self.kind.split(' ').collect::<Vec<_>>()[..] {
+            ["binary"] => FileContent::Binary,
+            ["invalid-utf8"] => FileContent::InvalidUtf8,
+            ["lockfile"] => FileContent::Lockfile,
+            ["minified"] => FileContent::Minified,
+            ["content-excluded"] => FileContent::ContentExcluded,
+            ["too-large"] => FileContent::TooLarge,
+            ["empty"] => FileContent::Empty,
+            ["beyond-depth"] => FileContent::BeyondDepth,
+            ["text", ref truncation @ ..] => FileContent::Text {
+                text: self.text.to_string(),
+                truncated: match truncation {
+                    [] => None,
+                    ["lines"] => Some(Truncation::Lines),
+                    ["bytes", limit, original, omitted] => Some(Truncation::Bytes {
+                        limit: limit.parse().ok()?,
+                        original: original.parse().ok()?,
+                        omitted: omitted.parse().ok()?,
+                    }),
+                    _ => return None,
+                },
+                encoding_note: self.note.map(str::to_string),
+            },
+            _ => return None,
+        };
+        let stats = transform::Stats::default();
+        let counters = [
+            &stats.control_chars_removed,
+            &stats.redactions,
+            &stats.comment_bytes_removed,
+            &stats.blobs_collapsed,
+            &stats.eol_normalized,
+            &stats.not_outlined,
+        ];
+        for (counter, n) in counters.into_iter().zip(self.stats) {
+            counter.set(n);
+        }
+        Some((content, stats))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hits_until_the_file_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        std::fs::write(&path, "\u{FEFF}one\n").unwrap();
+        let config = Config::default();
+        let open_files = OpenFiles::new(1);
+        let cache = Cache::open(
+            &dir.path().join("cache"),
+            &config,
+            &transform::Pipeline::new(Vec::new()),
+        )
+        .unwrap();
+        let load = || {
+            let content = crate::load_file(&path, Path::new("a.txt"), false, &open_files, &config);
+            (content, transform::Stats::default())
+        };
+        let text = |content: &FileContent| match content {
+            FileContent::Text { text, .. } => text.clone(),
+            _ => panic!("not text"),
+        };
+
+        let (content, stats) = cache.load(&config, &path, "text", None, &open_files, load);
+        assert_eq!(
+            (text(&content), stats.cache_misses.get()),
+            ("one\n".into(), 1)
+        );
+        let (content, stats) = cache.load(&config, &path, "text", None, &open_files, load);
+        assert_eq!(
+            (text(&content), stats.cache_hits.get()),
+            ("one\n".into(), 1)
+        );
+
+        std::fs::write(&path, "two, and longer\n").unwrap();
+        let (content, stats) = cache.load(&config, &path, "text", None, &open_files, load);
+        assert_eq!(text(&content), "two, and longer\n");
+        assert_eq!(stats.cache_misses.get(), 1);
+    }
+}
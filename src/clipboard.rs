@@ -0,0 +1,56 @@
+//! `--clipboard`: hand the rendered dump to the system clipboard.
+//!
+//! On X11 and Wayland, copied text is served by the process that copied it
+//! and is gone once that process exits. The library only copies it; the
+//! binary then starts a helper (itself, see [`serve`]) that keeps serving
+//! the dump in the background until something else is copied.
+
+use std::io;
+
+use crate::ClipboardUnavailable;
+
+pub struct Clipboard(arboard::Clipboard);
+
+impl Clipboard {
+    /// Connect to the clipboard. Done before any work so a headless run
+    /// fails straight away.
+    pub fn open() -> io::Result<Self> {
+        arboard::Clipboard::new()
+            .map(Clipboard)
+            .map_err(|e| io::Error::other(ClipboardUnavailable(e.to_string())))
+    }
+
+    /// Replace the clipboard contents with `text`.
+    pub fn set(mut self, text: String) -> io::Result<()> {
+        self.0
+            .set_text(text)
+            .map_err(|e| io::Error::other(format!("copying to the clipboard failed: {e}")))
+    }
+}
+
+/// Put `text` on the clipboard and, on X11 and Wayland, keep serving it
+/// until something else is copied.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+))]
+pub fn serve(text: String) -> io::Result<()> {
+    use arboard::SetExtLinux;
+
+    let mut clipboard = Clipboard::open()?;
+    clipboard
+        .0
+        .set()
+        .wait()
+        .text(text)
+        .map_err(|e| io::Error::other(format!("copying to the clipboard failed: {e}")))
+}
+
+/// Elsewhere the system keeps copied text itself, so this only sets it.
+#[cfg(not(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+)))]
+pub fn serve(text: String) -> io::Result<()> {
+    Clipboard::open()?.set(text)
+}
@@ -0,0 +1,241 @@
+//! `--strip-comments`: remove line and block comments, per language
+//! family, without touching comment-like text inside string literals.
+//!
+//! This is a lexer-free state machine, not a parser: it knows each
+//! family's comment markers and string delimiters and nothing else, so it
+//! errs on the side of keeping text (a `#` only starts a comment after
+//! whitespace, a shebang line stays). Lines left blank by a removed
+//! comment are dropped; languages it doesn't know pass through unchanged.
+
+struct Quote {
+    delim: &'static str,
+    escapes: bool,
+}
+
+const fn quote(delim: &'static str, escapes: bool) -> Quote {
+    Quote { delim, escapes }
+}
+
+/// Comment and string syntax for one family of languages.
+struct Syntax {
+    line: &'static [&'static str],
+    block: Option<(&'static str, &'static str)>,
+    /// Line markers only count at the start of a line or after whitespace
+    /// (`#` in shell is also `$#`, `${#var}`, ...).
+    line_needs_space: bool,
+    /// Quotes, longest first so `"""` wins over `"`.
+    quotes: &'static [Quote],
+    /// Rust: nested block comments, raw strings and `'a` lifetimes.
+    rust: bool,
+}
+
+const C_QUOTES: &[Quote] = &[quote("\"", true), quote("'", true)];
+const JS_QUOTES: &[Quote] = &[quote("\"", true), quote("'", true), quote("`", true)];
+const GO_QUOTES: &[Quote] = &[quote("\"", true), quote("'", true), quote("`", false)];
+const PY_QUOTES: &[Quote] = &[
+    quote("\"\"\"", true),
+    quote("'''", true),
+    quote("\"", true),
+    quote("'", true),
+];
+const SH_QUOTES: &[Quote] = &[quote("\"", true), quote("'", false)];
+
+const fn syntax(
+    line: &'static [&'static str],
+    block: Option<(&'static str, &'static str)>,
+    line_needs_space: bool,
+    quotes: &'static [Quote],
+) -> Syntax {
+    Syntax {
+        line,
+        block,
+        line_needs_space,
+        quotes,
+        rust: false,
+    }
+}
+
+const C_BLOCK: Option<(&str, &str)> = Some(("/*", "*/"));
+
+const RUST: Syntax = Syntax {
+    rust: true,
+    ..syntax(&["//"], C_BLOCK, false, &[quote("\"", true)])
+};
+const C_LIKE: Syntax = syntax(&["//"], C_BLOCK, false, C_QUOTES);
+const JS: Syntax = syntax(&["//"], C_BLOCK, false, JS_QUOTES);
+const GO: Syntax = syntax(&["//"], C_BLOCK, false, GO_QUOTES);
+const CSS: Syntax = syntax(&[], C_BLOCK, false, C_QUOTES);
+const SQL: Syntax = syntax(&["--"], C_BLOCK, false, C_QUOTES);
+const PYTHON: Syntax = syntax(&["#"], None, true, PY_QUOTES);
+const SHELL: Syntax = syntax(&["#"], None, true, SH_QUOTES);
+const HASH: Syntax = syntax(&["#"], None, true, C_QUOTES);
+const INI: Syntax = syntax(&[";", "#"], None, true, C_QUOTES);
+const HTML: Syntax = syntax(&[], Some(("<!--", "-->")), false, &[]);
+
+/// The syntax for a `language_tag`, if its comments are known.
+fn syntax_for(lang: &str) -> Option<&'static Syntax> {
+    Some(match lang {
+        "rust" => &RUST,
+        "c" | "cpp" | "csharp" | "java" | "kotlin" | "swift" | "php" | "scss" | "proto" => &C_LIKE,
+        "javascript" | "ts" | "jsx" | "tsx" => &JS,
+        "go" => &GO,
+        "css" => &CSS,
+        "sql" => &SQL,
+        "python" | "toml" | "graphql" => &PYTHON,
+        "bash" | "zsh" | "fish" => &SHELL,
+        "ruby" | "yaml" | "makefile" | "cmake" | "dockerfile" | "r" => &HASH,
+        "ini" => &INI,
+        "html" => &HTML,
+        _ => return None,
+    })
+}
+
+/// `text` with its comments removed, or unchanged when `lang` has no
+/// known comment syntax.
+pub fn strip(text: &str, lang: &str) -> String {
+    let Some(syntax) = syntax_for(lang) else {
+        return text.to_string();
+    };
+
+    let mut out = String::with_capacity(text.len());
+    // The current line, and whether a comment was cut from it; such a line
+    // is dropped if nothing but whitespace is left.
+    let mut line = String::new();
+    let mut stripped = false;
+    let mut prev: Option<char> = None;
+    let mut rest = text;
+
+    // Shebangs look like comments but aren't.
+    if rest.starts_with("#!") {
+        let end = rest.find('\n').map_or(rest.len(), |i| i + 1);
+        out.push_str(&rest[..end]);
+        rest = &rest[end..];
+    }
+
+    while let Some(c) = rest.chars().next() {
+        if c == '\n' {
+            if !(stripped && line.trim().is_empty()) {
+                out.push_str(&line);
+                out.push('\n');
+            }
+            line.clear();
+            stripped = false;
+            prev = Some(c);
+            rest = &rest[1..];
+            continue;
+        }
+
+        let at_word_start = prev.is_none_or(char::is_whitespace);
+        if let Some(len) = string_len(rest, syntax, prev) {
+            line.push_str(&rest[..len]);
+            prev = rest[..len].chars().next_back();
+            rest = &rest[len..];
+            continue;
+        }
+
+        if let Some((open, close)) = syntax.block
+            && rest.starts_with(open)
+        {
+            rest = &rest[block_comment_len(rest, open, close, syntax.rust)..];
+            stripped = true;
+            continue;
+        }
+
+        if syntax.line.iter().any(|m| rest.starts_with(m))
+            && (!syntax.line_needs_space || at_word_start)
+        {
+            let mut end = rest.find('\n').unwrap_or(rest.len());
+            if rest[..end].ends_with('\r') {
+                end -= 1;
+            }
+            line.truncate(line.trim_end_matches([' ', '\t']).len());
+            rest = &rest[end..];
+            stripped = true;
+            continue;
+        }
+
+        line.push(c);
+        prev = Some(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    if !(stripped && line.trim().is_empty()) {
+        out.push_str(&line);
+    }
+    out
+}
+
+/// Length of the string or char literal starting `rest`, if one does.
+/// Unterminated literals run to the end of the text.
+fn string_len(rest: &str, syntax: &Syntax, prev: Option<char>) -> Option<usize> {
+    let after_ident = prev.is_some_and(|p| p.is_alphanumeric() || p == '_');
+    if syntax.rust {
+        if !after_ident && let Some(len) = rust_raw_string_len(rest) {
+            return Some(len);
+        }
+        if rest.starts_with('\'') {
+            return rust_char_len(rest);
+        }
+    }
+
+    let quote = syntax.quotes.iter().find(|q| rest.starts_with(q.delim))?;
+    let body = &rest[quote.delim.len()..];
+    let mut chars = body.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if quote.escapes && c == '\\' {
+            chars.next();
+        } else if body[i..].starts_with(quote.delim) {
+            return Some(quote.delim.len() + i + quote.delim.len());
+        }
+    }
+    Some(rest.len())
+}
+
+/// `r"..."`, `r#"..."#`, `br"..."` and so on.
+fn rust_raw_string_len(rest: &str) -> Option<usize> {
+    let start = rest.strip_prefix('b').unwrap_or(rest).strip_prefix('r')?;
+    let hashes = start.len() - start.trim_start_matches('#').len();
+    let body = start[hashes..].strip_prefix('"')?;
+    let close = format!("\"{}", "#".repeat(hashes));
+    let prefix = rest.len() - body.len();
+    Some(
+        body.find(&close)
+            .map_or(rest.len(), |i| prefix + i + close.len()),
+    )
+}
+
+/// A char literal (`'x'`, `'\n'`, `'\u{1F600}'`); `None` for a lifetime
+/// or label, which has no closing quote.
+fn rust_char_len(rest: &str) -> Option<usize> {
+    let body = &rest[1..];
+    if let Some(escaped) = body.strip_prefix('\\') {
+        let first = escaped.chars().next()?.len_utf8();
+        let end = escaped[first..].find('\'')?;
+        return Some(2 + first + end + 1);
+    }
+    let c = body.chars().next()?;
+    body[c.len_utf8()..]
+        .starts_with('\'')
+        .then(|| 1 + c.len_utf8() + 1)
+}
+
+/// Length of the block comment starting `rest`, counting nested comments
+/// when `nested`. Unterminated comments run to the end of the text.
+fn block_comment_len(rest: &str, open: &str, close: &str, nested: bool) -> usize {
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < rest.len() {
+        if rest[i..].starts_with(open) && (nested || depth == 0) {
+            depth += 1;
+            i += open.len();
+        } else if rest[i..].starts_with(close) {
+            depth -= 1;
+            i += close.len();
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += rest[i..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    rest.len()
+}
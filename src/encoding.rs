@@ -0,0 +1,107 @@
+//! Recognizing text encodings other than plain UTF-8.
+
+use encoding_rs::Encoding;
+
+/// Decode `bytes` if it starts with a UTF-16 byte-order mark, returning
+/// the text and a note naming the encoding. A BOM identifies the file as
+/// text, so callers can skip the binary heuristic, which would otherwise
+/// trip over the NUL bytes in UTF-16.
+///
+/// A trailing odd byte (e.g. from truncation) is dropped; unpaired
+/// surrogates become U+FFFD.
+pub fn decode_bom(bytes: &[u8]) -> Option<(String, &'static str)> {
+    let (body, little_endian, note) = match bytes {
+        [0xFF, 0xFE, rest @ ..] => (rest, true, "note: decoded from UTF-16LE"),
+        [0xFE, 0xFF, rest @ ..] => (rest, false, "note: decoded from UTF-16BE"),
+        _ => return None,
+    };
+    let units = body.chunks_exact(2).map(|pair| {
+        let pair = [pair[0], pair[1]];
+        if little_endian {
+            u16::from_le_bytes(pair)
+        } else {
+            u16::from_be_bytes(pair)
+        }
+    });
+    let text = char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect();
+    Some((text, note))
+}
+
+/// Guess whether BOM-less `sample` is UTF-16 by where its zero bytes sit:
+/// mostly-ASCII UTF-16 has a zero in nearly every high byte and almost
+/// none in the low ones. Returns `Some(true)` for little-endian.
+pub fn sniff_utf16(sample: &[u8]) -> Option<bool> {
+    let pairs = sample.len() / 2;
+    if pairs < 4 {
+        return None;
+    }
+    let zeros = |offset: usize| {
+        sample
+            .chunks_exact(2)
+            .filter(|pair| pair[offset] == 0)
+            .count()
+    };
+    let (even, odd) = (zeros(0), zeros(1));
+    let mostly = |n: usize| n * 10 >= pairs * 9;
+    let hardly = |n: usize| n * 20 <= pairs;
+    if mostly(odd) && hardly(even) {
+        Some(true)
+    } else if mostly(even) && hardly(odd) {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Decode BOM-less UTF-16 recognized by [`sniff_utf16`], like
+/// [`decode_bom`] does for files with a BOM.
+pub fn decode_utf16_sniffed(bytes: &[u8]) -> Option<(String, &'static str)> {
+    let sample = &bytes[..bytes.len().min(8 * 1024)];
+    let (encoding, note) = match sniff_utf16(sample)? {
+        true => (
+            encoding_rs::UTF_16LE,
+            "note: decoded from UTF-16LE (no BOM)",
+        ),
+        false => (
+            encoding_rs::UTF_16BE,
+            "note: decoded from UTF-16BE (no BOM)",
+        ),
+    };
+    let (text, _) = encoding.decode_without_bom_handling(bytes);
+    Some((text.into_owned(), note))
+}
+
+/// Decode `bytes` (which aren't valid UTF-8) as a legacy encoding: `forced`
+/// if given, else chardetng's guess. Returns the text and the encoding's
+/// name.
+///
+/// A guess only counts if it decodes without errors and without control
+/// characters other than whitespace; otherwise detection is uncertain and
+/// this returns `None`, leaving the caller's strict/lossy fallback to
+/// apply. A forced encoding is always used, with U+FFFD for bad sequences.
+pub fn transcode(
+    bytes: &[u8],
+    forced: Option<&'static Encoding>,
+) -> Option<(String, &'static str)> {
+    if let Some(encoding) = forced {
+        let (text, _) = encoding.decode_without_bom_handling(bytes);
+        return Some((text.into_owned(), encoding.name()));
+    }
+
+    let mut detector = chardetng::EncodingDetector::new(chardetng::Iso2022JpDetection::Deny);
+    detector.feed(bytes, true);
+    let encoding = detector.guess(None, chardetng::Utf8Detection::Deny);
+    let text = encoding.decode_without_bom_handling_and_without_replacement(bytes)?;
+    let clean = !text
+        .chars()
+        .any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x0c'));
+    clean.then(|| (text.into_owned(), encoding.name()))
+}
+
+/// Parse an `--encoding` label (`latin1`, `shift_jis`, `utf-16le`, ...).
+pub fn parse_label(label: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(label.trim().as_bytes())
+        .ok_or_else(|| format!("unknown encoding `{label}`"))
+}
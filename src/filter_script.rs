@@ -0,0 +1,70 @@
+//! `--filter-script`: per-file selection logic written in Rhai.
+//!
+//! The script must define
+//!
+//! ```text
+//! fn include(path, size, language) -> bool
+//! ```
+//!
+//! where `path` is the displayed relative path (a string), `size` the file
+//! size in bytes (an integer) and `language` the fence tag dir2prompt would
+//! use (a string, e.g. `"rust"`). Files for which it returns `false` are
+//! dropped before anything is printed.
+//!
+//! The engine only has Rhai's standard library: no file, network or process
+//! access. `eval` is disabled, `print`/`debug` go to stderr, and runaway
+//! scripts are stopped by an operation limit.
+
+use std::io;
+use std::path::Path;
+
+use rhai::{AST, Engine, Scope};
+
+/// Upper bound on work per call, so a buggy loop can't hang the run.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+pub struct FilterScript {
+    engine: Engine,
+    ast: AST,
+}
+
+impl FilterScript {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.set_max_call_levels(64);
+        engine.disable_symbol("eval");
+        engine.on_print(|s| eprintln!("dir2prompt: filter script: {s}"));
+        engine.on_debug(|s, _, pos| eprintln!("dir2prompt: filter script: {pos}: {s}"));
+
+        let ast = engine
+            .compile(&source)
+            .map_err(|e| io::Error::other(format!("{}: {e}", path.display())))?;
+        if !ast
+            .iter_functions()
+            .any(|f| f.name == "include" && f.params.len() == 3)
+        {
+            return Err(io::Error::other(format!(
+                "{}: script must define `fn include(path, size, language)`",
+                path.display()
+            )));
+        }
+
+        Ok(FilterScript { engine, ast })
+    }
+
+    pub fn include(&self, path: &str, size: u64, language: &str) -> io::Result<bool> {
+        let size = i64::try_from(size).unwrap_or(i64::MAX);
+        self.engine
+            .call_fn::<bool>(
+                &mut Scope::new(),
+                &self.ast,
+                "include",
+                (path.to_string(), size, language.to_string()),
+            )
+            .map_err(|e| io::Error::other(format!("filter script failed on `{path}`: {e}")))
+    }
+}
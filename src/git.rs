@@ -0,0 +1,170 @@
+//! Shelling out to `git` for revision-aware features.
+
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// One file of `git diff --numstat`. Counts are `None` for binary files.
+pub struct NumStat {
+    pub path: String,
+    /// The path before, for a renamed file.
+    pub renamed_from: Option<String>,
+    pub insertions: Option<u64>,
+    pub deletions: Option<u64>,
+}
+
+/// Run `git` in `root` and return its stdout, turning a failure (not a
+/// repo, unknown revision, git missing) into an error carrying git's
+/// message.
+fn run(root: &Path, args: &[&str]) -> io::Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(args)
+        .output()
+        .map_err(|e| io::Error::new(e.kind(), format!("failed to run git: {e}")))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            stderr.trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Fail with a clear message unless `root` is inside a git work tree.
+fn ensure_repo(root: &Path) -> io::Result<()> {
+    match run(root, &["rev-parse", "--is-inside-work-tree"]) {
+        Ok(out) if out.trim() == "true" => Ok(()),
+        _ => Err(io::Error::other(format!(
+            "not a git repository: {}",
+            root.display()
+        ))),
+    }
+}
+
+/// Fail unless `rev` can only be read as a revision: git would take one
+/// starting with `-` (`--output=...`) as an option.
+fn check_rev(rev: &str) -> io::Result<()> {
+    if rev.starts_with('-') {
+        return Err(io::Error::other(format!(
+            "invalid revision '{rev}': must not start with '-'"
+        )));
+    }
+    Ok(())
+}
+
+/// Per-file insertions/deletions between `rev` and the working tree, with
+/// paths relative to `root`.
+pub fn numstat(root: &Path, rev: &str) -> io::Result<Vec<NumStat>> {
+    check_rev(rev)?;
+    ensure_repo(root)?;
+    let out = run(root, &["diff", "--numstat", "-z", "--relative", rev, "--"])?;
+    // `ins\tdel\tpath\0`, or `ins\tdel\t\0old\0new\0` for a rename; paths
+    // are never quoted with `-z`.
+    let mut fields = out.split('\0');
+    let mut stats = Vec::new();
+    while let Some(record) = fields.next() {
+        let mut counts = record.splitn(3, '\t');
+        let (Some(insertions), Some(deletions), Some(path)) =
+            (counts.next(), counts.next(), counts.next())
+        else {
+            continue;
+        };
+        let (path, renamed_from) = if path.is_empty() {
+            let (Some(old), Some(new)) = (fields.next(), fields.next()) else {
+                break;
+            };
+            (new.to_string(), Some(old.to_string()))
+        } else {
+            (path.to_string(), None)
+        };
+        stats.push(NumStat {
+            path,
+            renamed_from,
+            insertions: insertions.parse().ok(),
+            deletions: deletions.parse().ok(),
+        });
+    }
+    Ok(stats)
+}
+
+/// Paths relative to `root` that differ between `rev` and the working tree,
+/// plus untracked files that aren't ignored (new files count as changed).
+pub fn changed_files(root: &Path, rev: &str) -> io::Result<HashSet<PathBuf>> {
+    check_rev(rev)?;
+    ensure_repo(root)?;
+    let diff = run(
+        root,
+        &["diff", "--name-only", "-z", "--relative", rev, "--"],
+    )?;
+    let untracked = run(root, &["ls-files", "-z", "--others", "--exclude-standard"])?;
+    Ok(diff
+        .split('\0')
+        .chain(untracked.split('\0'))
+        .filter(|p| !p.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn option_like_revisions_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let written = dir.path().join("written.txt");
+        let rev = format!("--output={}", written.display());
+        assert!(numstat(dir.path(), &rev).is_err());
+        assert!(changed_files(dir.path(), &rev).is_err());
+        assert!(!written.exists());
+    }
+
+    #[test]
+    fn numstat_reads_renames_and_unusual_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let git = |args: &[&str]| run(dir.path(), args).unwrap();
+        git(&["init", "-q"]);
+        let body: String = (0..20).map(|n| format!("line {n}\n")).collect();
+        std::fs::write(dir.path().join("old.txt"), &body).unwrap();
+        std::fs::write(dir.path().join("naïve \"q\".txt"), "a\n").unwrap();
+        git(&["add", "."]);
+        git(&[
+            "-c",
+            "user.name=t",
+            "-c",
+            "user.email=t@t",
+            "commit",
+            "-qm",
+            "init",
+        ]);
+        git(&["mv", "old.txt", "new.txt"]);
+        std::fs::write(dir.path().join("new.txt"), format!("{body}more\n")).unwrap();
+        std::fs::write(dir.path().join("naïve \"q\".txt"), "a\nb\n").unwrap();
+
+        let mut stats = numstat(dir.path(), "HEAD").unwrap();
+        stats.sort_by(|a, b| a.path.cmp(&b.path));
+        let got: Vec<_> = stats
+            .iter()
+            .map(|s| {
+                (
+                    s.path.as_str(),
+                    s.renamed_from.as_deref(),
+                    s.insertions,
+                    s.deletions,
+                )
+            })
+            .collect();
+        assert_eq!(
+            got,
+            [
+                ("naïve \"q\".txt", None, Some(1), Some(0)),
+                ("new.txt", Some("old.txt"), Some(1), Some(0)),
+            ]
+        );
+    }
+}
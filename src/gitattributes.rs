@@ -0,0 +1,123 @@
+//! Files marked `linguist-generated` in `.gitattributes`, skipped unless
+//! `--include-generated`.
+//!
+//! Every `.gitattributes` from the repository's top level (if the root is
+//! inside one) down to a file's directory applies to it. Patterns match
+//! relative to the directory of the file they're in, with the usual
+//! gitignore-style globbing; later lines and deeper files win.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+const ATTRIBUTE: &str = "linguist-generated";
+
+/// One line setting or unsetting the attribute.
+struct Rule {
+    matcher: Gitignore,
+    generated: bool,
+}
+
+/// The subset of `files` (absolute, under `root`) marked generated.
+pub fn generated_files(root: &Path, files: &[PathBuf]) -> HashSet<PathBuf> {
+    let mut rules: HashMap<PathBuf, Vec<Rule>> = HashMap::new();
+    let above = dirs_above(root);
+    let mut generated = HashSet::new();
+    for file in files {
+        let Some(dir) = file.parent() else {
+            continue;
+        };
+        let Ok(below) = dir.strip_prefix(root) else {
+            continue;
+        };
+        // Outermost first, so deeper files override.
+        let mut dirs = above.clone();
+        dirs.push(root.to_path_buf());
+        let mut dir = root.to_path_buf();
+        for part in below.components() {
+            dir.push(part);
+            dirs.push(dir.clone());
+        }
+
+        let mut state = false;
+        for dir in dirs {
+            let rules = rules.entry(dir.clone()).or_insert_with(|| load(&dir));
+            for rule in rules.iter() {
+                if rule.matcher.matched(file, false).is_ignore() {
+                    state = rule.generated;
+                }
+            }
+        }
+        if state {
+            generated.insert(file.clone());
+        }
+    }
+    generated
+}
+
+/// The directories from the enclosing repository's top level down to
+/// `root`'s parent, outermost first; empty when `root` isn't inside a
+/// repository or is its top level.
+fn dirs_above(root: &Path) -> Vec<PathBuf> {
+    let mut above = Vec::new();
+    if root.join(".git").exists() {
+        return above;
+    }
+    for dir in root.ancestors().skip(1) {
+        above.push(dir.to_path_buf());
+        if dir.join(".git").exists() {
+            above.reverse();
+            return above;
+        }
+    }
+    Vec::new()
+}
+
+/// The rules in `dir/.gitattributes` mentioning the attribute.
+fn load(dir: &Path) -> Vec<Rule> {
+    let Ok(text) = std::fs::read_to_string(dir.join(".gitattributes")) else {
+        return Vec::new();
+    };
+    let mut rules = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let Some(pattern) = fields.next() else {
+            continue;
+        };
+        // Negative patterns are forbidden in .gitattributes; git ignores
+        // them, and so do we.
+        if pattern.starts_with('!') {
+            continue;
+        }
+        let Some(generated) = fields.filter_map(attribute_value).next_back() else {
+            continue;
+        };
+        let mut builder = GitignoreBuilder::new(dir);
+        if builder.add_line(None, pattern).is_err() {
+            continue;
+        }
+        if let Ok(matcher) = builder.build() {
+            rules.push(Rule { matcher, generated });
+        }
+    }
+    rules
+}
+
+/// What an attribute field says about `linguist-generated`, if anything.
+fn attribute_value(field: &str) -> Option<bool> {
+    match field {
+        ATTRIBUTE => Some(true),
+        _ => {
+            if let Some(name) = field.strip_prefix(['-', '!']) {
+                return (name == ATTRIBUTE).then_some(false);
+            }
+            let (name, value) = field.split_once('=')?;
+            (name == ATTRIBUTE).then_some(!matches!(value, "false" | "0" | "no"))
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! Minimal JSON helpers for the machine-readable outputs.
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::FileContent;
+
+/// `s` as a quoted JSON string literal.
+pub fn string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// True if `text` is one complete JSON value (with surrounding whitespace).
+pub fn is_valid(text: &str) -> bool {
+    let mut parser = Validator {
+        bytes: text.as_bytes(),
+        pos: 0,
+    };
+    parser.value(0) && {
+        parser.skip_ws();
+        parser.pos == parser.bytes.len()
+    }
+}
+
+/// Recursive-descent checker; nesting deeper than this is rejected rather
+/// than risking the stack.
+const MAX_DEPTH: usize = 128;
+
+struct Validator<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Validator<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn skip_ws(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, b: u8) -> bool {
+        self.skip_ws();
+        let found = self.peek() == Some(b);
+        self.pos += usize::from(found);
+        found
+    }
+
+    fn value(&mut self, depth: usize) -> bool {
+        self.skip_ws();
+        match self.peek() {
+            _ if depth > MAX_DEPTH => false,
+            Some(b'{') => self.sequence(b'}', depth, |p, depth| {
+                p.skip_ws();
+                p.peek() == Some(b'"') && p.string() && p.eat(b':') && p.value(depth)
+            }),
+            Some(b'[') => self.sequence(b']', depth, |p, depth| p.value(depth)),
+            Some(b'"') => self.string(),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ => ["true", "false", "null"].iter().any(|word| {
+                let found = self.bytes[self.pos..].starts_with(word.as_bytes());
+                self.pos += if found { word.len() } else { 0 };
+                found
+            }),
+        }
+    }
+
+    /// An object or array: the opening byte, then `item`s separated by
+    /// commas up to `close`.
+    fn sequence(&mut self, close: u8, depth: usize, item: fn(&mut Self, usize) -> bool) -> bool {
+        self.pos += 1;
+        if self.eat(close) {
+            return true;
+        }
+        loop {
+            if !item(self, depth + 1) {
+                return false;
+            }
+            if self.eat(close) {
+                return true;
+            }
+            if !self.eat(b',') {
+                return false;
+            }
+        }
+    }
+
+    fn string(&mut self) -> bool {
+        self.pos += 1;
+        while let Some(b) = self.peek() {
+            self.pos += 1;
+            match b {
+                b'"' => return true,
+                b'\\' => self.pos += 1,
+                0..0x20 => return false,
+                _ => {}
+            }
+        }
+        false
+    }
+
+    fn number(&mut self) -> bool {
+        let start = self.pos;
+        while matches!(
+            self.peek(),
+            Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')
+        ) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.bytes[start..self.pos])
+            .is_ok_and(|n| n.parse::<f64>().is_ok() && !n.starts_with('+'))
+    }
+}
+
+/// One file of the `--index` sidecar.
+pub struct IndexEntry<'a> {
+    pub path: &'a str,
+    pub language: &'a str,
+    pub bytes: u64,
+    pub lines: usize,
+    pub sha256: &'a str,
+}
+
+/// The `--index` sidecar: a JSON array with one file object per line.
+pub fn write_index(out: &mut impl Write, entries: &[IndexEntry]) -> io::Result<()> {
+    writeln!(out, "[")?;
+    for (i, entry) in entries.iter().enumerate() {
+        writeln!(
+            out,
+            r#"{{"path":{},"language":{},"bytes":{},"lines":{},"sha256":{}}}{}"#,
+            string(entry.path),
+            string(entry.language),
+            entry.bytes,
+            entry.lines,
+            string(entry.sha256),
+            if i + 1 < entries.len() { "," } else { "" }
+        )?;
+    }
+    writeln!(out, "]")
+}
+
+/// Opens the `--format json` document: `{"root":...,"files":[`. File
+/// objects follow one per line, comma-separated.
+pub fn write_document_header(out: &mut impl Write, root: &str) -> io::Result<()> {
+    writeln!(out, r#"{{"root":{},"files":["#, string(root))
+}
+
+/// `counters` (name, count) as one JSON object.
+pub fn object(counters: &[(&str, usize)]) -> String {
+    let fields: Vec<String> = counters
+        .iter()
+        .map(|(name, n)| format!(r#""{name}":{n}"#))
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+/// Closes the `files` array and appends the `summary` object of
+/// `counters`, as `--summary-format json` names them.
+pub fn write_document_footer(out: &mut impl Write, counters: &[(&str, usize)]) -> io::Result<()> {
+    writeln!(out)?;
+    writeln!(out, r#"],"summary":{}}}"#, object(counters))
+}
+
+/// One file as a single-line JSON object: `path`, `language`, `truncated`,
+/// `skipped_reason` (null when printed) and `content` (null when skipped).
+pub fn write_file_object(
+    out: &mut impl Write,
+    rel: &Path,
+    lang: &str,
+    content: &FileContent,
+) -> io::Result<()> {
+    let (truncated, text) = match content {
+        FileContent::Text {
+            text, truncated, ..
+        } => (truncated.is_some(), Some(text.as_str())),
+        _ => (false, None),
+    };
+    write!(
+        out,
+        r#"{{"path":{},"language":{},"truncated":{truncated},"skipped_reason":{},"content":{}}}"#,
+        string(&rel.display().to_string()),
+        string(lang),
+        content
+            .skip_reason()
+            .map_or("null".to_string(), |r| string(&r)),
+        text.map_or("null".to_string(), string),
+    )
+}
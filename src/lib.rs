@@ -0,0 +1,4965 @@
+//! Dump a directory as Markdown (or another format) for LLM prompting.
+//!
+//! The `dir2prompt` binary is a thin wrapper over [`run`]. Embedders can
+//! call [`dump`] to write into their own writer, or [`dump_with_callback`]
+//! to receive each file's content in output order and render it however
+//! they like.
+
+mod archive;
+mod bundle;
+mod cache;
+mod clipboard;
+mod comments;
+mod encoding;
+mod filter_script;
+mod git;
+mod gitattributes;
+mod json;
+mod locations;
+mod log;
+mod manifest;
+mod models;
+mod outline;
+mod parallel;
+mod secrets;
+mod settings;
+mod sha256;
+mod split;
+mod stats;
+mod stream;
+mod template;
+mod toc;
+mod tokens;
+mod transform;
+mod tree;
+mod unbundle;
+mod xml;
+mod yaml;
+
+use clap::ValueEnum;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use ignore::WalkBuilder;
+use ignore::overrides::OverrideBuilder;
+use regex::Regex;
+use std::borrow::Cow;
+use std::cmp::{Ordering, Reverse};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+/// Everything a dump can be configured with. The CLI flags map onto these
+/// fields one-to-one, and `Config::default()` is a run with no flags.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Config {
+    /// Root directory to dump, or a `.zip`, `.tar`, `.tar.gz` or `.tgz`
+    /// archive to dump the files of (gitignore rules don't apply inside; at
+    /// most 1 GiB is unpacked and, without --max-file-size, entries over
+    /// 256 MiB are skipped).
+    /// Several roots are dumped together, each file's path prefixed with
+    /// its root's name.
+    #[arg(value_name = "ROOT", default_value = ".")]
+    pub roots: Vec<PathBuf>,
+
+    /// Max bytes to include per file (files are truncated beyond this)
+    #[arg(long, default_value_t = 200_000)]
+    pub max_bytes: usize,
+
+    /// Per-extension --max-bytes, overriding it for files with extension
+    /// EXT (case-insensitive); may be repeated, the last given wins.
+    ///
+    /// Example:
+    ///   --max-bytes-ext json=2000 --max-bytes-ext csv=500
+    #[arg(long, value_name = "EXT=N", value_parser = parse_max_bytes_ext)]
+    pub max_bytes_ext: Vec<(String, usize)>,
+
+    /// If set, truncate at the last complete line within --max-bytes
+    /// instead of cutting mid-line
+    #[arg(long)]
+    pub soft_max_bytes: bool,
+
+    /// Which part of a file over --max-bytes to keep. `head-tail` keeps the
+    /// first and last halves of the budget, with a `… (N bytes omitted) …`
+    /// line in between.
+    #[arg(long, value_enum, default_value_t = TruncateMode::Head)]
+    pub truncate: TruncateMode,
+
+    /// Truncate each file after N complete lines. Applies alongside
+    /// --max-bytes; whichever limit is hit first wins.
+    #[arg(long, value_name = "N", conflicts_with = "truncate")]
+    pub max_lines: Option<usize>,
+
+    /// Skip files larger than N bytes on disk entirely (noted as too large)
+    /// instead of emitting a truncated fragment. They aren't read at all.
+    #[arg(long, value_name = "N")]
+    pub max_file_size: Option<u64>,
+
+    /// If set, do NOT respect .gitignore / git excludes / global ignores.
+    /// `.dir2promptignore` files are still honored.
+    #[arg(long)]
+    pub no_gitignore: bool,
+
+    /// If set, exclude hidden files/dirs (dotfiles)
+    #[arg(long)]
+    pub no_hidden: bool,
+
+    /// If set, include common lockfiles (Cargo.lock, package-lock.json, etc.)
+    #[arg(long)]
+    pub include_lockfiles: bool,
+
+    /// If set, include files marked `linguist-generated` in .gitattributes
+    /// (which are otherwise listed but skipped)
+    #[arg(long)]
+    pub include_generated: bool,
+
+    /// If set, also skip lockfiles recognized by content rather than name
+    /// (catches renamed or vendored lockfiles). Costs a sniff per file;
+    /// has no effect with --include-lockfiles.
+    #[arg(long)]
+    pub sniff_lockfiles: bool,
+
+    /// If set, include zero-byte files (empty `__init__.py`, `.gitkeep`),
+    /// which are otherwise listed but skipped. Files holding only
+    /// whitespace aren't empty and are always included. Always on with
+    /// --format bundle, so --unbundle recreates every file.
+    #[arg(long)]
+    pub include_empty: bool,
+
+    /// If set, skip files that look minified (bundled JS/CSS and the like:
+    /// a few very long lines), noting them as minified
+    #[arg(long)]
+    pub skip_minified: bool,
+
+    /// Additional exclude globs (gitignore-style), may be repeated.
+    /// Anchoring follows .gitignore: a pattern starting with `/`, or with
+    /// a `/` in the middle, matches from the root (of each root) only;
+    /// one without a `/` matches at any depth.
+    ///
+    /// Examples:
+    ///   --exclude '**/*.snap'
+    ///   --exclude '**/generated/**'
+    ///   --exclude /Cargo.toml
+    #[arg(long)]
+    pub exclude: Vec<String>,
+
+    /// Read more exclude globs from this file, one per line as in
+    /// .gitignore (blank lines and `#` comments are skipped), may be
+    /// repeated. They are added to --exclude's. As in .gitignore, the last
+    /// pattern matching a path decides, so `!pattern` takes back an
+    /// earlier exclude (not one of a directory above it, which isn't
+    /// entered).
+    #[arg(long, value_name = "PATH")]
+    pub exclude_from: Vec<PathBuf>,
+
+    /// Only keep files with this extension (case-insensitive; `rs` and
+    /// `.rs` both work), may be repeated. Combines with the excludes;
+    /// --include can still add other files.
+    ///
+    /// Example:
+    ///   --ext rs --ext toml
+    #[arg(long, value_name = "EXT")]
+    pub ext: Vec<String>,
+
+    /// Tag files with extension EXT as language LANG (fence tag, `lang`
+    /// field, ...), overriding the built-in table; may be repeated.
+    ///
+    /// Example:
+    ///   --lang-map tpl=go-template --lang-map cql=sql
+    #[arg(long, value_name = "EXT=LANG", value_parser = parse_lang_map)]
+    pub lang_map: Vec<(String, &'static str)>,
+
+    /// Additional include globs (gitignore-style, anchored like --exclude),
+    /// may be repeated. These "force include" matching files over every
+    /// exclude, including .gitignore, the built-in defaults and --exclude.
+    ///
+    /// Example:
+    ///   --include '**/Cargo.lock'
+    #[arg(long)]
+    pub include: Vec<String>,
+
+    /// Read more include globs from this file, one per line like
+    /// --exclude-from, may be repeated. They are added to --include's;
+    /// `!pattern` takes back an earlier include.
+    #[arg(long, value_name = "PATH")]
+    pub include_from: Vec<PathBuf>,
+
+    /// Dump exactly the files listed in this file (`-` for stdin), one
+    /// path per line relative to ROOT, instead of walking the directory.
+    /// Ignore rules, excludes and --ext don't apply; listed paths that
+    /// don't exist are noted as not found.
+    #[arg(long, value_name = "PATH")]
+    pub files_from: Option<PathBuf>,
+
+    /// Machine-wide exclude patterns (one per line, as in --exclude-from)
+    /// applied to every run. Defaults to `$XDG_CONFIG_HOME/dir2prompt/ignore`
+    /// (`~/.config/dir2prompt/ignore`) when that file exists.
+    ///
+    /// These patterns are combined with --exclude (see --exclude-reset);
+    /// --include still wins over both and over the built-in defaults.
+    #[arg(long, value_name = "PATH")]
+    pub global_excludes: Option<PathBuf>,
+
+    /// If set, --exclude and --exclude-from replace excludes from lower
+    /// layers (the global excludes file) instead of appending to them
+    #[arg(long)]
+    pub exclude_reset: bool,
+
+    /// If set, drop the built-in exclude list (VCS directories such as
+    /// .git, node_modules, target, dist, build, virtualenvs, caches, OS
+    /// noise and lockfiles). To dump just a file or two from one of those,
+    /// --include is enough: it already wins over the built-in list.
+    #[arg(long)]
+    pub no_default_excludes: bool,
+
+    /// If set, match --exclude, --include (and their -from files),
+    /// --priority and global excludes globs case-insensitively (`*.png`
+    /// also catches `LOGO.PNG`). --ext is case-insensitive either way.
+    /// .gitignore, .ignore and .dir2promptignore files keep their own
+    /// case-sensitive matching, as git does unless `core.ignoreCase` is
+    /// set.
+    #[arg(long)]
+    pub ignore_case: bool,
+
+    /// Skip files whose text matches this regex (e.g. `@generated` or a
+    /// vendored-code notice), may be repeated. Unlike --exclude this is
+    /// checked after a file is read, so excluded files still cost a read
+    /// and a scan of up to --max-bytes.
+    ///
+    /// Examples:
+    ///   --exclude-content '@generated'
+    ///   --exclude-content 'DO NOT EDIT'
+    #[arg(long, value_name = "REGEX", value_parser = Regex::new)]
+    pub exclude_content: Vec<Regex>,
+
+    /// If set, skip files that are not valid UTF-8 (instead of lossy output)
+    /// unless they can be transcoded from a detected legacy encoding
+    #[arg(long)]
+    pub strict_utf8: bool,
+
+    /// Decode files that aren't valid UTF-8 as this encoding (e.g.
+    /// `latin1`, `shift_jis`) instead of detecting one. Valid UTF-8 files
+    /// are unaffected.
+    #[arg(long, value_name = "NAME", value_parser = encoding::parse_label)]
+    pub encoding: Option<&'static encoding_rs::Encoding>,
+
+    /// Rhai script defining `fn include(path, size, language) -> bool`,
+    /// called for each file after the walk; files returning false are
+    /// dropped. The script runs sandboxed (no filesystem or network access).
+    #[arg(long, value_name = "PATH")]
+    pub filter_script: Option<PathBuf>,
+
+    /// If set, convert `\r\n` and lone `\r` line endings in emitted
+    /// content to `\n`, noting each file that had them
+    #[arg(long)]
+    pub normalize_eol: bool,
+
+    /// If set, replace likely secrets (API keys, tokens, private keys,
+    /// credential assignments) in emitted content with `«REDACTED»`.
+    /// Deliberately over-eager.
+    #[arg(long)]
+    pub redact: bool,
+
+    /// If set, replace runs of 512+ base64 characters (inline images,
+    /// data URIs, embedded fonts) in emitted content with
+    /// `«base64 blob, N bytes omitted»`
+    #[arg(long)]
+    pub collapse_blobs: bool,
+
+    /// If set, remove ANSI escape sequences and control characters other
+    /// than tab/newline/CR from emitted content
+    #[arg(long)]
+    pub strip_control_chars: bool,
+
+    /// If set, remove line and block comments from emitted content, for
+    /// languages whose comment syntax is known (C-family, Rust, JS/TS, Go,
+    /// Python, shell, Ruby, SQL, CSS, HTML, ...); others are unchanged
+    #[arg(long)]
+    pub strip_comments: bool,
+
+    /// If set, emit only each file's declarations (signatures, types,
+    /// classes), with function bodies replaced by `{ … }` (`…` in Python),
+    /// for Rust, Python, JS/TS and Go. A lossy, pattern-based outline;
+    /// files in other languages are emitted whole, with a note.
+    #[arg(long)]
+    pub only_headers: bool,
+
+    /// If set, emit each directory's `README*` file before the other files
+    /// and subdirectories in that directory
+    #[arg(long)]
+    pub readmes_first: bool,
+
+    /// Shorten displayed paths longer than N characters with a middle
+    /// ellipsis (`src/.../deep/Foo.java`) in the listing and headings
+    #[arg(long, value_name = "N")]
+    pub elide_path: Option<usize>,
+
+    /// Show each file's full path instead of its path relative to the
+    /// root, in the listing and headings
+    #[arg(long)]
+    pub absolute_paths: bool,
+
+    /// If set, emit well-known project manifests (Cargo.toml, package.json,
+    /// go.mod, ...) right after the root README and before other files
+    #[arg(long)]
+    pub manifests_first: bool,
+
+    /// File order. Ties (and everything under `path`) fall back to the
+    /// displayed path; --readmes-first, --manifests-first and
+    /// --schemas-first still apply on top.
+    #[arg(long, value_enum, default_value_t = SortMode::Path)]
+    pub sort: SortMode,
+
+    /// If set, reverse the sorted file order (after --sort and the
+    /// --*-first options), in both the listing and the content. Files
+    /// pinned by --priority still come first, in --priority order.
+    #[arg(long)]
+    pub reverse: bool,
+
+    /// If set, scan emitted content for likely secrets (API keys, tokens,
+    /// private keys) first; if any are found, list every finding with its
+    /// file and line and exit non-zero without writing the dump
+    #[arg(long)]
+    pub fail_on_secrets: bool,
+
+    /// If set, remove trailing spaces and tabs from each emitted line
+    #[arg(long)]
+    pub trim_trailing: bool,
+
+    /// Expand tabs in emitted content to spaces, with tab stops every N
+    /// columns
+    #[arg(long, value_name = "N")]
+    pub tabs_to_spaces: Option<usize>,
+
+    /// If set, collapse runs of empty lines in emitted content into a
+    /// single empty line (like `cat -s`)
+    #[arg(long)]
+    pub squeeze_blank: bool,
+
+    /// Hard-wrap emitted lines longer than N characters (not bytes) into
+    /// pieces of N, e.g. one-line JSON or wide CSV rows. With
+    /// --line-numbers, each original line keeps one number and its
+    /// continuation pieces get a blank gutter.
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub wrap_long_lines: Option<usize>,
+
+    /// Only walk N directory levels below the root (1 = files directly in
+    /// the root). Deeper files aren't listed at all; see
+    /// --content-max-depth to list them without content.
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub depth: Option<usize>,
+
+    /// Only walk files modified at or after SPEC: a date (`2024-01-15`,
+    /// midnight UTC) or a duration before now (`7d`, `24h`, `30m`, `2w`).
+    /// Files whose modification time can't be read are left out.
+    #[arg(long, value_name = "SPEC", value_parser = parse_since)]
+    pub since: Option<SystemTime>,
+
+    /// Only emit content for files at most N levels below the root (1 =
+    /// files directly in the root). Deeper files are still listed.
+    #[arg(long, value_name = "N")]
+    pub content_max_depth: Option<usize>,
+
+    /// Read files on up to N threads (default: the number of CPUs). Output
+    /// order is unaffected.
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub jobs: Option<usize>,
+
+    /// Cap on files open for reading at once across all --jobs threads.
+    /// Each thread holds at most one file open, so this only bites when it
+    /// is below --jobs.
+    #[arg(long, value_name = "N", default_value_t = 256)]
+    pub max_open_files: usize,
+
+    /// If set, leave skipped files (binary, invalid UTF-8, unreadable) out
+    /// of the content section entirely instead of printing a note for each;
+    /// Markdown output still lists them in its "Skipped files" section
+    #[arg(long)]
+    pub hide_skipped: bool,
+
+    /// If set, don't print a section for each skipped file among the
+    /// content; they're still listed, grouped by reason, in the
+    /// "Skipped files" section at the end of Markdown output
+    #[arg(long)]
+    pub no_inline_skips: bool,
+
+    /// If set, wrap each file's content in a `<details>` block (after its
+    /// heading), summarized by its path and size, so GitHub and other
+    /// viewers show the dump with every file collapsed. Markdown only.
+    #[arg(long)]
+    pub collapsible: bool,
+
+    /// If set, emit the content of byte-identical files only once, under
+    /// the first path in output order; later copies get a note naming it
+    #[arg(long)]
+    pub dedup: bool,
+
+    /// If set, list symlinks (which aren't followed without
+    /// --follow-links) in the included files section with their targets,
+    /// instead of silently dropping them
+    #[arg(long)]
+    pub note_symlinks: bool,
+
+    /// If set, follow symlinks into the files and directories they point
+    /// at. Loops are skipped, and a file reachable by several paths is
+    /// dumped once, under the first path in output order.
+    #[arg(long)]
+    pub follow_links: bool,
+
+    /// If set, emit API/data schemas (.proto, .graphql, .sql, OpenAPI and
+    /// Swagger specs) ahead of other files, after any manifests, so budget
+    /// limits drop source files before them
+    #[arg(long)]
+    pub schemas_first: bool,
+
+    /// Emit files matching this glob (gitignore-style) before all others,
+    /// may be repeated. Files go in the order of the first glob they
+    /// match, keeping the normal sort within each glob.
+    ///
+    /// Example:
+    ///   --priority README.md --priority src/lib.rs
+    #[arg(long, value_name = "GLOB")]
+    pub priority: Vec<String>,
+
+    /// Prepend a summary of files changed since REV (default HEAD) with
+    /// insertion/deletion counts, like `git diff --stat`
+    #[arg(long, value_name = "REV", num_args = 0..=1, default_missing_value = "HEAD")]
+    pub diff_stat: Option<String>,
+
+    /// Only dump files that differ from REV (default HEAD) in the working
+    /// tree, plus untracked files that aren't ignored. Applied on top of
+    /// the walk, so excludes and .gitignore still apply.
+    #[arg(long, value_name = "REV", num_args = 0..=1, default_missing_value = "HEAD")]
+    pub git_diff: Option<String>,
+
+    /// If set, draw the included files as a directory tree (like `tree`)
+    /// before the file list
+    #[arg(long)]
+    pub tree: bool,
+
+    /// If set, start the Markdown dump with a YAML front-matter block
+    /// (`---` ... `---`) giving the root, the generation time, the file
+    /// count, the total size on disk and the dir2prompt version
+    #[arg(long)]
+    pub front_matter: bool,
+
+    /// Text to put before the `# dir2prompt dump` heading (after any front
+    /// matter), e.g. instructions for the model, or `@path` to read it
+    /// from a file. Written as is, not escaped or fenced; Markdown only.
+    #[arg(long, value_name = "TEXT|@PATH", value_parser = parse_text_arg)]
+    pub header: Option<String>,
+
+    /// Text to put at the end of the Markdown dump, after the last file
+    /// section, or `@path` to read it from a file. Written as is.
+    #[arg(long, value_name = "TEXT|@PATH", value_parser = parse_text_arg)]
+    pub footer: Option<String>,
+
+    /// If set, put a `# Directory: path/to/dir` heading (`(root)` for the
+    /// root) before the first file section of each directory, in
+    /// Markdown output
+    #[arg(long)]
+    pub group_by_dir: bool,
+
+    /// If set, prefix each line of emitted content with its line number
+    /// (`  42 | let x = 1;`) in Markdown output
+    #[arg(long)]
+    pub line_numbers: bool,
+
+    /// If set, append line count and size to each file heading, e.g.
+    /// `(120 lines, 4.1 KiB)`
+    #[arg(long)]
+    pub heading_stats: bool,
+
+    /// If set, append each file's modification time (RFC 3339, UTC) to
+    /// its heading, e.g. `(modified 2024-05-01T12:34:56Z)`. Left out for
+    /// files whose mtime can't be read.
+    #[arg(long)]
+    pub show_mtime: bool,
+
+    /// If set, append the first 8 hex digits of each file's SHA-256 to its
+    /// heading, e.g. `(sha256 9f86d081)`, to spot changed files across
+    /// dumps. Hashes the whole file on disk, even when the dump truncates.
+    #[arg(long)]
+    pub show_hash: bool,
+
+    /// If set, annotate each entry of the included files list with the
+    /// size and line count of its emitted content, e.g.
+    /// `(8.2 KiB, 310 lines)`
+    #[arg(long)]
+    pub listing_stats: bool,
+
+    /// If set, make the included files list a table of contents linking
+    /// to each file's section (GitHub-style anchors), in Markdown output.
+    /// Files whose section is left out (--no-inline-skips, --hide-skipped,
+    /// --max-total-bytes) are listed without a link.
+    #[arg(long)]
+    pub toc: bool,
+
+    /// If set, show a rough token estimate of the emitted content for each
+    /// file (in the listing and headings) and in total (~4 chars per token;
+    /// not a real tokenizer)
+    #[arg(long)]
+    pub estimate_tokens: bool,
+
+    /// Count tokens with this BPE tokenizer instead of estimating them,
+    /// for --estimate-tokens and --max-total-tokens. Needs a build with
+    /// the `tokenizer` feature.
+    #[arg(long, value_enum, value_name = "NAME")]
+    pub tokenizer: Option<Tokenizer>,
+
+    /// Warn on stderr if the dump's tokens (counted with --tokenizer, else
+    /// estimated at ~4 chars each) won't fit this model's context window (e.g. `gpt-4o`, `claude-3-5-sonnet`,
+    /// `gemini-1.5-pro`). Advisory only; see --max-total-tokens to enforce
+    /// a budget.
+    #[arg(long, value_name = "NAME")]
+    pub model: Option<String>,
+
+    /// If set, report progress on stderr as newline-delimited JSON events
+    /// (`walk_done`, `file` with a 1-based `index` of `total`, `warning`
+    /// and `done`) instead of the human summary
+    #[arg(long)]
+    pub progress_json: bool,
+
+    /// Print nothing on stderr but warnings and errors: no summary, no
+    /// notes about what was written
+    #[arg(short, long, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Also log each skipped or omitted file with its reason, and each
+    /// error during the walk, on stderr as they happen
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// How to print the end-of-run summary on stderr: `text` (one line),
+    /// `json` (one object with every counter) or `none`
+    #[arg(long, value_name = "FORMAT", default_value = "text")]
+    pub summary_format: SummaryFormat,
+
+    /// Stop emitting content once the estimated token total (see
+    /// --estimate-tokens) would exceed N. Files are taken in output order;
+    /// the rest are listed as omitted.
+    #[arg(long, value_name = "N")]
+    pub max_total_tokens: Option<usize>,
+
+    /// Stop emitting content once the emitted bytes in total would exceed
+    /// N. Files are taken in output order; the rest are listed at the end
+    /// under "Skipped (budget exhausted)".
+    #[arg(long, value_name = "N")]
+    pub max_total_bytes: Option<usize>,
+
+    /// Keep only the first N files in output order (after --sort,
+    /// --priority and the other orderings); the rest are listed at the end
+    /// under "Omitted (max-files reached)"
+    #[arg(long, value_name = "N")]
+    pub max_files: Option<usize>,
+
+    /// Render the whole document from a layout file with `[[header]]`,
+    /// `[[file]]` and `[[footer]]` sections, instead of --format.
+    ///
+    /// Header/footer placeholders: {{root}}, {{file_count}}, {{files}}.
+    /// File placeholders: {{path}}, {{lang}}, {{content}}, {{note}},
+    /// {{truncated_note}}, {{fence}}, {{size}}, {{lines}}.
+    #[arg(long, visible_alias = "template", value_name = "PATH")]
+    pub layout_file: Option<PathBuf>,
+
+    /// Only dump the neighbourhoods of `path:line` anchors read from this
+    /// file, one per line (compiler errors, test failures and grep hits can
+    /// be fed in as-is). Other files are left out.
+    #[arg(long, value_name = "FILE")]
+    pub locations_from: Option<PathBuf>,
+
+    /// Lines of context either side of each --locations-from anchor;
+    /// overlapping windows in a file are merged
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 3,
+        requires = "locations_from"
+    )]
+    pub window: usize,
+
+    /// Output format
+    ///
+    /// `bundle` is a line-oriented format that encodes each file's path and
+    /// exact content so the directory can be reconstructed from the dump.
+    /// `json` writes a single object with `root`, a `files` array and a
+    /// `summary` with every counter of `--summary-format json` but the
+    /// document's own `bytes_written` and `document_tokens`.
+    /// `jsonl` writes one JSON object per file and flushes after each, so
+    /// memory stays bounded however large the tree is.
+    /// `xml` wraps each file in a `<file path=... lang=...>` element inside
+    /// a `<documents>` root, with the content XML-escaped.
+    #[arg(long, value_enum, default_value_t = Format::Markdown)]
+    pub format: Format,
+
+    /// Write the dump to this file (overwriting it) instead of stdout. The
+    /// summary still goes to stderr.
+    #[arg(short, long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+
+    /// Also write a manifest of the included files to this path: each
+    /// file's SHA-256 and size, sorted by path, then a checksum over those
+    /// lines. Unchanged inputs give a byte-identical manifest.
+    #[arg(long, value_name = "PATH")]
+    pub manifest: Option<PathBuf>,
+
+    /// Also write a JSON index of the included files to this path, in
+    /// output order: each file's path, language, size, line count and
+    /// SHA-256, without content. Written with --stats-only and --dry-run
+    /// too.
+    #[arg(long, value_name = "PATH")]
+    pub index: Option<PathBuf>,
+
+    /// Keep each file's emitted content in this directory and reuse it on
+    /// later runs while the file is unchanged. Entries are keyed by the
+    /// flags that shape content (--max-bytes, --strip-comments, ...), so
+    /// changing one of those misses rather than reusing stale text.
+    #[arg(long, value_name = "DIR")]
+    pub cache: Option<PathBuf>,
+
+    /// Write the Markdown dump as numbered chunks of at most N bytes
+    /// (`PATH.001.md`, `PATH.002.md`, ... for `--output PATH`) plus a
+    /// `PATH.index.json` listing each chunk's files and byte ranges. A
+    /// file's block is never split across chunks.
+    #[arg(long, value_name = "N", requires = "output")]
+    pub split_bytes: Option<usize>,
+
+    /// If set, print a report of what the dump would contain instead of
+    /// the dump: file count, total size, files and bytes per language and
+    /// the 10 largest files. No file content is read.
+    #[arg(long, conflicts_with = "split_bytes")]
+    pub stats_only: bool,
+
+    /// If set, print the files the dump would contain instead of the dump,
+    /// in output order (after --sort, --priority, --max-files, ...), each
+    /// with its size and estimated tokens, plus the projected total. Sizes
+    /// come from the file system (capped at --max-bytes); no content is
+    /// read.
+    #[arg(long, conflicts_with_all = ["stats_only", "split_bytes", "tree_only"])]
+    pub dry_run: bool,
+
+    /// If set, print only the header, the directory tree and the included
+    /// files list, with the same filtering as a full dump, and stop before
+    /// the file contents. Markdown only.
+    #[arg(long, conflicts_with_all = ["stats_only", "split_bytes", "layout_file", "toc"])]
+    pub tree_only: bool,
+
+    /// If set, copy the output to the system clipboard instead of writing
+    /// it to stdout (with --output, it's written there too). Fails if no
+    /// clipboard is available, e.g. over SSH without a display. On X11 and
+    /// Wayland a background process keeps the text available after
+    /// dir2prompt exits, until something else is copied.
+    #[arg(long, conflicts_with = "split_bytes")]
+    pub clipboard: bool,
+
+    /// If set, gzip the output (each chunk separately with --split-bytes).
+    /// The summary reports the uncompressed size.
+    #[arg(long, conflicts_with_all = ["clipboard", "stats_only", "dry_run"])]
+    pub gzip: bool,
+
+    /// Instead of dumping, read a bundle or Markdown dump from this file
+    /// (`-` for stdin) and write its files back out under ROOT
+    #[arg(long, value_name = "INPUT")]
+    pub unbundle: Option<PathBuf>,
+
+    /// If set, use the root path as given (made absolute) instead of
+    /// resolving symlinks, so the dump is stable across checkouts
+    #[arg(long)]
+    pub no_canonicalize: bool,
+
+    /// If set, ignore `.dir2prompt.toml` in the root and
+    /// `$XDG_CONFIG_HOME/dir2prompt/config.toml`. Otherwise their settings
+    /// (keyed by flag name, e.g. `max_bytes = 50000`) are used as defaults
+    /// that flags on the command line override. A root's file may only set
+    /// content and filtering options, not output paths or side effects.
+    #[arg(long)]
+    pub no_config: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        use clap::{Args, FromArgMatches};
+        let matches =
+            Config::augment_args(clap::Command::new("dir2prompt")).get_matches_from(["dir2prompt"]);
+        Config::from_arg_matches(&matches).expect("defaults are valid")
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SortMode {
+    /// Displayed path
+    Path,
+    /// Largest first
+    Size,
+    /// Most recently modified first
+    Mtime,
+    /// Extension (case-insensitive), files without one first
+    Extension,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Tokenizer {
+    /// GPT-4 and GPT-3.5
+    #[value(name = "cl100k_base")]
+    Cl100kBase,
+    /// GPT-4o
+    #[value(name = "o200k_base")]
+    O200kBase,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum TruncateMode {
+    /// The start of the file
+    Head,
+    /// The start and the end of the file
+    HeadTail,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SummaryFormat {
+    Text,
+    Json,
+    None,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Markdown,
+    Bundle,
+    Json,
+    Jsonl,
+    Xml,
+}
+
+/// Counts from a finished dump.
+#[derive(Clone, Debug, Default)]
+pub struct Summary {
+    pub printed: usize,
+    /// Bytes of content printed, after transforms and before markup.
+    pub printed_bytes: usize,
+    /// Bytes of printed files left out by `max_bytes`.
+    pub truncated_bytes: usize,
+    pub skipped_binary: usize,
+    pub skipped_utf8: usize,
+    pub skipped_lockfiles: usize,
+    /// Files that looked minified, with `skip_minified`.
+    pub skipped_minified: usize,
+    /// Files over `max_file_size`.
+    pub skipped_too_large: usize,
+    /// Zero-byte files, unless `include_empty`.
+    pub skipped_empty: usize,
+    /// Files whose content matched an earlier file's, with `dedup`.
+    pub skipped_duplicates: usize,
+    /// Files marked `linguist-generated`.
+    pub skipped_generated: usize,
+    /// Files whose text matched `exclude_content`.
+    pub skipped_content: usize,
+    pub omitted_depth: usize,
+    /// Files left out by `max_total_tokens`.
+    pub omitted_tokens: usize,
+    /// Files left out by `max_total_bytes`.
+    pub omitted_bytes: usize,
+    /// Files left out by `max_files`.
+    pub omitted_max_files: usize,
+    /// Estimated tokens counted against `max_total_tokens`.
+    pub tokens_used: usize,
+    /// Bytes counted against `max_total_bytes`.
+    pub bytes_used: usize,
+    /// Estimated tokens of all printed content, with `estimate_tokens`.
+    pub tokens_estimated: usize,
+    pub control_chars_removed: usize,
+    pub redactions: usize,
+    /// Bytes removed by `strip_comments`.
+    pub comment_bytes_removed: usize,
+    /// Base64 runs replaced by `collapse_blobs`.
+    pub blobs_collapsed: usize,
+    /// Files whose line endings `normalize_eol` rewrote.
+    pub eol_normalized: usize,
+    /// Files `only_headers` had no patterns for, emitted whole.
+    pub not_outlined: usize,
+    /// Files whose content `cache` had, and files it didn't.
+    pub cache_hits: usize,
+    pub cache_misses: usize,
+    /// Size of the rendered document, before any compression.
+    pub bytes_written: usize,
+    /// Tokens of the whole rendered document, markup included: counted
+    /// with `tokenizer`, else estimated (~4 chars per token).
+    pub document_tokens: usize,
+}
+
+impl Summary {
+    /// The one-line report the CLI prints on stderr.
+    pub fn report(&self, config: &Config) -> String {
+        let mut summary = format!(
+            "dir2prompt: printed {} files ({}), skipped binary {}, skipped utf8 {}",
+            self.printed,
+            format_size(self.printed_bytes as u64),
+            self.skipped_binary,
+            self.skipped_utf8
+        );
+        if self.truncated_bytes > 0 {
+            summary.push_str(&format!(
+                ", truncation omitted {}",
+                format_size(self.truncated_bytes as u64)
+            ));
+        }
+        if let Some(budget) = config.max_total_tokens {
+            summary.push_str(&format!(
+                ", {} of {budget} token budget used ({}), dropped {} files",
+                format_tokens(self.tokens_used),
+                token_method(config),
+                self.omitted_tokens
+            ));
+        }
+        if let Some(budget) = config.max_total_bytes {
+            summary.push_str(&format!(
+                ", {} of {} byte budget used, dropped {} files",
+                format_size(self.bytes_used as u64),
+                format_size(budget as u64),
+                self.omitted_bytes
+            ));
+        }
+        if let Some(max) = config.max_files {
+            summary.push_str(&format!(
+                ", dropped {} files over max-files {max}",
+                self.omitted_max_files
+            ));
+        }
+        if config.content_max_depth.is_some() {
+            summary.push_str(&format!(
+                ", content omitted by depth {}",
+                self.omitted_depth
+            ));
+        }
+        if config.max_file_size.is_some() {
+            summary.push_str(&format!(", skipped too large {}", self.skipped_too_large));
+        }
+        if config.dedup {
+            summary.push_str(&format!(", skipped duplicates {}", self.skipped_duplicates));
+        }
+        if self.skipped_empty > 0 {
+            summary.push_str(&format!(", skipped empty {}", self.skipped_empty));
+        }
+        if self.skipped_generated > 0 {
+            summary.push_str(&format!(", skipped generated {}", self.skipped_generated));
+        }
+        if !config.exclude_content.is_empty() {
+            summary.push_str(&format!(
+                ", skipped content excludes {}",
+                self.skipped_content
+            ));
+        }
+        if config.sniff_lockfiles {
+            summary.push_str(&format!(", skipped lockfiles {}", self.skipped_lockfiles));
+        }
+        if config.skip_minified {
+            summary.push_str(&format!(", skipped minified {}", self.skipped_minified));
+        }
+        if config.strip_control_chars {
+            summary.push_str(&format!(
+                ", stripped {} control chars",
+                self.control_chars_removed
+            ));
+        }
+        if config.redact {
+            summary.push_str(&format!(", redacted {} secrets", self.redactions));
+        }
+        if config.collapse_blobs {
+            summary.push_str(&format!(
+                ", collapsed {} base64 blobs",
+                self.blobs_collapsed
+            ));
+        }
+        if config.normalize_eol {
+            summary.push_str(&format!(
+                ", normalized line endings in {} files",
+                self.eol_normalized
+            ));
+        }
+        if config.only_headers {
+            summary.push_str(&format!(
+                ", {} files not outlined (no patterns)",
+                self.not_outlined
+            ));
+        }
+        if config.cache.is_some() {
+            summary.push_str(&format!(
+                ", cache hits {}, misses {}",
+                self.cache_hits, self.cache_misses
+            ));
+        }
+        if config.strip_comments {
+            summary.push_str(&format!(
+                ", removed {} of comments",
+                format_size(self.comment_bytes_removed as u64)
+            ));
+        }
+        if config.gzip {
+            summary.push_str(&format!(
+                ", wrote {} uncompressed",
+                format_size(self.bytes_written as u64)
+            ));
+        }
+        if config.estimate_tokens {
+            summary.push_str(&format!(
+                ", {} tokens ({})",
+                format_tokens(self.tokens_estimated),
+                match config.tokenizer {
+                    Some(_) => token_method(config),
+                    None => "estimated, ~4 chars/token",
+                }
+            ));
+        }
+        summary
+    }
+
+    /// Every counter as one JSON object, for `--summary-format json`.
+    pub fn to_json(&self) -> String {
+        json::object(&self.counters())
+    }
+
+    /// Every counter, by its JSON name.
+    fn counters(&self) -> [(&'static str, usize); 29] {
+        [
+            ("printed", self.printed),
+            ("printed_bytes", self.printed_bytes),
+            ("truncated_bytes", self.truncated_bytes),
+            ("skipped_binary", self.skipped_binary),
+            ("skipped_utf8", self.skipped_utf8),
+            ("skipped_lockfiles", self.skipped_lockfiles),
+            ("skipped_minified", self.skipped_minified),
+            ("skipped_too_large", self.skipped_too_large),
+            ("skipped_empty", self.skipped_empty),
+            ("skipped_duplicates", self.skipped_duplicates),
+            ("skipped_generated", self.skipped_generated),
+            ("skipped_content", self.skipped_content),
+            ("omitted_depth", self.omitted_depth),
+            ("omitted_tokens", self.omitted_tokens),
+            ("omitted_bytes", self.omitted_bytes),
+            ("omitted_max_files", self.omitted_max_files),
+            ("tokens_used", self.tokens_used),
+            ("bytes_used", self.bytes_used),
+            ("tokens_estimated", self.tokens_estimated),
+            ("control_chars_removed", self.control_chars_removed),
+            ("redactions", self.redactions),
+            ("comment_bytes_removed", self.comment_bytes_removed),
+            ("blobs_collapsed", self.blobs_collapsed),
+            ("eol_normalized", self.eol_normalized),
+            ("not_outlined", self.not_outlined),
+            ("cache_hits", self.cache_hits),
+            ("cache_misses", self.cache_misses),
+            ("bytes_written", self.bytes_written),
+            ("document_tokens", self.document_tokens),
+        ]
+    }
+}
+
+/// One file as handed to a [`dump_with_callback`] callback.
+pub struct FileOutput<'a> {
+    /// Position in output order, from 0.
+    pub index: usize,
+    /// Number of files in the dump.
+    pub total: usize,
+    /// Path relative to the root; with several roots, under the name of
+    /// the file's root.
+    pub path: &'a Path,
+    /// The path as displayed (shortened by `elide_path`).
+    pub label: &'a str,
+    pub language: &'static str,
+    pub content: &'a FileContent,
+}
+
+/// What a run fails with when `fail_on_secrets` finds something. Each
+/// finding has already been reported on stderr.
+#[derive(Debug)]
+pub struct SecretsFound(pub usize);
+
+impl fmt::Display for SecretsFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "found {} possible secrets; nothing was written", self.0)
+    }
+}
+
+impl std::error::Error for SecretsFound {}
+
+/// What a run fails with when `clipboard` is set but no clipboard can be
+/// reached (no display server, e.g. over SSH).
+#[derive(Debug)]
+pub struct ClipboardUnavailable(pub String);
+
+impl fmt::Display for ClipboardUnavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no clipboard available: {}", self.0)
+    }
+}
+
+impl std::error::Error for ClipboardUnavailable {}
+
+/// Dump as the CLI does: to `config.output` (as chunks with
+/// `config.split_bytes`) or else to stdout, and with `config.clipboard` to
+/// the clipboard instead of stdout. With `config.stats_only` or
+/// `config.dry_run`, that report is written instead and the summary is
+/// empty.
+pub fn run(config: &Config) -> io::Result<Summary> {
+    run_with_copied(config, |_| Ok(()))
+}
+
+/// [`run`], handing the text copied with `config.clipboard` to `copied`
+/// as well. On X11 and Wayland it stays on the clipboard only while this
+/// process runs; the CLI passes it to a helper process that keeps serving
+/// it with [`serve_clipboard`].
+pub fn run_with_copied(
+    config: &Config,
+    copied: impl FnOnce(String) -> io::Result<()>,
+) -> io::Result<Summary> {
+    if config.tree_only && config.format != Format::Markdown {
+        return Err(io::Error::other(
+            "--tree-only only works with --format markdown",
+        ));
+    }
+    let clipboard = config
+        .clipboard
+        .then(clipboard::Clipboard::open)
+        .transpose()?;
+    let plan = Plan::new(config)?;
+
+    if let Some(clipboard) = clipboard {
+        let mut rendered = Vec::new();
+        let summary = if config.stats_only || config.dry_run {
+            write_stats(&plan, config, &mut rendered)?;
+            Summary::default()
+        } else if config.tree_only {
+            write_document(config, &plan, || Ok(&mut rendered), None)?
+        } else {
+            plan.check_secrets(config)?;
+            write_document(config, &plan, || Ok(&mut rendered), None)?
+        };
+        if let Some(path) = &config.output {
+            create_output(path)?.write_all(&rendered)?;
+        }
+        let size = format_size(rendered.len() as u64);
+        let text = String::from_utf8_lossy(&rendered).into_owned();
+        clipboard.set(text.clone())?;
+        copied(text)?;
+        log::note(config, format_args!("copied {size} to the clipboard"));
+        plan.write_sidecars(config)?;
+        warn_context_window(config, &summary);
+        return Ok(summary);
+    }
+    if config.stats_only || config.dry_run {
+        match &config.output {
+            Some(path) => write_stats(&plan, config, create_output(path)?)?,
+            None => write_stats(&plan, config, io::stdout().lock())?,
+        }
+        plan.write_sidecars(config)?;
+        return Ok(Summary::default());
+    }
+    if !config.tree_only {
+        plan.check_secrets(config)?;
+    }
+
+    let summary = match (config.split_bytes, &config.output) {
+        (Some(max), Some(prefix)) => {
+            let mut splitter = split::Splitter::new(config.gzip);
+            let sink = splitter.sink();
+            let mut summary = write_document(config, &plan, || Ok(sink), Some(&mut splitter))?;
+            let (chunks, bytes) = splitter.write(prefix, max)?;
+            summary.bytes_written = bytes;
+            log::note(
+                config,
+                format_args!(
+                    "wrote {chunks} {} ({} ... {})",
+                    if chunks == 1 { "chunk" } else { "chunks" },
+                    split::chunk_path(prefix, 1, config.gzip).display(),
+                    split::chunk_path(prefix, chunks, config.gzip).display()
+                ),
+            );
+            Ok(summary)
+        }
+        (_, Some(path)) if config.gzip => write_gzipped(config, &plan, || create_output(path)),
+        (_, Some(path)) => write_document(config, &plan, || create_output(path), None),
+        (_, None) if config.gzip => write_gzipped(config, &plan, || Ok(io::stdout().lock())),
+        (_, None) => write_document(config, &plan, || Ok(io::stdout().lock()), None),
+    }?;
+    plan.write_sidecars(config)?;
+    warn_context_window(config, &summary);
+    Ok(summary)
+}
+
+/// With `model`, warn if the document is estimated not to fit the model's
+/// context window.
+fn warn_context_window(config: &Config, summary: &Summary) {
+    let Some(model) = &config.model else {
+        return;
+    };
+    let mut warnings = Vec::new();
+    let window = models::context_window(model).unwrap_or_else(|| {
+        warnings.push(format!(
+            "unknown model `{model}`; assuming a {}-token context window",
+            format_tokens(models::FALLBACK_WINDOW)
+        ));
+        models::FALLBACK_WINDOW
+    });
+    let tokens = summary.document_tokens;
+    if tokens > window {
+        warnings.push(format!(
+            "the dump is {} tokens ({}), {} over the {}-token context window of {model}",
+            format_tokens(tokens),
+            token_method(config),
+            format_tokens(tokens - window),
+            format_tokens(window)
+        ));
+    }
+    for warning in warnings {
+        log::warning(config, warning);
+    }
+}
+
+/// Write the dump to `out`. `config.output` is ignored; `split_bytes`
+/// needs [`run`].
+pub fn dump<W: Write>(config: &Config, out: &mut W) -> io::Result<Summary> {
+    if config.split_bytes.is_some() {
+        return Err(io::Error::other("split_bytes is only supported by `run`"));
+    }
+    let plan = Plan::new(config)?;
+    plan.check_secrets(config)?;
+    write_document(config, &plan, || Ok(out), None)
+}
+
+/// Walk, read and classify files as [`dump`] would, but hand each one to
+/// `on_file` in output order instead of rendering it. Budgets, transforms
+/// and `hide_skipped` apply; the output format options don't.
+pub fn dump_with_callback(
+    config: &Config,
+    mut on_file: impl FnMut(&FileOutput) -> io::Result<()>,
+) -> io::Result<Summary> {
+    let mut plan = Plan::new(config)?;
+    plan.stream = false;
+    plan.check_secrets(config)?;
+    emit(config, &plan, |file| {
+        if config.hide_skipped && file.content.is_skipped() {
+            return Ok(());
+        }
+        on_file(file)
+    })
+}
+
+/// `args` (program name first, as from `std::env::args_os`) with the
+/// settings from the config files (see `no_config`) inserted as flags, for
+/// `command` (the CLI, with [`Config`] flattened into it) to parse.
+/// Command-line flags override file settings; list settings are layered.
+pub fn with_config_files(
+    command: &clap::Command,
+    args: Vec<std::ffi::OsString>,
+) -> io::Result<Vec<std::ffi::OsString>> {
+    settings::with_config_files(command, args)
+}
+
+/// Put `text` on the clipboard and, on X11 and Wayland, block serving it
+/// until something else is copied; elsewhere just put it there.
+pub fn serve_clipboard(text: String) -> io::Result<()> {
+    clipboard::serve(text)
+}
+
+/// Reconstruct files under `target` from a bundle or Markdown dump read
+/// from `input` (`-` for stdin).
+pub fn unbundle(input: &Path, target: &Path) -> io::Result<()> {
+    unbundle::run(input, target)
+}
+
+/// The walked, filtered and ordered file set, plus what reading it needs.
+struct Plan {
+    roots: Roots,
+    files: Vec<PathBuf>,
+    labels: Vec<String>,
+    symlinks: Vec<PathBuf>,
+    /// Labels of the files after the first `max_files`, which were dropped.
+    over_max_files: Vec<String>,
+    /// Files marked `linguist-generated`, unless `include_generated`.
+    generated: HashSet<PathBuf>,
+    locations: Option<locations::Locations>,
+    pipeline: transform::Pipeline,
+    jobs: usize,
+    /// Large files may be read as [`FileContent::Streamed`]; only the
+    /// Markdown writer handles those.
+    stream: bool,
+    cache: Option<cache::Cache>,
+    /// The `max_open_files` cap, for this run alone.
+    open_files: parallel::OpenFiles,
+}
+
+/// One of the roots being dumped.
+struct Root {
+    /// Where its files are walked and read from.
+    dir: PathBuf,
+    /// As shown in the header, and joined onto with `absolute_paths`.
+    display: PathBuf,
+    /// Put in front of its files' paths: empty for a lone root, else the
+    /// root's name, so files from several roots don't collide.
+    prefix: PathBuf,
+    /// The unpacked archive `dir` points into, when given one.
+    archive: Option<archive::Unpacked>,
+}
+
+/// The roots of a dump, in the order given.
+struct Roots(Vec<Root>);
+
+impl Roots {
+    fn new(config: &Config) -> io::Result<Roots> {
+        if config.roots.is_empty() {
+            return Err(io::Error::other("no root to dump"));
+        }
+        let mut roots = Vec::with_capacity(config.roots.len());
+        for given in &config.roots {
+            // An archive is unpacked and its directory walked instead,
+            // without gitignore rules, which are about a checkout rather
+            // than its files.
+            let archive = if archive::is_archive(given) {
+                let wanted = archive_filter(config)?;
+                let filter = archive::Filter {
+                    wanted: &wanted,
+                    max_file_size: config.max_file_size,
+                    max_entry_size: archive::MAX_ENTRY_SIZE,
+                    max_total_size: archive::MAX_TOTAL_SIZE,
+                };
+                Some(archive::unpack(given, &filter, |name, reason| {
+                    log::detail(config, format_args!("{name}: skipped in archive: {reason}"));
+                })?)
+            } else {
+                None
+            };
+            let dir = normalize_root(archive.as_ref().map_or(given, |a| &a.dir))?;
+            // Walk the canonical root either way (the walker won't descend
+            // into a symlinked root), but show the as-given path when asked to.
+            let display = if config.no_canonicalize {
+                absolute_root(given)?
+            } else if archive.is_some() {
+                normalize_root(given)?
+            } else {
+                dir.clone()
+            };
+            roots.push(Root {
+                dir,
+                display,
+                prefix: PathBuf::new(),
+                archive,
+            });
+        }
+
+        if roots.len() > 1 {
+            let mut seen: HashMap<PathBuf, &Path> = HashMap::new();
+            for (root, given) in roots.iter_mut().zip(&config.roots) {
+                root.prefix = root
+                    .display
+                    .file_name()
+                    .map_or_else(|| root.display.clone(), PathBuf::from);
+                if let Some(other) = seen.insert(root.prefix.clone(), given) {
+                    return Err(io::Error::other(format!(
+                        "roots `{}` and `{}` are both named `{}`",
+                        other.display(),
+                        given.display(),
+                        root.prefix.display()
+                    )));
+                }
+            }
+        }
+        Ok(Roots(roots))
+    }
+
+    /// The root `path` is under, and `path` relative to it. If roots are
+    /// nested, the innermost one wins.
+    fn find<'a>(&self, path: &'a Path) -> Option<(&Root, &'a Path)> {
+        self.0
+            .iter()
+            .filter_map(|root| Some((root, path.strip_prefix(&root.dir).ok()?)))
+            .min_by_key(|(_, rel)| rel.components().count())
+    }
+
+    /// `path` relative to its own root, without the root's prefix.
+    fn local<'a>(&self, path: &'a Path) -> &'a Path {
+        self.find(path).map_or(path, |(_, rel)| rel)
+    }
+
+    /// `path` as shown: relative to its root, under the root's prefix.
+    fn rel<'a>(&self, path: &'a Path) -> Cow<'a, Path> {
+        match self.find(path) {
+            Some((root, rel)) if !root.prefix.as_os_str().is_empty() => {
+                Cow::Owned(root.prefix.join(rel))
+            }
+            Some((_, rel)) => Cow::Borrowed(rel),
+            None => Cow::Borrowed(path),
+        }
+    }
+
+    /// The file a path as shown (see [`Roots::rel`]) refers to: under the
+    /// root whose prefix it starts with, else under the first root.
+    fn resolve(&self, rel: &Path) -> PathBuf {
+        for root in &self.0 {
+            if !root.prefix.as_os_str().is_empty()
+                && let Ok(rest) = rel.strip_prefix(&root.prefix)
+            {
+                return root.dir.join(rest);
+            }
+        }
+        self.0[0].dir.join(rel)
+    }
+
+    /// The displayed roots, comma-separated.
+    fn display(&self) -> String {
+        let roots: Vec<String> = self
+            .0
+            .iter()
+            .map(|r| r.display.display().to_string())
+            .collect();
+        roots.join(", ")
+    }
+
+    /// True if gitignore rules apply to some root: not an archive, and not
+    /// `no_gitignore`.
+    fn respect_gitignore(&self, config: &Config) -> bool {
+        !config.no_gitignore && self.0.iter().any(|r| r.archive.is_none())
+    }
+}
+
+impl Plan {
+    fn new(config: &Config) -> io::Result<Plan> {
+        tokens::ensure_available(config.tokenizer)?;
+        let roots = Roots::new(config)?;
+
+        let (mut files, mut symlinks) = match &config.files_from {
+            Some(list) => (read_file_list(list, &roots)?, Vec::new()),
+            None => {
+                let (mut files, mut symlinks) = (Vec::new(), Vec::new());
+                for root in &roots.0 {
+                    let (f, s) = if root.archive.is_some() {
+                        walk(
+                            &Config {
+                                no_gitignore: true,
+                                ..config.clone()
+                            },
+                            &root.dir,
+                        )?
+                    } else {
+                        walk(config, &root.dir)?
+                    };
+                    files.extend(f);
+                    symlinks.extend(s);
+                }
+                (files, symlinks)
+            }
+        };
+
+        if let Some(rev) = &config.git_diff {
+            let mut changed = HashSet::new();
+            for root in &roots.0 {
+                let rels = git::changed_files(&root.dir, rev)?;
+                changed.extend(rels.into_iter().map(|rel| root.dir.join(rel)));
+            }
+            files.retain(|f| changed.contains(f));
+        }
+
+        if config.split_bytes.is_some()
+            && (config.format != Format::Markdown || config.layout_file.is_some())
+        {
+            return Err(io::Error::other(
+                "--split-bytes only supports the Markdown format",
+            ));
+        }
+        if config.front_matter
+            && (config.format != Format::Markdown || config.layout_file.is_some())
+        {
+            return Err(io::Error::other(
+                "--front-matter only supports the Markdown format",
+            ));
+        }
+        if config.collapsible && (config.format != Format::Markdown || config.layout_file.is_some())
+        {
+            return Err(io::Error::other(
+                "--collapsible only supports the Markdown format",
+            ));
+        }
+        if (config.header.is_some() || config.footer.is_some())
+            && (config.format != Format::Markdown || config.layout_file.is_some())
+        {
+            return Err(io::Error::other(
+                "--header and --footer only support the Markdown format",
+            ));
+        }
+
+        // Don't dump a previous run's output (or sidecar) back into this one.
+        for output in [&config.output, &config.manifest, &config.index] {
+            if let Some(output) = output
+                .as_deref()
+                .and_then(|p| std::fs::canonicalize(p).ok())
+            {
+                files.retain(|f| *f != output);
+            }
+        }
+        if let Some(dir) = config
+            .cache
+            .as_deref()
+            .and_then(|d| std::fs::canonicalize(d).ok())
+        {
+            files.retain(|f| !f.starts_with(&dir));
+        }
+        if let Some(prefix) = config.split_bytes.and(config.output.as_deref()) {
+            let dir = prefix.parent().filter(|d| !d.as_os_str().is_empty());
+            if let Ok(dir) = std::fs::canonicalize(dir.unwrap_or(Path::new("."))) {
+                let prefix = dir.join(prefix.file_name().unwrap_or_default());
+                files.retain(|f| !split::is_output(&prefix, f));
+            }
+        }
+
+        sort_files(&roots, &mut files, config);
+        files.dedup();
+        // The walker refuses directory loops, but a file reachable through
+        // several links would still be dumped once per path; keep the first.
+        if config.follow_links {
+            let mut seen = HashSet::new();
+            files.retain(|f| seen.insert(std::fs::canonicalize(f).unwrap_or_else(|_| f.clone())));
+        }
+        // Before pinning, so --priority keeps its files at the front.
+        if config.reverse {
+            files.reverse();
+        }
+        if !config.priority.is_empty() {
+            files = pin_priority(&roots, files, config)?;
+        }
+
+        if let Some(script) = &config.filter_script {
+            let script = filter_script::FilterScript::load(script)?;
+            let mut kept = Vec::with_capacity(files.len());
+            for path in files {
+                let rel = roots.rel(&path).display().to_string();
+                let size = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+                if script.include(&rel, size, detect_language(&path, config))? {
+                    kept.push(path);
+                }
+            }
+            files = kept;
+        }
+        let locations = match &config.locations_from {
+            Some(_) if config.format == Format::Bundle => {
+                return Err(io::Error::other(
+                    "--locations-from excerpts can't be written as a bundle",
+                ));
+            }
+            Some(path) => {
+                let locations = locations::load(path, |file| roots.rel(file).into_owned())?;
+                for rel in locations.keys() {
+                    if !files.iter().any(|f| *roots.rel(f) == **rel) {
+                        log::warning(
+                            config,
+                            format_args!("location in `{}` not found in the walk", rel.display()),
+                        );
+                    }
+                }
+                files.retain(|f| locations.contains_key(&*roots.rel(f)));
+                Some(locations)
+            }
+            None => None,
+        };
+        let mut labels = display_labels(&roots, &files, config);
+        let over_max_files = match config.max_files {
+            Some(max) if files.len() > max => {
+                files.truncate(max);
+                labels.split_off(max)
+            }
+            _ => Vec::new(),
+        };
+        let generated = if config.include_generated {
+            HashSet::new()
+        } else {
+            roots
+                .0
+                .iter()
+                .flat_map(|root| gitattributes::generated_files(&root.dir, &files))
+                .collect()
+        };
+        symlinks.sort();
+        symlinks.dedup();
+
+        if config.progress_json {
+            eprintln!(r#"{{"event":"walk_done","files":{}}}"#, files.len());
+        }
+        let jobs = config
+            .jobs
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+        let pipeline = transform_pipeline(config);
+        let cache = match &config.cache {
+            Some(dir) => Some(cache::Cache::open(dir, config, &pipeline)?),
+            None => None,
+        };
+
+        Ok(Plan {
+            roots,
+            files,
+            labels,
+            symlinks,
+            over_max_files,
+            generated,
+            locations,
+            pipeline,
+            jobs,
+            stream: stream::allowed(config),
+            cache,
+            open_files: parallel::OpenFiles::new(config.max_open_files),
+        })
+    }
+
+    /// A file's emitted content, with the counters its transforms reported.
+    /// Runs on the reader threads.
+    fn load(&self, config: &Config, path: &Path) -> (FileContent, transform::Stats) {
+        let stats = transform::Stats::default();
+        if self.generated.contains(path) {
+            return (FileContent::Generated, stats);
+        }
+        let anchors = self
+            .locations
+            .as_ref()
+            .and_then(|l| l.get(&*self.roots.rel(path)));
+        let anchors = anchors.map(Vec::as_slice);
+        let load = || {
+            let content =
+                emitted_content(path, self.roots.local(path), self, config, &stats, anchors);
+            (content, stats)
+        };
+        match &self.cache {
+            Some(cache) => cache.load(
+                config,
+                path,
+                detect_language(path, config),
+                anchors,
+                &self.open_files,
+                load,
+            ),
+            None => load(),
+        }
+    }
+
+    /// With `manifest` and/or `index`, read every file for its digest and
+    /// write them. Files that no longer exist (or never did, with
+    /// `files_from`) are left out.
+    fn write_sidecars(&self, config: &Config) -> io::Result<()> {
+        if config.manifest.is_none() && config.index.is_none() {
+            return Ok(());
+        }
+        let digests = parallel::map(&self.files, self.jobs, |path| {
+            let _permit = self.open_files.permit();
+            let file = File::open(path)?;
+            let bytes = file.metadata()?.len();
+            let mut reader = LineCountingReader {
+                inner: file,
+                newlines: 0,
+                last: None,
+            };
+            let sha256 = sha256::hex_digest(&mut reader)?;
+            io::Result::Ok(FileDigest {
+                sha256,
+                bytes,
+                lines: reader.lines(),
+            })
+        });
+        let mut files = Vec::new();
+        for (path, digest) in self.files.iter().zip(digests) {
+            let rel = self.roots.rel(path).display().to_string();
+            match digest {
+                Ok(digest) => files.push((path, rel, digest)),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => {
+                    return Err(io::Error::new(
+                        err.kind(),
+                        format!("cannot read {rel} for its digest: {err}"),
+                    ));
+                }
+            }
+        }
+
+        if let Some(path) = &config.manifest {
+            let entries = files
+                .iter()
+                .map(|(_, rel, digest)| manifest::Entry {
+                    path: rel.clone(),
+                    sha256: digest.sha256.clone(),
+                    bytes: digest.bytes,
+                })
+                .collect();
+            let mut out = BufWriter::new(create_output(path)?);
+            manifest::write(&mut out, entries)?;
+            out.flush()?;
+        }
+        if let Some(path) = &config.index {
+            let entries: Vec<json::IndexEntry> = files
+                .iter()
+                .map(|(path, rel, digest)| json::IndexEntry {
+                    path: rel,
+                    language: detect_language(path, config),
+                    bytes: digest.bytes,
+                    lines: digest.lines,
+                    sha256: &digest.sha256,
+                })
+                .collect();
+            let mut out = BufWriter::new(create_output(path)?);
+            json::write_index(&mut out, &entries)?;
+            out.flush()?;
+        }
+        Ok(())
+    }
+
+    /// With `fail_on_secrets`, scan everything up front so a failing run
+    /// writes no dump at all. This checks what would be emitted, so
+    /// `redact` can clear a file.
+    fn check_secrets(&self, config: &Config) -> io::Result<()> {
+        if !config.fail_on_secrets {
+            return Ok(());
+        }
+        let mut found = 0usize;
+        let findings = parallel::map(&self.files, self.jobs, |path| {
+            match self.load(config, path).0 {
+                FileContent::Text { text, .. } => secrets::scan(&text),
+                _ => Vec::new(),
+            }
+        });
+        for (path, findings) in self.files.iter().zip(findings) {
+            for finding in findings {
+                eprintln!(
+                    "dir2prompt: {}:{}: possible {}",
+                    self.roots.rel(path).display(),
+                    finding.line,
+                    finding.kind
+                );
+                found += 1;
+            }
+        }
+        if found > 0 {
+            return Err(io::Error::other(SecretsFound(found)));
+        }
+        Ok(())
+    }
+}
+
+/// Read every file of `plan` in order, apply the budgets, count, and pass
+/// each one to `on_file`, skipped files included; `hide_skipped` is left
+/// to the callers.
+fn emit(
+    config: &Config,
+    plan: &Plan,
+    mut on_file: impl FnMut(&FileOutput) -> io::Result<()>,
+) -> io::Result<Summary> {
+    let files = &plan.files;
+    let mut summary = Summary {
+        omitted_max_files: plan.over_max_files.len(),
+        ..Summary::default()
+    };
+    let mut token_budget_hit = false;
+    let mut byte_budget_hit = false;
+    let transform_stats = transform::Stats::default();
+    // Files are read ahead in batches across the reader threads and
+    // emitted in order, so memory stays bounded by the batch.
+    let batch = plan.jobs * 8;
+    let mut loaded = VecDeque::new();
+    let mut first_by_hash: HashMap<String, String> = HashMap::new();
+
+    for (index, (path, label)) in files.iter().zip(&plan.labels).enumerate() {
+        let rel = plan.roots.rel(path);
+        if config.progress_json {
+            eprintln!(
+                r#"{{"event":"file","path":{},"index":{},"total":{}}}"#,
+                json::string(&rel.display().to_string()),
+                index + 1,
+                files.len()
+            );
+        }
+        let lang = detect_language(path, config);
+        if loaded.is_empty() && !token_budget_hit && !byte_budget_hit {
+            let end = files.len().min(index + batch);
+            loaded = parallel::map(&files[index..end], plan.jobs, |path| {
+                plan.load(config, path)
+            })
+            .into();
+        }
+        let next = loaded.pop_front();
+        let mut content = if token_budget_hit {
+            FileContent::OverTokenBudget
+        } else if byte_budget_hit {
+            FileContent::OverByteBudget
+        } else {
+            let (content, stats) = next.expect("file was read ahead");
+            transform_stats.add(&stats);
+            content
+        };
+
+        // Copies are replaced before the budgets, so they don't use any.
+        if config.dedup
+            && let FileContent::Text { text, .. } = &content
+        {
+            // SHA-256, so telling two files apart never rests on luck.
+            let digest = sha256::hex_digest(text.as_bytes()).expect("hashing a string can't fail");
+            match first_by_hash.entry(digest) {
+                Entry::Occupied(first) => content = FileContent::Duplicate(first.get().clone()),
+                Entry::Vacant(slot) => {
+                    slot.insert(rel.display().to_string());
+                }
+            }
+        }
+
+        // Files are taken in output order until the next one would exceed
+        // the budget; everything after that is omitted.
+        if let (Some(budget), FileContent::Text { text, .. }) = (config.max_total_tokens, &content)
+        {
+            let tokens = count_tokens(text, config);
+            if summary.tokens_used + tokens > budget {
+                token_budget_hit = true;
+                content = FileContent::OverTokenBudget;
+            } else {
+                summary.tokens_used += tokens;
+            }
+        }
+        if let (Some(budget), FileContent::Text { text, .. }) = (config.max_total_bytes, &content) {
+            if summary.bytes_used + text.len() > budget {
+                byte_budget_hit = true;
+                content = FileContent::OverByteBudget;
+            } else {
+                summary.bytes_used += text.len();
+            }
+        }
+
+        match &content {
+            FileContent::Text {
+                text, truncated, ..
+            } => {
+                summary.printed += 1;
+                summary.printed_bytes += text.len();
+                if let Some(Truncation::Bytes { omitted, .. }) = truncated {
+                    summary.truncated_bytes += *omitted as usize;
+                }
+                if config.estimate_tokens {
+                    summary.tokens_estimated += count_tokens(text, config);
+                }
+            }
+            FileContent::Streamed(streamed) => {
+                summary.printed += 1;
+                summary.printed_bytes += streamed.len as usize;
+                if let Some(Truncation::Bytes { omitted, .. }) = streamed.truncated {
+                    summary.truncated_bytes += omitted as usize;
+                }
+            }
+            FileContent::OverTokenBudget => summary.omitted_tokens += 1,
+            FileContent::OverByteBudget => summary.omitted_bytes += 1,
+            FileContent::Binary => summary.skipped_binary += 1,
+            FileContent::InvalidUtf8 => summary.skipped_utf8 += 1,
+            FileContent::Lockfile => summary.skipped_lockfiles += 1,
+            FileContent::Minified => summary.skipped_minified += 1,
+            FileContent::TooLarge => summary.skipped_too_large += 1,
+            FileContent::Empty => summary.skipped_empty += 1,
+            FileContent::Generated => summary.skipped_generated += 1,
+            FileContent::ContentExcluded => summary.skipped_content += 1,
+            FileContent::Duplicate(_) => summary.skipped_duplicates += 1,
+            FileContent::BeyondDepth => summary.omitted_depth += 1,
+            FileContent::NotFound | FileContent::ReadError(_) => {}
+        }
+
+        if let Some(reason) = content.skip_reason() {
+            log::detail(
+                config,
+                format_args!("{}: {}: {reason}", rel.display(), content.skip_kind()),
+            );
+        }
+        on_file(&FileOutput {
+            index,
+            total: files.len(),
+            path: &rel,
+            label,
+            language: lang,
+            content: &content,
+        })?;
+    }
+
+    summary.control_chars_removed = transform_stats.control_chars_removed.get();
+    summary.redactions = transform_stats.redactions.get();
+    summary.comment_bytes_removed = transform_stats.comment_bytes_removed.get();
+    summary.blobs_collapsed = transform_stats.blobs_collapsed.get();
+    summary.eol_normalized = transform_stats.eol_normalized.get();
+    summary.not_outlined = transform_stats.not_outlined.get();
+    summary.cache_hits = transform_stats.cache_hits.get();
+    summary.cache_misses = transform_stats.cache_misses.get();
+    if config.progress_json {
+        eprintln!(
+            r#"{{"event":"done","printed":{},"skipped_binary":{},"skipped_utf8":{}}}"#,
+            summary.printed, summary.skipped_binary, summary.skipped_utf8
+        );
+    }
+    Ok(summary)
+}
+
+/// Render the whole document in `config.format` (or `config.layout_file`)
+/// into the sink `open` returns. Everything that can fail before output
+/// starts (layout, diff stat) is done before the sink is opened. With a
+/// `splitter`, the output is cut into pieces at file boundaries.
+fn write_document<W: Write>(
+    config: &Config,
+    plan: &Plan,
+    open: impl FnOnce() -> io::Result<W>,
+    mut splitter: Option<&mut split::Splitter>,
+) -> io::Result<Summary> {
+    let files = &plan.files;
+    let labels = &plan.labels;
+
+    let layout = config
+        .layout_file
+        .as_deref()
+        .map(template::Layout::load)
+        .transpose()?;
+    let document_var = |name: &str| match name {
+        "root" => plan.roots.display(),
+        "file_count" => files.len().to_string(),
+        "files" => labels.join("\n"),
+        _ => String::new(),
+    };
+
+    // The listing comes before the content, so per-file annotations (and
+    // the heading stats the anchors are made from) need a pass of their own.
+    let annotations: Option<Vec<Option<Annotation>>> =
+        (config.listing_stats || config.estimate_tokens || config.toc && config.heading_stats)
+            .then(|| {
+                parallel::map(files, plan.jobs, |path| match plan.load(config, path).0 {
+                    FileContent::Text { text, .. } => Some(Annotation {
+                        note: listing_note(&text, config),
+                        heading_stats: heading_stats(&text, config),
+                    }),
+                    _ => None,
+                })
+            });
+
+    // Files without a section of their own (see `has_section`) can't be
+    // linked to; which ones those are depends on the budgets and copies
+    // too, so it takes a quiet run through the files first.
+    let sectioned: Option<Vec<bool>> = (config.toc
+        && layout.is_none()
+        && config.format == Format::Markdown
+        && (config.no_inline_skips || config.hide_skipped || config.max_total_bytes.is_some()))
+    .then(|| {
+        let quiet = Config {
+            quiet: true,
+            verbose: false,
+            progress_json: false,
+            ..config.clone()
+        };
+        let mut sectioned = Vec::with_capacity(files.len());
+        emit(&quiet, plan, |file| {
+            sectioned.push(has_section(file.content, config));
+            Ok(())
+        })?;
+        Ok::<_, io::Error>(sectioned)
+    })
+    .transpose()?;
+
+    let diff_stats = config
+        .diff_stat
+        .as_ref()
+        .map(|rev| diff_stats(&plan.roots, rev))
+        .transpose()?;
+
+    let mut out = BufWriter::new(CountingWriter::new(open()?, config));
+
+    match config.format {
+        _ if layout.is_some() => {}
+        Format::Markdown => {
+            if config.front_matter {
+                let roots: Vec<String> = plan
+                    .roots
+                    .0
+                    .iter()
+                    .map(|r| r.display.display().to_string())
+                    .collect();
+                let generated = format_rfc3339(SystemTime::now()).unwrap_or_default();
+                yaml::write_front_matter(
+                    &mut out,
+                    &yaml::FrontMatter {
+                        roots: &roots,
+                        generated: &generated,
+                        files: files.len(),
+                        total_bytes: files
+                            .iter()
+                            .map(|f| std::fs::metadata(f).map_or(0, |m| m.len()))
+                            .sum(),
+                    },
+                )?;
+            }
+            if let Some(header) = &config.header {
+                write_raw_text(&mut out, header)?;
+            }
+            writeln!(out, "# dir2prompt dump")?;
+            writeln!(out)?;
+            match plan.roots.0.as_slice() {
+                [root] => writeln!(out, "- Root: `{}`", root.display.display())?,
+                roots => {
+                    let roots: Vec<String> = roots
+                        .iter()
+                        .map(|r| format!("`{}`", r.display.display()))
+                        .collect();
+                    writeln!(out, "- Roots: {}", roots.join(", "))?
+                }
+            }
+            writeln!(
+                out,
+                "- Respect .gitignore: `{}`",
+                if plan.roots.respect_gitignore(config) {
+                    "yes"
+                } else {
+                    "no"
+                }
+            )?;
+            writeln!(
+                out,
+                "- Hidden files included: `{}`",
+                if config.no_hidden { "no" } else { "yes" }
+            )?;
+            write!(out, "- Per-file max bytes: `{}`", config.max_bytes)?;
+            if !config.max_bytes_ext.is_empty() {
+                let overrides: Vec<String> = config
+                    .max_bytes_ext
+                    .iter()
+                    .map(|(ext, max)| format!("{ext}: `{max}`"))
+                    .collect();
+                write!(out, " ({})", overrides.join(", "))?;
+            }
+            writeln!(out)?;
+            writeln!(out)?;
+            let mut slugger = toc::Slugger::default();
+            slugger.slug("dir2prompt dump");
+            if let (Some(rev), Some(stats)) = (&config.diff_stat, &diff_stats) {
+                slugger.slug(&format!("Diff stat (vs `{rev}`)"));
+                write_diff_stat(&mut out, stats, rev)?;
+            }
+            if config.tree || config.tree_only {
+                // Several roots hang off `.`, each under its own name.
+                let name = match plan.roots.0.as_slice() {
+                    [root] => root.display.file_name().map_or_else(
+                        || root.display.display().to_string(),
+                        |n| n.display().to_string(),
+                    ),
+                    _ => ".".to_string(),
+                };
+                slugger.slug("Tree");
+                writeln!(out, "## Tree")?;
+                writeln!(out)?;
+                writeln!(out, "```text")?;
+                let rels: Vec<Cow<Path>> = files.iter().map(|f| plan.roots.rel(f)).collect();
+                write!(out, "{}", tree::render(&name, rels.iter().map(|r| &**r)))?;
+                writeln!(out, "```")?;
+                writeln!(out)?;
+            }
+            slugger.slug("Included files");
+            writeln!(out, "## Included files")?;
+            let mut listed_dir = None;
+            for (i, label) in labels.iter().enumerate() {
+                let annotation = annotations.as_ref().and_then(|a| a[i].as_ref());
+                let linked = config.toc && sectioned.as_ref().is_none_or(|s| s[i]);
+                // Directory headings come between the file sections, so
+                // they take their anchors in the same order.
+                if linked && config.group_by_dir {
+                    let heading = directory_heading(&plan.roots.rel(&files[i]));
+                    if listed_dir.as_ref() != Some(&heading) {
+                        slugger.slug(&heading);
+                        listed_dir = Some(heading);
+                    }
+                }
+                let entry = if linked {
+                    let stats = annotation.and_then(|a| a.heading_stats.clone());
+                    let stats = with_file_info(stats, &files[i], config);
+                    let anchor = slugger.slug(&markdown_heading(label, stats.as_deref()));
+                    format!("[`{label}`](#{anchor})")
+                } else {
+                    format!("`{label}`")
+                };
+                match annotation.map(|a| &a.note).filter(|n| !n.is_empty()) {
+                    Some(note) => writeln!(out, "- {entry} ({note})")?,
+                    None => writeln!(out, "- {entry}")?,
+                }
+            }
+            if config.note_symlinks {
+                for link in &plan.symlinks {
+                    let target = std::fs::read_link(link)
+                        .map(|t| t.display().to_string())
+                        .unwrap_or_else(|e| format!("unreadable: {e}"));
+                    writeln!(
+                        out,
+                        "- `{}` → `{target}` (symlink, not followed)",
+                        plan.roots.rel(link).display()
+                    )?;
+                }
+            }
+            if !config.tree_only {
+                writeln!(out)?;
+                writeln!(out, "---")?;
+                writeln!(out)?;
+            }
+        }
+        Format::Bundle => bundle::write_header(&mut out)?,
+        Format::Json => json::write_document_header(&mut out, &plan.roots.display())?,
+        Format::Jsonl => {}
+        Format::Xml => xml::write_document_header(&mut out, &plan.roots.display())?,
+    }
+    if let Some(layout) = &layout {
+        out.write_all(layout.header.render(document_var).as_bytes())?;
+    }
+    if config.tree_only {
+        out.flush()?;
+        return Ok(Summary {
+            bytes_written: out.get_ref().bytes,
+            document_tokens: out.get_mut().document_tokens(),
+            ..Summary::default()
+        });
+    }
+
+    // Output order is the displayed path order, whatever the walk yielded.
+    debug_assert!(
+        config.sort != SortMode::Path
+            || !config.priority.is_empty()
+            || files.windows(2).all(|w| {
+                let ord = compare_display(&plan.roots, &w[0], &w[1], config);
+                if config.reverse {
+                    ord.is_ge()
+                } else {
+                    ord.is_le()
+                }
+            })
+    );
+    if let Some(splitter) = splitter.as_deref_mut() {
+        out.flush()?;
+        splitter.cut(None);
+    }
+
+    let mut budget_dropped: Vec<String> = Vec::new();
+    let mut skipped: Vec<(String, String)> = Vec::new();
+    let mut current_dir = None;
+    let mut json_objects = 0usize;
+    let mut summary = emit(config, plan, |file| {
+        let (rel, label, lang, content) = (file.path, file.label, file.language, file.content);
+        // Hidden from the content, but still listed in "Skipped files".
+        if config.hide_skipped && content.is_skipped() {
+            if layout.is_none() && config.format == Format::Markdown {
+                skipped.push(skipped_entry(label, content));
+            }
+            return Ok(());
+        }
+        if let Some(layout) = &layout {
+            write_layout_file(&mut out, &layout.file, label, lang, content, config)?;
+        } else {
+            match config.format {
+                // Listed together at the end instead of one note per file.
+                Format::Markdown if matches!(content, FileContent::OverByteBudget) => {
+                    budget_dropped.push(label.to_string());
+                }
+                Format::Markdown if content.is_skipped() && config.no_inline_skips => {
+                    skipped.push(skipped_entry(label, content));
+                }
+                Format::Markdown => {
+                    if content.is_skipped() {
+                        skipped.push(skipped_entry(label, content));
+                    }
+                    if config.group_by_dir {
+                        let heading = directory_heading(rel);
+                        if current_dir.as_ref() != Some(&heading) {
+                            writeln!(out, "# {heading}")?;
+                            writeln!(out)?;
+                            current_dir = Some(heading);
+                        }
+                    }
+                    let path = &files[file.index];
+                    write_markdown_file(
+                        &mut out,
+                        path,
+                        label,
+                        lang,
+                        content,
+                        config,
+                        &plan.open_files,
+                    )?
+                }
+                Format::Bundle => bundle::write_file(&mut out, rel, content, config)?,
+                Format::Json => {
+                    if json_objects > 0 {
+                        writeln!(out, ",")?;
+                    }
+                    json::write_file_object(&mut out, rel, lang, content)?;
+                    json_objects += 1;
+                }
+                Format::Jsonl => {
+                    json::write_file_object(&mut out, rel, lang, content)?;
+                    writeln!(out)?;
+                    out.flush()?;
+                }
+                Format::Xml => xml::write_file(&mut out, rel, lang, content)?,
+            }
+        }
+        // Everything written for this file is one piece.
+        if let Some(splitter) = splitter.as_deref_mut() {
+            out.flush()?;
+            splitter.cut(Some(rel));
+        }
+        Ok(())
+    })?;
+
+    if let Some(layout) = &layout {
+        out.write_all(layout.footer.render(document_var).as_bytes())?;
+    } else if let Format::Bundle = config.format {
+        bundle::write_footer(&mut out)?;
+    } else if let Format::Markdown = config.format {
+        if !budget_dropped.is_empty() {
+            writeln!(out, "## Skipped (budget exhausted)")?;
+            writeln!(out)?;
+            for label in &budget_dropped {
+                writeln!(out, "- `{label}`")?;
+            }
+            writeln!(out)?;
+        }
+        if !plan.over_max_files.is_empty() {
+            writeln!(out, "## Omitted (max-files reached)")?;
+            writeln!(out)?;
+            for label in &plan.over_max_files {
+                writeln!(out, "- `{label}`")?;
+            }
+            writeln!(out)?;
+        }
+        if !skipped.is_empty() {
+            write_skipped_section(&mut out, &skipped)?;
+        }
+        if let Some(footer) = &config.footer {
+            write_raw_text(&mut out, footer)?;
+        }
+    } else if let Format::Xml = config.format {
+        xml::write_document_footer(&mut out)?;
+    } else if let Format::Json = config.format {
+        // The document can't hold its own size.
+        let counters: Vec<_> = summary
+            .counters()
+            .into_iter()
+            .filter(|(name, _)| !matches!(*name, "bytes_written" | "document_tokens"))
+            .collect();
+        json::write_document_footer(&mut out, &counters)?;
+    }
+    out.flush()?;
+    summary.bytes_written = out.get_ref().bytes;
+    summary.document_tokens = out.get_mut().document_tokens();
+    Ok(summary)
+}
+
+/// `--header`/`--footer` text, as given, ended by a newline and a blank
+/// line unless it has them.
+fn write_raw_text(out: &mut impl Write, text: &str) -> io::Result<()> {
+    out.write_all(text.as_bytes())?;
+    if !text.ends_with('\n') {
+        writeln!(out)?;
+    }
+    if !text.ends_with("\n\n") {
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// What `manifest` and `index` record about a file, as it is on disk.
+struct FileDigest {
+    sha256: String,
+    bytes: u64,
+    /// As `str::lines` would count them.
+    lines: usize,
+}
+
+/// Counts the lines of what is read through it.
+struct LineCountingReader<R> {
+    inner: R,
+    newlines: usize,
+    last: Option<u8>,
+}
+
+impl<R> LineCountingReader<R> {
+    /// Lines read so far, a last line without a newline included.
+    fn lines(&self) -> usize {
+        self.newlines + usize::from(self.last.is_some_and(|b| b != b'\n'))
+    }
+}
+
+impl<R: Read> Read for LineCountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.newlines += buf[..n].iter().filter(|&&b| b == b'\n').count();
+        if n > 0 {
+            self.last = Some(buf[n - 1]);
+        }
+        Ok(n)
+    }
+}
+
+/// Counts what passes through it, before any compression below.
+struct CountingWriter<W> {
+    inner: W,
+    bytes: usize,
+    /// UTF-8 characters, for a token estimate of the whole document.
+    chars: usize,
+    /// Counts the document's tokens a line at a time, when set.
+    tokenizer: Option<Tokenizer>,
+    tokens: usize,
+    /// The current line, not yet counted.
+    line: Vec<u8>,
+}
+
+impl<W> CountingWriter<W> {
+    fn new(inner: W, config: &Config) -> Self {
+        CountingWriter {
+            inner,
+            bytes: 0,
+            chars: 0,
+            tokenizer: config.tokenizer,
+            tokens: 0,
+            line: Vec::new(),
+        }
+    }
+
+    /// Tokens of everything written so far: counted with the tokenizer,
+    /// else estimated.
+    fn document_tokens(&mut self) -> usize {
+        match self.tokenizer {
+            Some(tokenizer) => {
+                self.tokens += tokens::count(&String::from_utf8_lossy(&self.line), tokenizer);
+                self.line.clear();
+                self.tokens
+            }
+            None => self.chars.div_ceil(4),
+        }
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.bytes += n;
+        self.chars += buf[..n].iter().filter(|&&b| b & 0xC0 != 0x80).count();
+        if let Some(tokenizer) = self.tokenizer {
+            for piece in buf[..n].split_inclusive(|&b| b == b'\n') {
+                self.line.extend_from_slice(piece);
+                if piece.ends_with(b"\n") {
+                    let line = String::from_utf8_lossy(&self.line);
+                    self.tokens += tokens::count(&line, tokenizer);
+                    self.line.clear();
+                }
+            }
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// [`write_document`] through a gzip encoder, finished once the document
+/// is complete.
+fn write_gzipped<W: Write>(
+    config: &Config,
+    plan: &Plan,
+    open: impl FnOnce() -> io::Result<W>,
+) -> io::Result<Summary> {
+    let mut encoder = None;
+    let slot = &mut encoder;
+    let summary = write_document(
+        config,
+        plan,
+        move || Ok(slot.insert(GzEncoder::new(open()?, Compression::default()))),
+        None,
+    )?;
+    if let Some(encoder) = encoder {
+        encoder.finish()?;
+    }
+    Ok(summary)
+}
+
+/// The `--stats-only` or `--dry-run` report for `plan`, sized from file
+/// metadata.
+fn write_stats(plan: &Plan, config: &Config, out: impl Write) -> io::Result<()> {
+    let entries: Vec<stats::Entry> = plan
+        .files
+        .iter()
+        .zip(&plan.labels)
+        .map(|(path, label)| stats::Entry {
+            label,
+            language: mapped_language(path, config),
+            bytes: std::fs::metadata(path).map_or(0, |m| m.len()),
+            max_bytes: max_bytes_for(path, config) as u64,
+        })
+        .collect();
+    let mut out = BufWriter::new(out);
+    if config.dry_run {
+        stats::write_dry_run(
+            &mut out,
+            &plan.roots.display(),
+            &entries,
+            plan.over_max_files.len(),
+        )?;
+    } else {
+        stats::write_report(&mut out, &plan.roots.display(), &entries)?;
+    }
+    out.flush()
+}
+
+/// The user's exclude globs (global excludes, --exclude and
+/// --exclude-from, layered) and include globs (--include and
+/// --include-from), each in precedence order. A leading `!` takes back an
+/// earlier pattern's match; one given on the command line is dropped, as
+/// it always has been, so `--exclude '!x'` still excludes `x`.
+fn user_patterns(config: &Config) -> io::Result<(Vec<String>, Vec<String>)> {
+    let plain = |p: &String| {
+        let p = p.trim();
+        p.strip_prefix('!').unwrap_or(p).to_string()
+    };
+    let global_excludes = match config
+        .global_excludes
+        .clone()
+        .or_else(default_global_excludes)
+    {
+        Some(path) => read_pattern_file(&path)?,
+        None => Vec::new(),
+    };
+    let mut cli_excludes: Vec<String> = config.exclude.iter().map(plain).collect();
+    for path in &config.exclude_from {
+        cli_excludes.extend(read_pattern_file(path)?);
+    }
+    let mut includes: Vec<String> = config.include.iter().map(plain).collect();
+    for path in &config.include_from {
+        includes.extend(read_pattern_file(path)?);
+    }
+    let excludes = merge_list_layers(&[&global_excludes], &cli_excludes, config.exclude_reset);
+    Ok((excludes, includes))
+}
+
+/// Whether `walk` would keep a file of an archive at `rel`: the same
+/// depth, hidden-file, exclude, built-in list, `ext` and include rules,
+/// decided on the entry's name before it is unpacked. Globs are matched as
+/// if the archive were unpacked at `/`. Gitignore files don't apply to
+/// archives.
+fn archive_filter(config: &Config) -> io::Result<impl Fn(&Path) -> bool> {
+    let root = Path::new("/");
+    let (excludes, includes) = user_patterns(config)?;
+    let excludes = build_excludes(root, &excludes, config.ignore_case).map_err(io::Error::other)?;
+    let defaults = if config.no_default_excludes {
+        None
+    } else {
+        Some(build_default_excludes(root, config.include_lockfiles).map_err(io::Error::other)?)
+    };
+    let includes = build_includes(root, &includes, config.ignore_case).map_err(io::Error::other)?;
+    let wanted_ext: Vec<String> = config.ext.iter().map(|e| normalize_ext(e)).collect();
+    let (depth, no_hidden) = (config.depth, config.no_hidden);
+
+    Ok(move |rel: &Path| {
+        let parts: Vec<_> = rel.components().collect();
+        if depth.is_some_and(|max| parts.len() > max) {
+            return false;
+        }
+        let path = root.join(rel);
+        let dirs = &parts[..parts.len().saturating_sub(1)];
+        if includes
+            .as_ref()
+            .is_some_and(|i| i.matched(&path, false).is_whitelist())
+            && !dirs
+                .iter()
+                .any(|p| include_walk_skips(p.as_os_str(), no_hidden))
+        {
+            return true;
+        }
+        if no_hidden
+            && parts
+                .iter()
+                .any(|p| p.as_os_str().to_string_lossy().starts_with('.'))
+        {
+            return false;
+        }
+        let ignored = |p: &Path, is_dir: bool| {
+            excludes.matched(p, is_dir).is_whitelist()
+                || defaults
+                    .as_ref()
+                    .is_some_and(|d| d.matched(p, is_dir).is_ignore())
+        };
+        // Directories first, as the walk meets them.
+        let mut dir = root.to_path_buf();
+        for part in dirs {
+            dir.push(part);
+            if ignored(&dir, true) {
+                return false;
+            }
+        }
+        if ignored(&path, false) {
+            return false;
+        }
+        wanted_ext.is_empty()
+            || path
+                .extension()
+                .is_some_and(|e| wanted_ext.contains(&e.to_string_lossy().to_ascii_lowercase()))
+    })
+}
+
+/// Walk `root` as configured: ignore files, excludes, `ext` and `depth`,
+/// plus the force-included files. Returns the files and unfollowed
+/// symlinks, unsorted.
+fn walk(config: &Config, root: &Path) -> io::Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+    let respect_gitignore = !config.no_gitignore;
+    let (excludes, includes) = user_patterns(config)?;
+    let excludes = build_excludes(root, &excludes, config.ignore_case).map_err(io::Error::other)?;
+
+    let mut walk = WalkBuilder::new(root);
+
+    // The built-in noise list matches case-insensitively (it should catch
+    // `Node_Modules/` and `thumbs.db` too), so it's a separate matcher from
+    // the user's case-sensitive excludes.
+    let defaults = if config.no_default_excludes {
+        None
+    } else {
+        Some(build_default_excludes(root, config.include_lockfiles).map_err(io::Error::other)?)
+    };
+    walk.filter_entry(move |entry| {
+        let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+        !excludes.matched(entry.path(), is_dir).is_whitelist()
+            && !defaults
+                .as_ref()
+                .is_some_and(|d| d.matched(entry.path(), is_dir).is_ignore())
+    });
+
+    // Hidden handling: default is to include hidden (dotfiles), unless --no_hidden
+    walk.hidden(config.no_hidden);
+
+    // Respect gitignore & related mechanisms unless --no-gitignore
+    walk.git_ignore(respect_gitignore);
+    walk.git_exclude(respect_gitignore);
+    walk.git_global(respect_gitignore);
+    walk.parents(respect_gitignore);
+
+    // Also respect `.ignore` files (ripgrep style) when honoring ignore rules
+    walk.ignore(respect_gitignore);
+
+    // Project-local dir2prompt rules apply even with --no-gitignore; they
+    // aren't a git mechanism.
+    walk.add_custom_ignore_filename(".dir2promptignore");
+
+    // Don’t follow symlinks by default (safer, avoids cycles)
+    walk.follow_links(config.follow_links);
+    walk.max_depth(config.depth);
+
+    let mut files: Vec<PathBuf> = Vec::new();
+    let mut symlinks: Vec<PathBuf> = Vec::new();
+    collect_files(&walk, &mut files, &mut symlinks, config);
+
+    if !config.ext.is_empty() {
+        let wanted: Vec<String> = config.ext.iter().map(|e| normalize_ext(e)).collect();
+        files.retain(|f| {
+            f.extension()
+                .is_some_and(|e| wanted.contains(&e.to_string_lossy().to_ascii_lowercase()))
+        });
+    }
+
+    // Force-includes beat every exclude layer, .gitignore included, so they
+    // get their own unfiltered walks restricted to the include globs. These
+    // only go where the globs can match, and skip VCS directories (and
+    // hidden ones with `no_hidden`), so they don't read the whole tree again.
+    if let Some(matcher) =
+        build_includes(root, &includes, config.ignore_case).map_err(io::Error::other)?
+    {
+        let bases = if config.ignore_case {
+            // The literal part of a glob may not be the name on disk.
+            vec![Vec::new()]
+        } else {
+            include_bases(&includes)
+        };
+        for parts in bases {
+            let max_depth = match config.depth {
+                Some(depth) if depth < parts.len() => continue,
+                depth => depth.map(|d| d - parts.len()),
+            };
+            let mut base = root.to_path_buf();
+            let reachable = parts.iter().all(|part| {
+                base.push(part);
+                std::fs::symlink_metadata(&base)
+                    .is_ok_and(|meta| meta.is_dir() || (config.follow_links && base.is_dir()))
+            });
+            if !reachable {
+                continue;
+            }
+            let mut walk = WalkBuilder::new(&base);
+            walk.standard_filters(false);
+            walk.overrides(matcher.clone());
+            let no_hidden = config.no_hidden;
+            walk.filter_entry(move |entry| {
+                !entry.file_type().is_some_and(|t| t.is_dir())
+                    || !include_walk_skips(entry.file_name(), no_hidden)
+            });
+            walk.follow_links(config.follow_links);
+            walk.max_depth(max_depth);
+            collect_files(&walk, &mut files, &mut symlinks, config);
+        }
+    }
+
+    if let Some(since) = config.since {
+        files.retain(|f| match std::fs::metadata(f).and_then(|m| m.modified()) {
+            Ok(mtime) => mtime >= since,
+            Err(err) => {
+                log::detail(
+                    config,
+                    format_args!(
+                        "{}: left out by --since: no modification time: {err}",
+                        f.display()
+                    ),
+                );
+                false
+            }
+        });
+    }
+    Ok((files, symlinks))
+}
+
+/// Whether the walks for `--include` skip a directory named `name`: VCS
+/// metadata always, hidden directories with `no_hidden`.
+fn include_walk_skips(name: &std::ffi::OsStr, no_hidden: bool) -> bool {
+    let name = name.to_string_lossy();
+    matches!(&*name, ".git" | ".hg" | ".svn") || (no_hidden && name.starts_with('.'))
+}
+
+/// The directories (as components below the root) the include globs can
+/// match in: for a glob anchored at the root, its literal leading
+/// directories (`src/gen/*.rs` only matches under `src/gen`); for one that
+/// matches at any depth (`*.log`), the root. Negated globs add nothing,
+/// and a directory under another one listed is left to that one's walk.
+fn include_bases(includes: &[String]) -> Vec<Vec<&str>> {
+    let mut bases: Vec<Vec<&str>> = Vec::new();
+    for glob in includes {
+        let glob = glob.trim();
+        if glob.starts_with('!') {
+            continue;
+        }
+        let glob = glob.trim_end_matches('/');
+        // As in .gitignore, a glob is anchored by a leading `/` or by one
+        // in the middle.
+        let anchored = match glob.strip_prefix('/') {
+            Some(rest) => rest,
+            None if glob.contains('/') => glob,
+            None => "",
+        };
+        let mut parts: Vec<&str> = anchored.split('/').collect();
+        // The last part names what is matched, not a directory to walk.
+        parts.pop();
+        let literal = parts
+            .iter()
+            .take_while(|p| {
+                !p.is_empty()
+                    && !matches!(**p, "." | "..")
+                    && !p.contains(['*', '?', '[', '{', '\\'])
+            })
+            .count();
+        parts.truncate(literal);
+        bases.push(parts);
+    }
+    bases.sort_by_key(Vec::len);
+    let mut kept: Vec<Vec<&str>> = Vec::new();
+    for base in bases {
+        if !kept.iter().any(|k| base.starts_with(k)) {
+            kept.push(base);
+        }
+    }
+    kept
+}
+
+/// Read a `files_from` list (`-` for stdin): one path per line, as shown
+/// in the dump (see [`Roots::resolve`]) unless absolute. Blank lines are
+/// ignored; paths are kept even if they don't exist, so they can be
+/// reported as not found.
+fn read_file_list(list: &Path, roots: &Roots) -> io::Result<Vec<PathBuf>> {
+    let text = if list == Path::new("-") {
+        io::read_to_string(io::stdin())?
+    } else {
+        std::fs::read_to_string(list)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", list.display())))?
+    };
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let path = Path::new(line);
+            let path = path.strip_prefix(".").unwrap_or(path);
+            roots.resolve(path)
+        })
+        .collect())
+}
+
+/// What the included files list shows for a file with text.
+struct Annotation {
+    /// From [`listing_note`]; empty if there's nothing to show.
+    note: String,
+    /// From [`heading_stats`], which the file's anchor depends on.
+    heading_stats: Option<String>,
+}
+
+/// The parenthesized note after a file in the included files list, from
+/// its emitted `text`: size and lines with `listing_stats`, tokens with
+/// `estimate_tokens`.
+fn listing_note(text: &str, config: &Config) -> String {
+    let mut parts = Vec::new();
+    if config.listing_stats {
+        parts.push(format_size(text.len() as u64));
+        let lines = text.lines().count();
+        parts.push(format!(
+            "{lines} {}",
+            if lines == 1 { "line" } else { "lines" }
+        ));
+    }
+    if config.estimate_tokens {
+        parts.push(format!(
+            "{} tokens",
+            format_tokens(count_tokens(text, config))
+        ));
+    }
+    parts.join(", ")
+}
+
+/// Gather regular files into `files` and (unfollowed) symlinks into
+/// `symlinks`.
+fn collect_files(
+    walk: &WalkBuilder,
+    files: &mut Vec<PathBuf>,
+    symlinks: &mut Vec<PathBuf>,
+    config: &Config,
+) {
+    for result in walk.build() {
+        let entry = match result {
+            Ok(e) => e,
+            Err(err) => {
+                // Progress consumers have always been told about these.
+                if config.progress_json {
+                    log::warning(config, format_args!("walk error: {err}"));
+                } else {
+                    log::detail(config, format_args!("walk error: {err}"));
+                }
+                continue;
+            }
+        };
+
+        let ft = match entry.file_type() {
+            Some(t) => t,
+            None => continue,
+        };
+
+        if ft.is_symlink() {
+            symlinks.push(entry.into_path());
+            continue;
+        }
+        if !ft.is_file() {
+            continue;
+        }
+
+        files.push(entry.into_path());
+    }
+}
+
+/// Create (or truncate) the `--output` file, with a clear error when its
+/// directory is missing.
+fn create_output(path: &Path) -> io::Result<File> {
+    if let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+        && !parent.is_dir()
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "cannot write {}: directory {} does not exist",
+                path.display(),
+                parent.display()
+            ),
+        ));
+    }
+    File::create(path).map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))
+}
+
+/// `--ext` value as compared: no leading dot, lower case.
+fn normalize_ext(ext: &str) -> String {
+    ext.trim().trim_start_matches('.').to_ascii_lowercase()
+}
+
+fn normalize_root(root: &Path) -> io::Result<PathBuf> {
+    let root = if root.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        root.to_path_buf()
+    };
+    // Canonicalize if possible; fall back to provided path if it fails.
+    match std::fs::canonicalize(&root) {
+        Ok(p) => Ok(p),
+        Err(_) => Ok(root),
+    }
+}
+
+/// The root as the user gave it, made absolute but with symlinks left alone.
+/// Only `.` components are dropped so the header doesn't end in `/.`.
+fn absolute_root(root: &Path) -> io::Result<PathBuf> {
+    let abs = if root.is_absolute() {
+        root.to_path_buf()
+    } else {
+        std::env::current_dir()?.join(root)
+    };
+    Ok(abs
+        .components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .collect())
+}
+
+/// The content transforms enabled on the command line; the pipeline
+/// applies them in its own fixed order.
+fn transform_pipeline(config: &Config) -> transform::Pipeline {
+    let mut stages = Vec::new();
+    if config.normalize_eol {
+        stages.push(transform::Stage::NormalizeEol);
+    }
+    if config.redact {
+        stages.push(transform::Stage::Redact);
+    }
+    if config.collapse_blobs {
+        stages.push(transform::Stage::CollapseBlobs);
+    }
+    if config.strip_control_chars {
+        stages.push(transform::Stage::StripControlChars);
+    }
+    if config.strip_comments {
+        stages.push(transform::Stage::StripComments);
+    }
+    if config.only_headers {
+        stages.push(transform::Stage::OnlyHeaders);
+    }
+    if config.trim_trailing {
+        stages.push(transform::Stage::TrimTrailing);
+    }
+    if config.tabs_to_spaces.is_some() {
+        stages.push(transform::Stage::TabsToSpaces);
+    }
+    // Numbered lines are wrapped as they're numbered instead, so the
+    // numbers stay those of the file.
+    if config.wrap_long_lines.is_some() && !numbers_lines(config) {
+        stages.push(transform::Stage::WrapLongLines);
+    }
+    if config.squeeze_blank {
+        stages.push(transform::Stage::SqueezeBlank);
+    }
+    transform::Pipeline::new(stages)
+}
+
+/// Which limit cut a file short.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Truncation {
+    /// `max_bytes` (or its `max_bytes_ext` override), `limit`, cutting a
+    /// file of `original` bytes by `omitted`.
+    Bytes {
+        limit: u64,
+        original: u64,
+        omitted: u64,
+    },
+    /// `max_lines`.
+    Lines,
+}
+
+/// What we managed to get out of a file, independent of output format.
+pub enum FileContent {
+    Text {
+        text: String,
+        truncated: Option<Truncation>,
+        encoding_note: Option<String>,
+    },
+    /// Text too large to be worth holding in memory, copied from the file
+    /// as its section is written. Only the Markdown writer sees these.
+    Streamed(stream::Streamed),
+    Binary,
+    InvalidUtf8,
+    Lockfile,
+    Minified,
+    /// Marked `linguist-generated` in .gitattributes.
+    Generated,
+    /// Text matching an `exclude_content` regex.
+    ContentExcluded,
+    TooLarge,
+    /// A zero-byte file, without `include_empty`.
+    Empty,
+    /// Same content as the file at this path, emitted earlier.
+    Duplicate(String),
+    BeyondDepth,
+    OverTokenBudget,
+    OverByteBudget,
+    NotFound,
+    ReadError(io::Error),
+}
+
+impl FileContent {
+    /// Human-readable reason for a skipped file; `None` if it has text.
+    pub fn skip_reason(&self) -> Option<String> {
+        match self {
+            FileContent::Text { .. } | FileContent::Streamed(_) => None,
+            FileContent::Binary => Some("looks like a binary file".to_string()),
+            FileContent::InvalidUtf8 => Some("not valid UTF-8".to_string()),
+            FileContent::Lockfile => Some("looks like a lockfile".to_string()),
+            FileContent::Minified => Some("looks minified".to_string()),
+            FileContent::Generated => Some("marked generated".to_string()),
+            FileContent::ContentExcluded => Some("matched content exclude".to_string()),
+            FileContent::TooLarge => Some("file too large".to_string()),
+            FileContent::Empty => Some("empty file".to_string()),
+            FileContent::Duplicate(first) => Some(format!("duplicate of {first}")),
+            FileContent::BeyondDepth => Some("beyond content depth".to_string()),
+            FileContent::OverTokenBudget => Some("token budget reached".to_string()),
+            FileContent::OverByteBudget => Some("byte budget reached".to_string()),
+            FileContent::NotFound => Some("not found".to_string()),
+            FileContent::ReadError(err) => Some(format!("failed to read file: {err}")),
+        }
+    }
+
+    /// True for files that were skipped (binary, undecodable, unreadable,
+    /// ...), as opposed to printed or deliberately omitted.
+    fn is_skipped(&self) -> bool {
+        !matches!(
+            self,
+            FileContent::Text { .. }
+                | FileContent::Streamed(_)
+                | FileContent::BeyondDepth
+                | FileContent::OverTokenBudget
+                | FileContent::OverByteBudget
+        )
+    }
+
+    /// How the Markdown note introduces the reason. Files left out on
+    /// purpose (rather than unreadable) say their content was omitted.
+    fn skip_kind(&self) -> &'static str {
+        match self {
+            FileContent::OverTokenBudget | FileContent::OverByteBudget => "omitted",
+            _ if self.is_skipped() => "skipped",
+            _ => "content omitted",
+        }
+    }
+}
+
+/// Whether zero-byte files are printed rather than skipped: with
+/// `include_empty`, and always in a bundle, which must round-trip.
+fn keeps_empty(config: &Config) -> bool {
+    config.include_empty || config.format == Format::Bundle
+}
+
+/// `stream` allows a large file to come back as [`FileContent::Streamed`].
+fn load_file(
+    path: &Path,
+    rel: &Path,
+    stream: bool,
+    open_files: &parallel::OpenFiles,
+    config: &Config,
+) -> FileContent {
+    if config
+        .content_max_depth
+        .is_some_and(|max| rel.components().count() > max)
+    {
+        return FileContent::BeyondDepth;
+    }
+    if let Some(max) = config.max_file_size
+        && std::fs::metadata(path).is_ok_and(|m| m.len() > max)
+    {
+        return FileContent::TooLarge;
+    }
+    if stream && let Some(streamed) = stream::probe(path, config, open_files) {
+        return FileContent::Streamed(streamed);
+    }
+    let ReadResult { bytes, truncated } = match read_file_limited(
+        path,
+        max_bytes_for(path, config),
+        config.soft_max_bytes,
+        config.truncate,
+        open_files,
+    ) {
+        Ok(r) => r,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return FileContent::NotFound,
+        Err(err) => return FileContent::ReadError(err),
+    };
+    if bytes.is_empty() && truncated.is_none() && !keeps_empty(config) {
+        return FileContent::Empty;
+    }
+    // A BOM says "text" more reliably than the NUL-byte heuristic.
+    if let Some((text, note)) = encoding::decode_bom(&bytes) {
+        return FileContent::Text {
+            text,
+            truncated,
+            encoding_note: Some(note.to_string()),
+        };
+    }
+    if let Some((text, note)) = encoding::decode_utf16_sniffed(&bytes) {
+        return FileContent::Text {
+            text,
+            truncated,
+            encoding_note: Some(note.to_string()),
+        };
+    }
+    if looks_binary(&bytes) {
+        return FileContent::Binary;
+    }
+    match bytes_to_text(&bytes, config) {
+        (Some(text), _)
+            if config.sniff_lockfiles
+                && !config.include_lockfiles
+                && looks_like_lockfile(&text) =>
+        {
+            FileContent::Lockfile
+        }
+        (Some(text), _) if config.skip_minified && looks_minified(&text) => FileContent::Minified,
+        (Some(text), encoding_note) => FileContent::Text {
+            text,
+            truncated,
+            encoding_note,
+        },
+        (None, _) => FileContent::InvalidUtf8,
+    }
+}
+
+/// A file's content as it will be emitted: loaded, transformed and, with
+/// the file's --locations-from `anchors`, cut down to the windows around
+/// them. `rel` is relative to the file's own root; `plan` has the
+/// transforms and the open-file cap.
+fn emitted_content(
+    path: &Path,
+    rel: &Path,
+    plan: &Plan,
+    config: &Config,
+    stats: &transform::Stats,
+    anchors: Option<&[usize]>,
+) -> FileContent {
+    let mut content = load_file(path, rel, plan.stream, &plan.open_files, config);
+    let pipeline = &plan.pipeline;
+
+    // Matched against the text as read, before any transform.
+    if let FileContent::Text { text, .. } = &content
+        && config.exclude_content.iter().any(|re| re.is_match(text))
+    {
+        return FileContent::ContentExcluded;
+    }
+
+    if let Some(max) = config.max_lines
+        && let FileContent::Text {
+            text, truncated, ..
+        } = &mut content
+        && limit_lines(text, max, truncated.is_some())
+    {
+        *truncated = Some(Truncation::Lines);
+    }
+
+    if !pipeline.is_empty()
+        && let FileContent::Text {
+            text,
+            encoding_note,
+            ..
+        } = &mut content
+    {
+        let ctx = transform::Context {
+            tab_width: config.tabs_to_spaces.unwrap_or(0),
+            wrap_width: config.wrap_long_lines.unwrap_or(0),
+            language: detect_language(path, config),
+            stats,
+        };
+        *text = pipeline.apply(text, &ctx);
+        // `stats` is this file's own, so these say whether it had any.
+        let notes = [
+            (stats.eol_normalized.get() > 0).then_some("note: line endings normalized to LF"),
+            (stats.not_outlined.get() > 0)
+                .then_some("note: no declaration patterns for this language; emitted whole"),
+        ];
+        for note in notes.into_iter().flatten() {
+            *encoding_note = Some(match encoding_note.take() {
+                Some(previous) => format!("{previous}; {note}"),
+                None => note.to_string(),
+            });
+        }
+    }
+
+    if let (Some(anchors), FileContent::Text { text, .. }) = (anchors, &mut content) {
+        *text = locations::excerpt(text, anchors, config.window);
+    }
+    content
+}
+
+/// Cut `text` after its first `max` lines. Returns whether anything was
+/// cut, or the text was already cut short (`truncated`) right at the end
+/// of line `max`; exactly `max` lines, or a last line without a newline,
+/// is left alone.
+fn limit_lines(text: &mut String, max: usize, truncated: bool) -> bool {
+    if max == 0 {
+        let cut = !text.is_empty() || truncated;
+        text.clear();
+        return cut;
+    }
+    let Some((end, _)) = text.match_indices('\n').nth(max - 1) else {
+        return false;
+    };
+    if end + 1 < text.len() || truncated {
+        text.truncate(end + 1);
+        return true;
+    }
+    false
+}
+
+/// The note for a file cut short by `truncation`, without parentheses.
+fn truncation_note(truncation: Truncation, config: &Config) -> String {
+    match truncation {
+        Truncation::Lines => format!("truncated to {} lines", config.max_lines.unwrap_or(0)),
+        Truncation::Bytes {
+            limit,
+            original,
+            omitted,
+        } => {
+            let percent = omitted * 100 / original.max(1);
+            match config.truncate {
+                TruncateMode::HeadTail => format!(
+                    "truncated to about {} of {original} bytes, start and end kept, \
+                     {percent}% omitted",
+                    limit
+                ),
+                _ if config.soft_max_bytes => format!(
+                    "truncated at a line boundary, max {} of {original} bytes, \
+                     {percent}% omitted",
+                    limit
+                ),
+                _ => format!(
+                    "truncated to {} of {original} bytes, {percent}% omitted",
+                    limit
+                ),
+            }
+        }
+    }
+}
+
+/// `git diff --numstat` against `rev` for every root, with paths as shown.
+fn diff_stats(roots: &Roots, rev: &str) -> io::Result<Vec<git::NumStat>> {
+    let mut stats = Vec::new();
+    for root in &roots.0 {
+        for mut stat in git::numstat(&root.dir, rev)? {
+            if !root.prefix.as_os_str().is_empty() {
+                stat.path = root.prefix.join(&stat.path).display().to_string();
+                stat.renamed_from = stat
+                    .renamed_from
+                    .map(|old| root.prefix.join(old).display().to_string());
+            }
+            stats.push(stat);
+        }
+    }
+    Ok(stats)
+}
+
+/// A `git diff --stat`-style overview section.
+fn write_diff_stat(out: &mut impl Write, stats: &[git::NumStat], rev: &str) -> io::Result<()> {
+    writeln!(out, "## Diff stat (vs `{rev}`)")?;
+    writeln!(out)?;
+    let (mut insertions, mut deletions) = (0u64, 0u64);
+    for stat in stats {
+        let renamed = match &stat.renamed_from {
+            Some(old) => format!(" (renamed from `{old}`)"),
+            None => String::new(),
+        };
+        match (stat.insertions, stat.deletions) {
+            (Some(i), Some(d)) => {
+                insertions += i;
+                deletions += d;
+                writeln!(out, "- `{}`{renamed} +{i} -{d}", stat.path)?;
+            }
+            _ => writeln!(out, "- `{}`{renamed} (binary)", stat.path)?,
+        }
+    }
+    if !stats.is_empty() {
+        writeln!(out)?;
+    }
+    writeln!(
+        out,
+        "{} {} changed, {insertions} insertions(+), {deletions} deletions(-)",
+        stats.len(),
+        if stats.len() == 1 { "file" } else { "files" }
+    )?;
+    writeln!(out)?;
+    Ok(())
+}
+
+fn write_layout_file(
+    out: &mut impl Write,
+    template: &template::Template,
+    label: &str,
+    lang: &str,
+    content: &FileContent,
+    config: &Config,
+) -> io::Result<()> {
+    let text = match content {
+        FileContent::Text { text, .. } => text.as_str(),
+        _ => "",
+    };
+    let truncated_note = match content {
+        FileContent::Text {
+            truncated: Some(truncation),
+            ..
+        } => truncation_note(*truncation, config),
+        _ => String::new(),
+    };
+    let mut notes = Vec::new();
+    match content {
+        FileContent::Text {
+            truncated,
+            encoding_note,
+            ..
+        } => {
+            notes.extend(truncated.map(|t| truncation_note(t, config)));
+            notes.extend(encoding_note.clone());
+        }
+        _ => notes.push(format!(
+            "{}: {}",
+            content.skip_kind(),
+            content.skip_reason().unwrap_or_default()
+        )),
+    }
+
+    let rendered = template.render(|name| match name {
+        "path" => label.to_string(),
+        "lang" => lang.to_string(),
+        "content" => text.to_string(),
+        "note" => notes.join("; "),
+        "truncated_note" => truncated_note.clone(),
+        "fence" => fence_for(text),
+        "size" => format_size(text.len() as u64),
+        "lines" => text.lines().count().to_string(),
+        _ => String::new(),
+    });
+    out.write_all(rendered.as_bytes())
+}
+
+/// Whether the Markdown document gives a file with `content` a section (and
+/// so an anchor), rather than only listing it at the end.
+fn has_section(content: &FileContent, config: &Config) -> bool {
+    match content {
+        FileContent::OverByteBudget => false,
+        _ if content.is_skipped() => !(config.no_inline_skips || config.hide_skipped),
+        _ => true,
+    }
+}
+
+/// A skipped file's group (its reason, less any per-file detail) and list
+/// item in the "Skipped files" section.
+fn skipped_entry(label: &str, content: &FileContent) -> (String, String) {
+    let reason = content.skip_reason().unwrap_or_default();
+    match content {
+        FileContent::Duplicate(_) => (
+            "duplicate of an earlier file".to_string(),
+            format!("`{label}` ({reason})"),
+        ),
+        FileContent::ReadError(_) => (
+            "failed to read file".to_string(),
+            format!("`{label}` ({reason})"),
+        ),
+        _ => (reason, format!("`{label}`")),
+    }
+}
+
+/// The "Skipped files" section: every skipped file, grouped by reason in
+/// order of each reason's first file.
+fn write_skipped_section(out: &mut impl Write, skipped: &[(String, String)]) -> io::Result<()> {
+    let mut groups: Vec<(&str, Vec<&str>)> = Vec::new();
+    for (group, item) in skipped {
+        match groups.iter_mut().find(|(g, _)| g == group) {
+            Some((_, items)) => items.push(item),
+            None => groups.push((group, vec![item])),
+        }
+    }
+    writeln!(out, "## Skipped files")?;
+    writeln!(out)?;
+    for (group, items) in groups {
+        writeln!(out, "- {group} ({})", items.len())?;
+        for item in items {
+            writeln!(out, "  - {item}")?;
+        }
+    }
+    writeln!(out)
+}
+
+/// The `--group-by-dir` heading text (after `# `) for the directory of a
+/// file at `rel`.
+fn directory_heading(rel: &Path) -> String {
+    match rel.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        Some(dir) => format!("Directory: {}", dir.display()),
+        None => "Directory: (root)".to_string(),
+    }
+}
+
+/// A file section's heading text (after `## `).
+fn markdown_heading(label: &str, stats: Option<&str>) -> String {
+    match stats {
+        Some(stats) => format!("`{label}` ({stats})"),
+        None => format!("`{label}`"),
+    }
+}
+
+/// The stats shown in a file's heading with `heading_stats` and
+/// `estimate_tokens`, if any.
+fn heading_stats(text: &str, config: &Config) -> Option<String> {
+    let mut stats = Vec::new();
+    if config.heading_stats {
+        let lines = text.lines().count();
+        stats.push(format!(
+            "{lines} {}",
+            if lines == 1 { "line" } else { "lines" }
+        ));
+        stats.push(format_size(text.len() as u64));
+    }
+    if config.estimate_tokens {
+        stats.push(format!(
+            "{} tokens{}",
+            format_tokens(count_tokens(text, config)),
+            if config.tokenizer.is_some() {
+                ""
+            } else {
+                " est."
+            }
+        ));
+    }
+    (!stats.is_empty()).then(|| stats.join(", "))
+}
+
+/// `stats` with `modified <mtime>` (with `show_mtime`) and `sha256 <hash>`
+/// (with `show_hash`) appended, each left out if it can't be read.
+fn with_file_info(stats: Option<String>, path: &Path, config: &Config) -> Option<String> {
+    let mut parts: Vec<String> = stats.into_iter().collect();
+    if config.show_mtime
+        && let Some(mtime) = std::fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(format_rfc3339)
+    {
+        parts.push(format!("modified {mtime}"));
+    }
+    if config.show_hash
+        && let Ok(hash) = File::open(path).and_then(sha256::hex_digest)
+    {
+        parts.push(format!("sha256 {}", &hash[..8]));
+    }
+    (!parts.is_empty()).then(|| parts.join(", "))
+}
+
+fn write_markdown_file(
+    out: &mut impl Write,
+    path: &Path,
+    label: &str,
+    lang: &str,
+    content: &FileContent,
+    config: &Config,
+    open_files: &parallel::OpenFiles,
+) -> io::Result<()> {
+    if let FileContent::Streamed(streamed) = content {
+        let stats = with_file_info(None, path, config);
+        writeln!(out, "## {}", markdown_heading(label, stats.as_deref()))?;
+        writeln!(out)?;
+        open_details(out, label, streamed.len, config)?;
+        if let Some(truncation) = streamed.truncated {
+            writeln!(out, "({})", truncation_note(truncation, config))?;
+            writeln!(out)?;
+        }
+        writeln!(out, "{}{lang}", streamed.fence)?;
+        stream::copy(streamed, out, open_files)?;
+        writeln!(out, "{}", streamed.fence)?;
+        writeln!(out)?;
+        return close_details(out, config);
+    }
+    let FileContent::Text {
+        text,
+        truncated,
+        encoding_note,
+    } = content
+    else {
+        let reason = content.skip_reason().unwrap_or_default();
+        let stats = with_file_info(None, path, config);
+        writeln!(out, "## {}", markdown_heading(label, stats.as_deref()))?;
+        writeln!(out)?;
+        writeln!(out, "({}: {reason})", content.skip_kind())?;
+        writeln!(out)?;
+        return Ok(());
+    };
+
+    let stats = with_file_info(heading_stats(text, config), path, config);
+    writeln!(out, "## {}", markdown_heading(label, stats.as_deref()))?;
+    writeln!(out)?;
+    open_details(out, label, text.len() as u64, config)?;
+
+    if let Some(truncation) = truncated {
+        writeln!(out, "({})", truncation_note(*truncation, config))?;
+        writeln!(out)?;
+    }
+    if let Some(note) = encoding_note {
+        writeln!(out, "({note})")?;
+        writeln!(out)?;
+    }
+
+    let numbered;
+    let text = if numbers_lines(config) {
+        numbered = number_lines(text, config.wrap_long_lines);
+        &numbered
+    } else {
+        text
+    };
+    let fence = fence_for(text);
+    writeln!(out, "{fence}{lang}")?;
+    write!(out, "{text}")?;
+    if !text.ends_with('\n') {
+        writeln!(out)?;
+    }
+    writeln!(out, "{fence}")?;
+    writeln!(out)?;
+    close_details(out, config)
+}
+
+/// With `collapsible`, open the `<details>` block around a file's content,
+/// summarized as `label (size)`. GitHub only renders the Markdown inside
+/// as Markdown after a blank line, so one follows the summary.
+fn open_details(out: &mut impl Write, label: &str, bytes: u64, config: &Config) -> io::Result<()> {
+    if config.collapsible {
+        writeln!(out, "<details>")?;
+        writeln!(
+            out,
+            "<summary>{} ({})</summary>",
+            xml::escape(label, false),
+            format_size(bytes)
+        )?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// Close what `open_details` opened. The content before it ends with a
+/// blank line, which the closing tag also needs.
+fn close_details(out: &mut impl Write, config: &Config) -> io::Result<()> {
+    if config.collapsible {
+        writeln!(out, "</details>")?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// True if Markdown file sections get `line_numbers`. Excerpts from
+/// `locations_from` are numbered already.
+fn numbers_lines(config: &Config) -> bool {
+    config.line_numbers
+        && config.locations_from.is_none()
+        && config.format == Format::Markdown
+        && config.layout_file.is_none()
+}
+
+/// `text` with each line prefixed by its number, right-aligned to the
+/// widest number in the file: `  42 | let x = 1;`. Lines longer than
+/// `wrap` characters continue on rows with a blank number.
+fn number_lines(text: &str, wrap: Option<usize>) -> String {
+    let width = text.lines().count().max(1).to_string().len();
+    let mut out = String::with_capacity(text.len() + text.len() / 8);
+    for (i, line) in text.split_inclusive('\n').enumerate() {
+        let n = i + 1;
+        if line == "\n" || line == "\r\n" {
+            out.push_str(&format!("{n:>width$} |{line}"));
+            continue;
+        }
+        let pieces = match wrap {
+            Some(wrap) => transform::wrap_line(line, wrap),
+            None => vec![line],
+        };
+        let last = pieces.len() - 1;
+        for (j, piece) in pieces.into_iter().enumerate() {
+            match j {
+                0 => out.push_str(&format!("{n:>width$} | {piece}")),
+                _ => out.push_str(&format!("{:width$} | {piece}", "")),
+            }
+            if j < last {
+                out.push('\n');
+            }
+        }
+    }
+    out
+}
+
+/// A backtick fence longer than any backtick run in `text` (at least three),
+/// so content containing fences of its own can't close the block early.
+fn fence_for(text: &str) -> String {
+    let longest = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    "`".repeat((longest + 1).max(3))
+}
+
+/// Sort by the --sort key, then by the path as it will be displayed, so the
+/// listing and content order never depend on enumeration order or on how
+/// the root was spelled. The --*-first priorities come before either.
+fn sort_files(roots: &Roots, files: &mut [PathBuf], config: &Config) {
+    if config.sort == SortMode::Path {
+        files.sort_by(|a, b| compare_display(roots, a, b, config));
+        return;
+    }
+    let mut keyed: Vec<((u8, SortKey), PathBuf)> = files
+        .iter()
+        .map(|path| {
+            let rank = if config.manifests_first || config.schemas_first {
+                priority_rank(roots.local(path), config)
+            } else {
+                0
+            };
+            ((rank, sort_key(path, config.sort)), path.clone())
+        })
+        .collect();
+    keyed.sort_by(|(ka, a), (kb, b)| {
+        ka.cmp(kb)
+            .then_with(|| compare_display(roots, a, b, config))
+    });
+    for (slot, (_, path)) in files.iter_mut().zip(keyed) {
+        *slot = path;
+    }
+}
+
+/// `files` with those matching a `--priority` glob moved to the front, by
+/// the first glob each matches; the order is otherwise kept. Globs match
+/// the paths as shown, root prefix included.
+fn pin_priority(roots: &Roots, files: Vec<PathBuf>, config: &Config) -> io::Result<Vec<PathBuf>> {
+    let globs = &config.priority;
+    let mut matchers = Vec::with_capacity(globs.len());
+    for glob in globs {
+        let glob = glob.trim();
+        let glob = glob.strip_prefix('!').unwrap_or(glob).to_string();
+        let matcher = build_includes(&roots.0[0].dir, &[glob], config.ignore_case)
+            .map_err(io::Error::other)?
+            .expect("one glob given");
+        matchers.push(matcher);
+    }
+    let mut buckets = vec![Vec::new(); globs.len() + 1];
+    for file in files {
+        let bucket = matchers
+            .iter()
+            .position(|m| m.matched(&*roots.rel(&file), false).is_whitelist())
+            .unwrap_or(globs.len());
+        buckets[bucket].push(file);
+    }
+    Ok(buckets.concat())
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum SortKey {
+    None,
+    Size(Reverse<u64>),
+    Mtime(Reverse<SystemTime>),
+    Extension(String),
+}
+
+fn sort_key(path: &Path, mode: SortMode) -> SortKey {
+    let meta = || std::fs::metadata(path).ok();
+    match mode {
+        SortMode::Path => SortKey::None,
+        SortMode::Size => SortKey::Size(Reverse(meta().map_or(0, |m| m.len()))),
+        SortMode::Mtime => SortKey::Mtime(Reverse(
+            meta()
+                .and_then(|m| m.modified().ok())
+                .unwrap_or(SystemTime::UNIX_EPOCH),
+        )),
+        SortMode::Extension => SortKey::Extension(
+            path.extension()
+                .map(|e| e.to_string_lossy().to_ascii_lowercase())
+                .unwrap_or_default(),
+        ),
+    }
+}
+
+/// Component-wise order of the displayed paths. With `--readmes-first`, a
+/// `README*` file sorts ahead of its siblings (files and subdirectories).
+/// With `--manifests-first` / `--schemas-first`, root READMEs and then
+/// manifests and schemas come before everything else (for each root).
+fn compare_display(roots: &Roots, a: &Path, b: &Path, config: &Config) -> Ordering {
+    if config.manifests_first || config.schemas_first {
+        let rank =
+            priority_rank(roots.local(a), config).cmp(&priority_rank(roots.local(b), config));
+        if rank.is_ne() {
+            return rank;
+        }
+    }
+    let a = roots.rel(a);
+    let b = roots.rel(b);
+    if !config.readmes_first {
+        return a.cmp(&b);
+    }
+    readme_key(&a).cmp(&readme_key(&b))
+}
+
+/// Well-known project manifests that orient a reader on a repo.
+const MANIFEST_NAMES: &[&str] = &[
+    "Cargo.toml",
+    "package.json",
+    "deno.json",
+    "pyproject.toml",
+    "setup.py",
+    "setup.cfg",
+    "requirements.txt",
+    "Pipfile",
+    "go.mod",
+    "pom.xml",
+    "build.gradle",
+    "build.gradle.kts",
+    "settings.gradle",
+    "settings.gradle.kts",
+    "build.sbt",
+    "Gemfile",
+    "composer.json",
+    "mix.exs",
+    "Package.swift",
+    "pubspec.yaml",
+    "CMakeLists.txt",
+    "meson.build",
+    "stack.yaml",
+    "deps.edn",
+    "project.clj",
+    "flake.nix",
+];
+
+/// Manifest formats identified by extension rather than a fixed name.
+const MANIFEST_EXTENSIONS: &[&str] = &["cabal", "csproj", "fsproj", "gemspec", "nimble"];
+
+/// 0 for a README at the root, then (when enabled) 1 for manifests and
+/// 2 for schemas anywhere, 3 otherwise.
+fn priority_rank(rel: &Path, config: &Config) -> u8 {
+    let Some(name) = rel.file_name() else {
+        return 3;
+    };
+    if rel.components().count() == 1 && is_readme(name) {
+        0
+    } else if config.manifests_first && is_manifest(rel) {
+        1
+    } else if config.schemas_first && is_schema(rel) {
+        2
+    } else {
+        3
+    }
+}
+
+/// API and data schemas: protobuf, GraphQL, SQL and OpenAPI/Swagger specs.
+fn is_schema(path: &Path) -> bool {
+    if matches!(language_tag(path), "proto" | "graphql" | "sql") {
+        return true;
+    }
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    (name.contains("openapi") || name.contains("swagger"))
+        && [".yaml", ".yml", ".json"]
+            .iter()
+            .any(|ext| name.ends_with(ext))
+}
+
+fn is_manifest(path: &Path) -> bool {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    MANIFEST_NAMES.contains(&name) || MANIFEST_EXTENSIONS.contains(&ext)
+}
+
+/// Components paired with a rank that is `false` only for a trailing
+/// `README*` name, so it sorts first at its level.
+fn readme_key(p: &Path) -> Vec<(bool, Component<'_>)> {
+    let n = p.components().count();
+    p.components()
+        .enumerate()
+        .map(|(i, c)| (!(i + 1 == n && is_readme(c.as_os_str())), c))
+        .collect()
+}
+
+fn is_readme(name: &OsStr) -> bool {
+    name.to_str()
+        .is_some_and(|n| n.to_ascii_lowercase().starts_with("readme"))
+}
+
+/// The path shown for each file in the listing and headings: relative to
+/// its root (under the root's prefix), or joined onto the displayed root
+/// with `absolute_paths`. With `elide_path`, long paths are shortened; any
+/// that would then collide keep their full path so every label still
+/// identifies one file.
+fn display_labels(roots: &Roots, files: &[PathBuf], config: &Config) -> Vec<String> {
+    let full: Vec<String> = files
+        .iter()
+        .map(|p| match roots.find(p) {
+            Some((root, rel)) if config.absolute_paths => {
+                root.display.join(rel).display().to_string()
+            }
+            _ => roots.rel(p).display().to_string(),
+        })
+        .collect();
+    let Some(max) = config.elide_path else {
+        return full;
+    };
+
+    let elided: Vec<String> = full.iter().map(|p| elide_path(p, max)).collect();
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    for label in &elided {
+        *seen.entry(label).or_default() += 1;
+    }
+    elided
+        .iter()
+        .zip(&full)
+        .map(|(short, long)| {
+            if seen[short.as_str()] > 1 {
+                long.clone()
+            } else {
+                short.clone()
+            }
+        })
+        .collect()
+}
+
+/// Shorten `path` to at most `max` chars as `first/.../tail`, keeping as
+/// many trailing components as fit. The first component and the file name
+/// are always kept, even if that exceeds `max`.
+fn elide_path(path: &str, max: usize) -> String {
+    let parts: Vec<&str> = path.split('/').collect();
+    if path.chars().count() <= max || parts.len() <= 2 {
+        return path.to_string();
+    }
+
+    let head = parts[0];
+    let budget = max.saturating_sub(head.chars().count() + "/.../".len());
+    let mut tail_len = parts[parts.len() - 1].chars().count();
+    let mut start = parts.len() - 1;
+    while start > 1 {
+        let next = parts[start - 1].chars().count() + 1;
+        if tail_len + next > budget {
+            break;
+        }
+        tail_len += next;
+        start -= 1;
+    }
+    if start == 1 {
+        return path.to_string();
+    }
+    format!("{head}/.../{}", parts[start..].join("/"))
+}
+
+/// Rough token count using the common ~4 characters per token rule of
+/// thumb. Cheap and dependency-free, but only an estimate.
+fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Tokens in `text`: counted with `config.tokenizer`, else estimated.
+fn count_tokens(text: &str, config: &Config) -> usize {
+    match config.tokenizer {
+        Some(tokenizer) => tokens::count(text, tokenizer),
+        None => estimate_tokens(text),
+    }
+}
+
+/// How token counts were arrived at, for the summary.
+fn token_method(config: &Config) -> &'static str {
+    match config.tokenizer {
+        Some(Tokenizer::Cl100kBase) => "cl100k_base",
+        Some(Tokenizer::O200kBase) => "o200k_base",
+        None => "estimated",
+    }
+}
+
+/// Compact token count, e.g. `~850`, `~1.2k`, `~3.4M`.
+fn format_tokens(tokens: usize) -> String {
+    if tokens < 1_000 {
+        return format!("~{tokens}");
+    }
+    // Rounded to tenths first, so 999,950 becomes `~1.0M`, not `~1000.0k`.
+    let tenths_k = (tokens + 50) / 100;
+    if tenths_k < 10_000 {
+        return format!("~{}.{}k", tenths_k / 10, tenths_k % 10);
+    }
+    let tenths_m = (tokens + 50_000) / 100_000;
+    format!("~{}.{}M", tenths_m / 10, tenths_m % 10)
+}
+
+/// `time` as an RFC 3339 UTC timestamp to the second, e.g.
+/// `2024-05-01T12:34:56Z`; `None` before 1970.
+fn format_rfc3339(time: SystemTime) -> Option<String> {
+    let secs = time.duration_since(SystemTime::UNIX_EPOCH).ok()?.as_secs();
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    // Civil date from days since the epoch (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    Some(format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    ))
+}
+
+/// `--since` value: `YYYY-MM-DD` (midnight UTC) or a number followed by
+/// `s`, `m`, `h`, `d` or `w`, counted back from now.
+fn parse_since(spec: &str) -> Result<SystemTime, String> {
+    let spec = spec.trim();
+    let invalid =
+        || format!("expected a date like `2024-01-15` or a duration like `7d`, got `{spec}`");
+    if let Some((n, unit)) = spec
+        .char_indices()
+        .last()
+        .filter(|(_, unit)| unit.is_ascii_alphabetic())
+        .map(|(i, unit)| (&spec[..i], unit))
+    {
+        let n: u64 = n.parse().map_err(|_| invalid())?;
+        let secs = match unit {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86_400,
+            'w' => 7 * 86_400,
+            _ => return Err(invalid()),
+        };
+        let ago = std::time::Duration::from_secs(n.saturating_mul(secs));
+        return Ok(SystemTime::now()
+            .checked_sub(ago)
+            .unwrap_or(SystemTime::UNIX_EPOCH));
+    }
+
+    let parts: Vec<&str> = spec.split('-').collect();
+    let [year, month, day] = parts.as_slice() else {
+        return Err(invalid());
+    };
+    let (Ok(year), Ok(month), Ok(day)) = (
+        year.parse::<u64>(),
+        month.parse::<u64>(),
+        day.parse::<u64>(),
+    ) else {
+        return Err(invalid());
+    };
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(invalid());
+    }
+    // Days since the epoch from a civil date (Howard Hinnant's algorithm),
+    // the inverse of `format_rfc3339`.
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y / 400;
+    let yoe = y % 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+    Ok(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(days * 86_400))
+}
+
+/// Human-readable size using binary units, e.g. `512 B`, `4.1 KiB`.
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// `$XDG_CONFIG_HOME/dir2prompt/ignore`, if that file exists.
+fn default_global_excludes() -> Option<PathBuf> {
+    let path = config_dir()?.join("ignore");
+    path.is_file().then_some(path)
+}
+
+/// `$XDG_CONFIG_HOME/dir2prompt` (`~/.config/dir2prompt`), whether or not
+/// it exists.
+fn config_dir() -> Option<PathBuf> {
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))?;
+    Some(config.join("dir2prompt"))
+}
+
+/// Read gitignore-style patterns, one per line, skipping blank lines and
+/// `#` comments. Negated (`!`) patterns are kept as they are. A pattern
+/// that isn't a valid glob is reported with its line.
+fn read_pattern_file(path: &Path) -> io::Result<Vec<String>> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+    let mut patterns = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Err(e) = OverrideBuilder::new("/").add(line) {
+            return Err(io::Error::other(format!(
+                "{}:{}: bad pattern '{line}': {e}",
+                path.display(),
+                n + 1
+            )));
+        }
+        patterns.push(line.to_string());
+    }
+    Ok(patterns)
+}
+
+/// Merge a list-valued option across layers. `lower` holds the layers
+/// beneath the command line, lowest first; `cli` is the command-line
+/// value. Each layer appends to the ones below it, so the result is in
+/// precedence order. With `reset`, the lower layers are dropped and only
+/// `cli` is kept.
+fn merge_list_layers(lower: &[&[String]], cli: &[String], reset: bool) -> Vec<String> {
+    let mut merged = Vec::new();
+    if !reset {
+        for layer in lower {
+            merged.extend_from_slice(layer);
+        }
+    }
+    merged.extend_from_slice(cli);
+    merged
+}
+
+/// The built-in exclude list, matched case-insensitively. It is the lowest
+/// layer: --include files are collected by a walk that doesn't apply it,
+/// and `no_default_excludes` drops it altogether.
+fn build_default_excludes(
+    root: &Path,
+    include_lockfiles: bool,
+) -> Result<ignore::overrides::Override, String> {
+    let mut ob = OverrideBuilder::new(root);
+    ob.case_insensitive(true).map_err(|e| e.to_string())?;
+
+    // Always skip VCS dirs (even if someone disables gitignore respecting).
+    // (The walker already has behavior around .git, but this makes it explicit.)
+    add_exclude(&mut ob, "**/.git/**")?;
+    add_exclude(&mut ob, "**/.hg/**")?;
+    add_exclude(&mut ob, "**/.svn/**")?;
+
+    // Common virtualenv / cache / build artifacts
+    add_exclude(&mut ob, "**/.venv/**")?;
+    add_exclude(&mut ob, "**/venv/**")?;
+    add_exclude(&mut ob, "**/__pycache__/**")?;
+    add_exclude(&mut ob, "**/.mypy_cache/**")?;
+    add_exclude(&mut ob, "**/.pytest_cache/**")?;
+    add_exclude(&mut ob, "**/.ruff_cache/**")?;
+    add_exclude(&mut ob, "**/.tox/**")?;
+
+    // Common dependency/build output dirs
+    add_exclude(&mut ob, "**/node_modules/**")?;
+    add_exclude(&mut ob, "**/target/**")?;
+    add_exclude(&mut ob, "**/dist/**")?;
+    add_exclude(&mut ob, "**/build/**")?;
+    add_exclude(&mut ob, "**/.next/**")?;
+    add_exclude(&mut ob, "**/.nuxt/**")?;
+    add_exclude(&mut ob, "**/.svelte-kit/**")?;
+
+    // OS/editor noise
+    add_exclude(&mut ob, "**/.DS_Store")?;
+    add_exclude(&mut ob, "**/Thumbs.db")?;
+
+    // “Package files” / lockfiles (skip by default; can be re-enabled)
+    if !include_lockfiles {
+        add_exclude(&mut ob, "**/Cargo.lock")?;
+        add_exclude(&mut ob, "**/package-lock.json")?;
+        add_exclude(&mut ob, "**/yarn.lock")?;
+        add_exclude(&mut ob, "**/pnpm-lock.yaml")?;
+        add_exclude(&mut ob, "**/composer.lock")?;
+        add_exclude(&mut ob, "**/Gemfile.lock")?;
+        add_exclude(&mut ob, "**/poetry.lock")?;
+        add_exclude(&mut ob, "**/Pipfile.lock")?;
+    }
+
+    ob.build().map_err(|e| e.to_string())
+}
+
+/// User excludes (`--exclude` and lower layers), matched case-sensitively
+/// unless `ignore_case`, as a matcher whose whitelist matches are the
+/// excluded paths. Override syntax reads `!` as "ignore", the opposite of
+/// .gitignore, so the patterns are added as they are and the result read
+/// the other way round: the last pattern matching a path decides, and a
+/// path whose last match is a `!pattern` (or that matches none) is kept.
+fn build_excludes(
+    root: &Path,
+    excludes: &[String],
+    ignore_case: bool,
+) -> Result<ignore::overrides::Override, String> {
+    let mut ob = OverrideBuilder::new(root);
+    ob.case_insensitive(ignore_case)
+        .map_err(|e| e.to_string())?;
+    for ex in excludes {
+        ob.add(ex).map_err(|e| format!("bad exclude '{ex}': {e}"))?;
+    }
+    ob.build().map_err(|e| e.to_string())
+}
+
+/// Includes are plain (whitelist) globs in override syntax, where a
+/// `!pattern` takes back an earlier one. Returns `None` when there are
+/// none, since an empty whitelist would match nothing.
+fn build_includes(
+    root: &Path,
+    includes: &[String],
+    ignore_case: bool,
+) -> Result<Option<ignore::overrides::Override>, String> {
+    if includes.is_empty() {
+        return Ok(None);
+    }
+    let mut ob = OverrideBuilder::new(root);
+    ob.case_insensitive(ignore_case)
+        .map_err(|e| e.to_string())?;
+    for inc in includes {
+        ob.add(inc)
+            .map_err(|e| format!("bad override '{inc}': {e}"))?;
+    }
+    ob.build().map(Some).map_err(|e| e.to_string())
+}
+
+/// Add `pattern` to `ob` as an exclude. In override syntax a leading `!`
+/// means "ignore"; the glob after it keeps gitignore anchoring, so
+/// `!/Cargo.toml` still only matches at the root.
+fn add_exclude(ob: &mut OverrideBuilder, pattern: &str) -> Result<(), String> {
+    let line = format!("!{}", pattern.trim());
+    ob.add(&line)
+        .map_err(|e| format!("bad override '{line}': {e}"))?;
+    Ok(())
+}
+
+struct ReadResult {
+    bytes: Vec<u8>,
+    truncated: Option<Truncation>,
+}
+
+/// Read at most `max_bytes`. With `soft`, a truncated read is cut back to
+/// the last complete line (unless the first line alone exceeds the cap).
+/// With `TruncateMode::HeadTail`, a file over the cap is read as its first
+/// and last halves, joined by an omission marker line. The file is open
+/// under a permit from `open_files`.
+fn read_file_limited(
+    path: &Path,
+    max_bytes: usize,
+    soft: bool,
+    mode: TruncateMode,
+    open_files: &parallel::OpenFiles,
+) -> io::Result<ReadResult> {
+    let _permit = open_files.permit();
+    let mut f = File::open(path)?;
+    if mode == TruncateMode::HeadTail {
+        let len = f.metadata()?.len();
+        if len > max_bytes as u64 {
+            return read_head_tail(&mut f, len, max_bytes, soft);
+        }
+    }
+    let mut buf = Vec::with_capacity(std::cmp::min(max_bytes, 64 * 1024));
+
+    let mut limited = f.take((max_bytes as u64) + 1);
+    limited.read_to_end(&mut buf)?;
+
+    let mut truncated = None;
+    if buf.len() > max_bytes {
+        buf.truncate(max_bytes);
+        if soft && let Some(nl) = buf.iter().rposition(|&b| b == b'\n') {
+            buf.truncate(nl + 1);
+        }
+        // The read stopped early, so the size comes from the file itself
+        // (at least what was read, should it have grown meanwhile).
+        let original = limited
+            .into_inner()
+            .metadata()?
+            .len()
+            .max(max_bytes as u64 + 1);
+        truncated = Some(Truncation::Bytes {
+            limit: max_bytes as u64,
+            original,
+            omitted: original - buf.len() as u64,
+        });
+    }
+
+    Ok(ReadResult {
+        bytes: buf,
+        truncated,
+    })
+}
+
+/// The first and last halves of `max_bytes` from a file of `len` bytes,
+/// with `… (N bytes omitted) …` on a line between them. Both cuts are
+/// moved off the middle of a UTF-8 sequence, and with `soft` onto line
+/// boundaries.
+fn read_head_tail(f: &mut File, len: u64, max_bytes: usize, soft: bool) -> io::Result<ReadResult> {
+    let head_len = max_bytes / 2;
+    let tail_len = max_bytes - head_len;
+
+    let mut head = Vec::with_capacity(head_len);
+    Read::by_ref(f)
+        .take(head_len as u64)
+        .read_to_end(&mut head)?;
+    let mut tail = Vec::with_capacity(tail_len);
+    f.seek(SeekFrom::Start(len - tail_len as u64))?;
+    f.take(tail_len as u64).read_to_end(&mut tail)?;
+
+    let is_continuation = |b: u8| b & 0xC0 == 0x80;
+    if soft && let Some(nl) = head.iter().rposition(|&b| b == b'\n') {
+        head.truncate(nl + 1);
+    } else if let Some(lead) = head.iter().rposition(|&b| !is_continuation(b))
+        && lead + utf8_sequence_len(head[lead]) > head.len()
+    {
+        head.truncate(lead);
+    }
+    let tail_start = if soft && let Some(nl) = tail.iter().position(|&b| b == b'\n') {
+        nl + 1
+    } else {
+        // A newline at the cut ends an omitted line; the marker has its own.
+        let start = tail
+            .iter()
+            .position(|&b| !is_continuation(b))
+            .unwrap_or(tail.len());
+        start + usize::from(tail.get(start) == Some(&b'\n'))
+    };
+    let tail = &tail[tail_start..];
+
+    let omitted = len - head.len() as u64 - tail.len() as u64;
+    let mut bytes = head;
+    if !bytes.is_empty() && !bytes.ends_with(b"\n") {
+        bytes.push(b'\n');
+    }
+    bytes.extend_from_slice(format!("… ({omitted} bytes omitted) …\n").as_bytes());
+    bytes.extend_from_slice(tail);
+    Ok(ReadResult {
+        bytes,
+        truncated: Some(Truncation::Bytes {
+            limit: max_bytes as u64,
+            original: len,
+            omitted,
+        }),
+    })
+}
+
+/// Length of the UTF-8 sequence a lead byte starts (1 for anything else).
+fn utf8_sequence_len(lead: u8) -> usize {
+    match lead {
+        0xF0..=0xF7 => 4,
+        0xE0..=0xEF => 3,
+        0xC0..=0xDF => 2,
+        _ => 1,
+    }
+}
+
+/// Recognize common lockfiles by their generated preamble or structure,
+/// whatever they are named. Only the start of the file is inspected.
+fn looks_like_lockfile(text: &str) -> bool {
+    let mut end = text.len().min(4 * 1024);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let head = &text[..end];
+
+    // Cargo, Poetry and other tools that stamp "@generated".
+    let generated = head.starts_with("# This file is automatically @generated by")
+        // yarn v1
+        || head.contains("# yarn lockfile v1")
+        // pnpm-lock.yaml
+        || head.starts_with("lockfileVersion:");
+    // package-lock.json / npm-shrinkwrap.json
+    let npm = head.trim_start().starts_with('{') && head.contains("\"lockfileVersion\"");
+    // yarn berry
+    let yarn_berry = head.contains("__metadata:") && head.contains("cacheKey:");
+    // composer.lock
+    let composer =
+        head.contains("\"_readme\"") && head.contains("This file locks the dependencies");
+    // Pipfile.lock
+    let pipfile = head.contains("\"_meta\"") && head.contains("\"pipfile-spec\"");
+    // Gemfile.lock
+    let gemfile =
+        head.starts_with("GEM\n") && head.contains("  remote:") && head.contains("  specs:");
+    // go.sum: every line is `module version h1:hash=`
+    let go_sum = !head.is_empty()
+        && head.lines().take(20).all(|l| {
+            let f: Vec<&str> = l.split_whitespace().collect();
+            f.len() == 3 && f[2].starts_with("h1:") && f[2].ends_with('=')
+        });
+
+    generated || npm || yarn_berry || composer || pipfile || gemfile || go_sum
+}
+
+/// How much of a file `looks_minified` inspects.
+const MINIFIED_SAMPLE_BYTES: usize = 64 * 1024;
+/// Samples shorter than this are never called minified; one long line in
+/// a small file is just a long line.
+const MINIFIED_MIN_BYTES: usize = 2 * 1024;
+/// Average line length above which a sample looks minified.
+const MINIFIED_AVG_LINE: usize = 500;
+/// Fraction of the sample in lines over `MINIFIED_AVG_LINE` above which
+/// it looks minified, so a formatted file with one huge data line passes.
+const MINIFIED_LONG_LINE_SHARE: f64 = 0.8;
+
+/// Heuristic over the start of the file: minified if its lines are very
+/// long on average and nearly all of its text is in such long lines.
+fn looks_minified(text: &str) -> bool {
+    let mut end = text.len().min(MINIFIED_SAMPLE_BYTES);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let sample = &text[..end];
+    if sample.len() < MINIFIED_MIN_BYTES {
+        return false;
+    }
+    let lines = sample.lines().count().max(1);
+    if sample.len() / lines <= MINIFIED_AVG_LINE {
+        return false;
+    }
+    let in_long_lines: usize = sample
+        .lines()
+        .map(str::len)
+        .filter(|&len| len > MINIFIED_AVG_LINE)
+        .sum();
+    in_long_lines as f64 > sample.len() as f64 * MINIFIED_LONG_LINE_SHARE
+}
+
+/// Heuristic over the first 8 KiB: binary if it has a NUL byte (unless it
+/// looks like BOM-less UTF-16) or if more than 30% of it is control bytes.
+/// Whitespace, backspace and ESC don't count, so terminal logs and man
+/// page output stay text.
+fn looks_binary(bytes: &[u8]) -> bool {
+    let sample = &bytes[..bytes.len().min(8 * 1024)];
+    if sample.contains(&0) {
+        return encoding::sniff_utf16(sample).is_none();
+    }
+    let control = sample
+        .iter()
+        .filter(|&&b| {
+            (b < 0x20 || b == 0x7f)
+                && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0b | 0x0c | 0x08 | 0x1b)
+        })
+        .count();
+    control * 10 > sample.len() * 3
+}
+
+/// UTF-8 as is, less a leading byte-order mark (never content, and it
+/// would show as a stray character on the first line); otherwise
+/// transcoded from a detected (or `--encoding`) legacy encoding, falling
+/// back to skipping (`--strict-utf8`) or lossy replacement when detection
+/// is uncertain. UTF-16 BOMs are handled earlier, by `decode_bom`.
+fn bytes_to_text(bytes: &[u8], config: &Config) -> (Option<String>, Option<String>) {
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    match std::str::from_utf8(bytes) {
+        Ok(s) => (Some(s.to_string()), None),
+        Err(_) => match encoding::transcode(bytes, config.encoding) {
+            Some((text, name)) => (Some(text), Some(format!("note: transcoded from {name}"))),
+            None if config.strict_utf8 => (None, None),
+            None => (
+                Some(String::from_utf8_lossy(bytes).to_string()),
+                Some(
+                    "note: contained invalid UTF-8; printed with lossless replacement".to_string(),
+                ),
+            ),
+        },
+    }
+}
+
+/// `--header`/`--footer` value: the text itself, or with a leading `@`
+/// the contents of the file it names.
+fn parse_text_arg(arg: &str) -> Result<String, String> {
+    match arg.strip_prefix('@') {
+        Some(path) => std::fs::read_to_string(path).map_err(|err| format!("{path}: {err}")),
+        None => Ok(arg.to_string()),
+    }
+}
+
+/// `--max-bytes-ext` entry `ext=N`.
+fn parse_max_bytes_ext(entry: &str) -> Result<(String, usize), String> {
+    let Some((ext, max)) = entry.split_once('=') else {
+        return Err(format!("expected EXT=N, got `{entry}`"));
+    };
+    let ext = normalize_ext(ext);
+    if ext.is_empty() {
+        return Err(format!("missing extension in `{entry}`"));
+    }
+    match max.trim().parse::<usize>() {
+        Ok(max) => Ok((ext, max)),
+        Err(_) => Err(format!(
+            "byte limit in `{entry}` must be a non-negative whole number"
+        )),
+    }
+}
+
+/// `--lang-map` entry `ext=lang`. The language is leaked: there are only
+/// a handful, and tags are `&'static str` everywhere else.
+fn parse_lang_map(entry: &str) -> Result<(String, &'static str), String> {
+    let Some((ext, lang)) = entry.split_once('=') else {
+        return Err(format!("expected EXT=LANG, got `{entry}`"));
+    };
+    let (ext, lang) = (normalize_ext(ext), lang.trim());
+    if ext.is_empty() {
+        return Err(format!("missing extension in `{entry}`"));
+    }
+    if lang.is_empty() || lang.contains(char::is_whitespace) {
+        return Err(format!(
+            "language in `{entry}` must be one word, e.g. `tpl=go-template`"
+        ));
+    }
+    Ok((ext, Box::leak(lang.to_string().into_boxed_str())))
+}
+
+/// `max_bytes` for `path`: its extension's `max_bytes_ext` override (the
+/// last given wins), else the global limit.
+fn max_bytes_for(path: &Path, config: &Config) -> usize {
+    let ext = path
+        .extension()
+        .and_then(|s| s.to_str())
+        .map(normalize_ext)
+        .unwrap_or_default();
+    config
+        .max_bytes_ext
+        .iter()
+        .rev()
+        .find(|(e, _)| !ext.is_empty() && *e == ext)
+        .map_or(config.max_bytes, |&(_, max)| max)
+}
+
+/// The `--lang-map` language for `path`'s extension (the last mapping
+/// given wins), else `language_tag`.
+fn mapped_language(path: &Path, config: &Config) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|s| s.to_str())
+        .map(normalize_ext)
+        .unwrap_or_default();
+    config
+        .lang_map
+        .iter()
+        .rev()
+        .find(|(e, _)| !ext.is_empty() && *e == ext)
+        .map_or_else(|| language_tag(path), |&(_, lang)| lang)
+}
+
+/// How much of a file `detect_language` reads to sniff its language.
+const SNIFF_BYTES: u64 = 16 * 1024;
+
+/// `mapped_language`, falling back to the shebang line and then to the
+/// content for files whose extension says nothing better than `text`
+/// (e.g. `bin/deploy`, `.eslintrc`).
+fn detect_language(path: &Path, config: &Config) -> &'static str {
+    match mapped_language(path, config) {
+        "text" => {
+            let mut head = Vec::new();
+            let _ = File::open(path).and_then(|f| f.take(SNIFF_BYTES).read_to_end(&mut head));
+            let first_line = head.split(|&b| b == b'\n').next().unwrap_or(&[]);
+            if let Some(lang) = std::str::from_utf8(first_line)
+                .ok()
+                .and_then(language_from_shebang)
+            {
+                return lang;
+            }
+            // A sample cut mid-character still sniffs.
+            let text = match std::str::from_utf8(&head) {
+                Ok(text) => text,
+                Err(e) if e.error_len().is_none() => {
+                    std::str::from_utf8(&head[..e.valid_up_to()]).unwrap_or_default()
+                }
+                Err(_) => return "text",
+            };
+            let complete = (head.len() as u64) < SNIFF_BYTES;
+            detect_language_from_content(text, complete).unwrap_or("text")
+        }
+        lang => lang,
+    }
+}
+
+/// JSON, YAML, TOML, XML or HTML recognized from the start of a file,
+/// erring towards `None`. `complete` says `text` is the whole file; JSON is
+/// only recognized then, since it has to parse.
+fn detect_language_from_content(text: &str, complete: bool) -> Option<&'static str> {
+    let trimmed = text.trim_start_matches('\u{feff}').trim();
+    let lower = trimmed
+        .get(..trimmed.len().min(64))
+        .unwrap_or(trimmed)
+        .to_ascii_lowercase();
+    if lower.starts_with("<!doctype html") || lower.starts_with("<html") {
+        return Some("html");
+    }
+    if lower.starts_with("<?xml") || lower.starts_with("<svg") {
+        return Some("xml");
+    }
+    if trimmed.starts_with(['{', '[']) && complete && json::is_valid(trimmed) {
+        return Some("json");
+    }
+
+    // Line shapes, ignoring blanks and comments.
+    let lines: Vec<&str> = text
+        .lines()
+        .filter(|l| !l.trim().is_empty() && !l.trim_start().starts_with('#'))
+        .take(200)
+        .collect();
+    let is_key = |s: &str| {
+        !s.is_empty()
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+    };
+    let is_section = |l: &str| {
+        l.strip_prefix('[')
+            .and_then(|l| l.strip_suffix(']'))
+            .map(|l| l.trim_start_matches('[').trim_end_matches(']'))
+            .is_some_and(|name| name.split('.').all(|part| is_key(part.trim_matches('"'))))
+    };
+    let is_assignment = |l: &str| l.split_once('=').is_some_and(|(key, _)| is_key(key.trim()));
+    // Continuation lines of multi-line arrays and strings are indented.
+    let toml = lines.iter().any(|l| is_section(l.trim()))
+        && lines.iter().all(|l| {
+            let l = l.trim_end();
+            is_section(l) || is_assignment(l) || l.starts_with([' ', '\t', ']'])
+        });
+    if toml {
+        return Some("toml");
+    }
+
+    let top_level_key = |l: &str| {
+        l.split_once(':')
+            .is_some_and(|(key, rest)| is_key(key) && (rest.is_empty() || rest.starts_with(' ')))
+    };
+    let body = match lines.first() {
+        Some(&"---") => &lines[1..],
+        _ => &lines[..],
+    };
+    // Two `Word: ...` lines could be prose; want a third key, nesting or a
+    // document marker too.
+    let keys = body.iter().filter(|l| top_level_key(l)).count();
+    let nested = body.len() > keys || body.len() < lines.len();
+    let yaml = (keys >= 3 || keys == 2 && nested)
+        && body.iter().all(|l| {
+            top_level_key(l) || l.starts_with([' ', '\t']) || l.starts_with("- ") || *l == "---"
+        });
+    yaml.then_some("yaml")
+}
+
+/// The language named by a `#!` line: `#!/bin/sh`, `#!/usr/bin/env -S
+/// python3 -u`, ...
+fn language_from_shebang(first_line: &str) -> Option<&'static str> {
+    let rest = first_line.strip_prefix("#!")?;
+    let mut words = rest.split_whitespace();
+    let mut interpreter = words.next()?.rsplit('/').next()?;
+    if interpreter == "env" {
+        interpreter = words.find(|w| !w.starts_with('-'))?;
+    }
+    // `python3.12` -> `python`, `ruby2.7` -> `ruby`
+    let name = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    Some(match name {
+        "python" | "pypy" => "python",
+        "sh" | "bash" | "dash" | "ksh" => "bash",
+        "zsh" => "zsh",
+        "fish" => "fish",
+        "node" | "nodejs" | "deno" | "bun" => "javascript",
+        "ts-node" => "ts",
+        "ruby" => "ruby",
+        "perl" => "perl",
+        "php" => "php",
+        "lua" => "lua",
+        "Rscript" => "r",
+        _ => return None,
+    })
+}
+
+fn language_tag(path: &Path) -> &'static str {
+    // Well-known names win over the extension (`CMakeLists.txt` isn't text).
+    let name = path
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    match name.as_str() {
+        n if n.starts_with("dockerfile") || n == "containerfile" => return "dockerfile",
+        "makefile" | "gnumakefile" => return "makefile",
+        "gemfile" | "rakefile" | "podfile" | "vagrantfile" => return "ruby",
+        "cmakelists.txt" => return "cmake",
+        _ => {}
+    }
+
+    let ext = path
+        .extension()
+        .and_then(|s| s.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+
+    match ext.as_str() {
+        "rs" => "rust",
+        "toml" => "toml",
+        "md" => "markdown",
+        "txt" => "text",
+        "json" => "json",
+        "yml" | "yaml" => "yaml",
+        "js" => "javascript",
+        "ts" => "ts",
+        "jsx" => "jsx",
+        "tsx" => "tsx",
+        "py" => "python",
+        "sh" => "bash",
+        "zsh" => "zsh",
+        "fish" => "fish",
+        "go" => "go",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "c" => "c",
+        "h" => "c",
+        "cpp" | "cc" | "cxx" => "cpp",
+        "hpp" | "hh" | "hxx" => "cpp",
+        "cs" => "csharp",
+        "swift" => "swift",
+        "rb" => "ruby",
+        "php" => "php",
+        "sql" => "sql",
+        "html" => "html",
+        "css" => "css",
+        "scss" => "scss",
+        "proto" => "proto",
+        "graphql" | "gql" => "graphql",
+        "ini" => "ini",
+        "env" => "bash",
+        "mk" => "makefile",
+        "cmake" => "cmake",
+        "dockerfile" => "dockerfile",
+        _ => "text",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A temporary tree of `files` (path, content).
+    fn tree(files: &[(&str, &str)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, content) in files {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    fn config_for(dir: &Path) -> Config {
+        Config {
+            roots: vec![dir.to_path_buf()],
+            ..Config::default()
+        }
+    }
+
+    /// Fisher-Yates with a xorshift generator, so failures reproduce.
+    fn shuffle<T>(items: &mut [T], seed: &mut u64) {
+        for i in (1..items.len()).rev() {
+            *seed ^= *seed << 13;
+            *seed ^= *seed >> 7;
+            *seed ^= *seed << 17;
+            items.swap(i, (*seed % (i as u64 + 1)) as usize);
+        }
+    }
+
+    #[test]
+    fn file_order_does_not_depend_on_walk_order() {
+        let dir = tree(&[
+            ("README.md", "r"),
+            ("Cargo.toml", "c"),
+            ("src/main.rs", "fn main() {}"),
+            ("src/lib.rs", "x"),
+            ("src/a/README.md", "nested readme"),
+            ("src/a/b.rs", "b"),
+            ("src/a_b.rs", "ab"),
+            ("docs/guide.MD", "g"),
+            ("z.txt", ""),
+            ("Makefile", "all:"),
+        ]);
+        let variants: [fn(&mut Config); 5] = [
+            |_| {},
+            |c| c.readmes_first = true,
+            |c| c.manifests_first = true,
+            |c| c.sort = SortMode::Size,
+            |c| c.sort = SortMode::Extension,
+        ];
+        for configure in variants {
+            let mut config = config_for(dir.path());
+            configure(&mut config);
+            let roots = Roots::new(&config).unwrap();
+            let (mut files, _) = walk(&config, &roots.0[0].dir).unwrap();
+            sort_files(&roots, &mut files, &config);
+            let expected = files.clone();
+            let mut seed = 0x9E37_79B9_7F4A_7C15;
+            for _ in 0..20 {
+                shuffle(&mut files, &mut seed);
+                sort_files(&roots, &mut files, &config);
+                assert_eq!(files, expected);
+            }
+        }
+    }
+
+    /// The dump order of `config`'s files, as displayed.
+    fn order(config: &Config) -> Vec<String> {
+        Plan::new(config).unwrap().labels
+    }
+
+    #[test]
+    fn priority_stays_first_when_reversed() {
+        let dir = tree(&[
+            ("README.md", "r"),
+            ("a.rs", "a"),
+            ("b.rs", "b"),
+            ("c.rs", "c"),
+        ]);
+        let config = Config {
+            reverse: true,
+            priority: vec!["README.md".to_string()],
+            ..config_for(dir.path())
+        };
+        assert_eq!(order(&config), ["README.md", "c.rs", "b.rs", "a.rs"]);
+    }
+
+    #[test]
+    fn exclude_layers_apply_in_precedence_order() {
+        let dir = tree(&[
+            ("a.log", "a"),
+            ("keep.log", "k"),
+            ("b.rs", "b"),
+            ("c.rs", "c"),
+            ("node_modules/x.js", "x"),
+        ]);
+        let patterns = tempfile::tempdir().unwrap();
+        let global = patterns.path().join("ignore");
+        std::fs::write(&global, "# shared\n*.log\n").unwrap();
+        let config = Config {
+            global_excludes: Some(global.clone()),
+            exclude: vec!["b.rs".to_string()],
+            include: vec!["keep.log".to_string()],
+            ..config_for(dir.path())
+        };
+        assert_eq!(order(&config), ["c.rs", "keep.log"]);
+
+        // --exclude-reset drops the global layer but keeps --exclude.
+        let config = Config {
+            exclude_reset: true,
+            ..config
+        };
+        assert_eq!(order(&config), ["a.log", "c.rs", "keep.log"]);
+
+        // Without --include, the global layer and the built-in list both
+        // apply.
+        let config = Config {
+            global_excludes: Some(global),
+            ..config_for(dir.path())
+        };
+        assert_eq!(order(&config), ["b.rs", "c.rs"]);
+    }
+
+    #[test]
+    fn pattern_files_take_back_matches_with_a_bang() {
+        let dir = tree(&[
+            ("a.log", "a"),
+            ("keep.log", "k"),
+            ("b.rs", "b"),
+            ("c.rs", "c"),
+        ]);
+        let patterns = tempfile::tempdir().unwrap();
+        let write = |name: &str, text: &str| {
+            let path = patterns.path().join(name);
+            std::fs::write(&path, text).unwrap();
+            path
+        };
+        let global = write("ignore", "*.log\n!keep.log\n");
+        let excludes = write("excludes", "*.rs\n!c.rs\n");
+        let includes = write("includes", "*.log\n!a.log\n");
+
+        let config = Config {
+            global_excludes: Some(global.clone()),
+            ..config_for(dir.path())
+        };
+        assert_eq!(order(&config), ["b.rs", "c.rs", "keep.log"]);
+
+        // A later layer's negation takes back an earlier layer's exclude.
+        let config = Config {
+            exclude_from: vec![excludes.clone(), write("more", "!a.log\n")],
+            ..config
+        };
+        assert_eq!(order(&config), ["a.log", "c.rs", "keep.log"]);
+
+        // An include taken back leaves the path to the excludes.
+        let config = Config {
+            exclude: vec!["*.log".to_string()],
+            include_from: vec![includes],
+            ..config_for(dir.path())
+        };
+        assert_eq!(order(&config), ["b.rs", "c.rs", "keep.log"]);
+
+        // On the command line a leading `!` is still a plain exclude.
+        let config = Config {
+            exclude: vec!["!b.rs".to_string()],
+            ..config_for(dir.path())
+        };
+        assert_eq!(order(&config), ["a.log", "c.rs", "keep.log"]);
+    }
+
+    #[test]
+    fn dedup_only_stubs_identical_text() {
+        let dir = tree(&[
+            ("a.txt", "same\n"),
+            ("b.txt", "same\n"),
+            ("c.txt", "other\n"),
+        ]);
+        let config = Config {
+            dedup: true,
+            quiet: true,
+            ..config_for(dir.path())
+        };
+        let mut out = Vec::new();
+        let summary = dump(&config, &mut out).unwrap();
+        assert_eq!((summary.printed, summary.skipped_duplicates), (2, 1));
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("duplicate of a.txt"), "{out}");
+    }
+
+    #[test]
+    fn include_walks_skip_vcs_and_stay_under_their_base() {
+        let globs = |g: &[&str]| g.iter().map(|g| g.to_string()).collect::<Vec<_>>();
+        let includes = globs(&["src/gen/*.rs", "src/gen/sub/x.rs", "/docs/**", "!a/*"]);
+        assert_eq!(include_bases(&includes), [vec!["docs"], vec!["src", "gen"]]);
+        let includes = globs(&["**/*.md", "target/keep.txt", "*.log"]);
+        assert_eq!(include_bases(&includes), [Vec::<&str>::new()]);
+
+        let dir = tree(&[
+            ("a.rs", "a"),
+            ("x.log", "x"),
+            (".git/y.log", "y"),
+            (".cache/z.log", "z"),
+            (".hidden.log", "h"),
+            ("target/keep.txt", "k"),
+            ("target/other.txt", "o"),
+        ]);
+        let config = Config {
+            include: globs(&["*.log", "target/keep.txt"]),
+            ..config_for(dir.path())
+        };
+        assert_eq!(
+            order(&config),
+            [
+                ".cache/z.log",
+                ".hidden.log",
+                "a.rs",
+                "target/keep.txt",
+                "x.log"
+            ]
+        );
+        let config = Config {
+            no_hidden: true,
+            ..config
+        };
+        assert_eq!(
+            order(&config),
+            [".hidden.log", "a.rs", "target/keep.txt", "x.log"]
+        );
+
+        // An anchored glob is walked from its directory, at the same depth.
+        let config = Config {
+            include: globs(&["target/keep.txt"]),
+            ..config_for(dir.path())
+        };
+        assert!(order(&config).contains(&"target/keep.txt".to_string()));
+        let config = Config {
+            depth: Some(1),
+            ..config
+        };
+        assert!(!order(&config).contains(&"target/keep.txt".to_string()));
+    }
+
+    #[test]
+    fn soft_max_bytes_never_cuts_a_line() {
+        let text: String = (0..40)
+            .map(|n| format!("line {n} {}\n", "x".repeat(n % 7)))
+            .collect();
+        let dir = tree(&[("a.txt", &text)]);
+        let path = dir.path().join("a.txt");
+        let lines: Vec<&str> = text.split_inclusive('\n').collect();
+        let open_files = parallel::OpenFiles::new(1);
+        // Below this, a single line can exceed the cap (or half of it) and
+        // is cut anyway.
+        let longest = lines.iter().map(|l| l.len()).max().unwrap();
+        for max in 2 * longest..text.len() {
+            let read =
+                read_file_limited(&path, max, true, TruncateMode::Head, &open_files).unwrap();
+            let head = String::from_utf8(read.bytes).unwrap();
+            assert!(head.len() <= max, "cap {max}");
+            assert!(text.starts_with(&head), "cap {max}");
+            assert!(
+                head.is_empty() || head.ends_with('\n'),
+                "cap {max}: {head:?}"
+            );
+
+            let read =
+                read_file_limited(&path, max, true, TruncateMode::HeadTail, &open_files).unwrap();
+            let both = String::from_utf8(read.bytes).unwrap();
+            for line in both.split_inclusive('\n') {
+                assert!(
+                    lines.contains(&line) || line.ends_with("bytes omitted) …\n"),
+                    "cap {max}: {line:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn default_excludes_catch_any_case() {
+        let dir = tree(&[
+            ("Node_Modules/dep/index.js", "x"),
+            ("src/NODE_MODULES/x.js", "x"),
+            ("THUMBS.DB", "x"),
+            ("docs/.ds_store", "x"),
+            ("Target/debug/out", "x"),
+            ("a.log", "x"),
+            ("B.LOG", "x"),
+            ("main.rs", "fn main() {}"),
+        ]);
+        let config = Config {
+            exclude: vec!["*.log".to_string()],
+            ..config_for(dir.path())
+        };
+        // User patterns keep their case without --ignore-case.
+        assert_eq!(order(&config), ["B.LOG", "main.rs"]);
+        let config = Config {
+            ignore_case: true,
+            ..config
+        };
+        assert_eq!(order(&config), ["main.rs"]);
+    }
+
+    #[test]
+    fn fences_outgrow_backtick_runs() {
+        assert_eq!(fence_for("plain"), "```");
+        assert_eq!(fence_for("`inline` and ``double``"), "```");
+        // A file that is itself a fenced block.
+        assert_eq!(fence_for("```rust\nfn main() {}\n```\n"), "````");
+        // One that ends mid-fence, and one with a longer run inside.
+        assert_eq!(fence_for("# Notes\n\n```sh\nmake"), "````");
+        assert_eq!(fence_for("`````\n```\n"), "``````");
+    }
+
+    #[test]
+    fn dir2promptignore_applies_without_gitignore() {
+        let dir = tree(&[
+            (".dir2promptignore", "*.snap\nfixtures/\n"),
+            ("src/lib.rs", "x"),
+            ("src/lib.snap", "x"),
+            ("fixtures/big.json", "x"),
+            ("sub/.dir2promptignore", "local.txt\n"),
+            ("sub/local.txt", "x"),
+            ("local.txt", "x"),
+        ]);
+        let expected = [
+            ".dir2promptignore",
+            "local.txt",
+            "src/lib.rs",
+            "sub/.dir2promptignore",
+        ];
+        assert_eq!(order(&config_for(dir.path())), expected);
+        let config = Config {
+            no_gitignore: true,
+            ..config_for(dir.path())
+        };
+        assert_eq!(order(&config), expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn linked_directory_is_dumped_once() {
+        use std::os::unix::fs::symlink;
+
+        let dir = tree(&[("shared/proto/api.proto", "syntax"), ("repo/main.rs", "x")]);
+        let repo = dir.path().join("repo");
+        symlink(dir.path().join("shared/proto"), repo.join("proto")).unwrap();
+        symlink(repo.join("proto"), repo.join("again")).unwrap();
+        // A loop back to the root.
+        symlink(&repo, dir.path().join("shared/proto/up")).unwrap();
+
+        assert_eq!(order(&config_for(&repo)), ["main.rs"]);
+        let config = Config {
+            follow_links: true,
+            ..config_for(&repo)
+        };
+        assert_eq!(order(&config), ["again/api.proto", "main.rs"]);
+    }
+
+    #[test]
+    fn binary_detection() {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\0\0\x01\0\0\0\x01\0\x08\x06\0\0\0";
+        assert!(looks_binary(png));
+        // No NUL, but mostly control bytes.
+        let packed: Vec<u8> = (0..512u32).map(|i| (i % 31 + 1) as u8).collect();
+        assert!(looks_binary(&packed));
+
+        let source = "fn main() {\n\tprintln!(\"hi\");\r\n}\n";
+        assert!(!looks_binary(source.as_bytes()));
+        let utf16le: Vec<u8> = source.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        assert!(!looks_binary(&utf16le));
+        // Terminal output: escapes and backspaces are text.
+        assert!(!looks_binary(b"\x1b[1mbold\x1b[0m and b\x08bold\n"));
+    }
+
+    /// Formatted JavaScript, and the same code minified onto one line.
+    fn javascript() -> (String, String) {
+        let formatted: String = (0..200)
+            .map(|i| {
+                format!(
+                    "export function handler{i}(event, context) {{\n    const value = event.items[{i}] ?? null;\n    if (!value) {{\n        return context.fail(\"missing item {i}\");\n    }}\n    return context.ok(value * {i});\n}}\n\n"
+                )
+            })
+            .collect();
+        let minified = formatted
+            .lines()
+            .map(str::trim)
+            .collect::<String>()
+            .replace(" = ", "=")
+            .replace(", ", ",");
+        (formatted, minified)
+    }
+
+    #[test]
+    fn minified_versus_formatted() {
+        let (formatted, minified) = javascript();
+        assert!(looks_minified(&minified));
+        assert!(!looks_minified(&formatted));
+        // A formatted file with one huge data line is still formatted.
+        let data = format!("const DATA = \"{}\";\n{formatted}", "A".repeat(20_000));
+        assert!(!looks_minified(&data));
+        // One long line in a small file is just a long line.
+        assert!(!looks_minified(&"x".repeat(1500)));
+
+        let dir = tree(&[("app.min.js", &minified), ("app.js", &formatted)]);
+        let config = Config {
+            skip_minified: true,
+            quiet: true,
+            ..config_for(dir.path())
+        };
+        let mut out = Vec::new();
+        dump(&config, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("## `app.min.js`\n\n(skipped: looks minified)"));
+        assert!(out.contains("export function handler199(event, context) {\n"));
+    }
+
+    #[test]
+    fn ignore_case_excludes_upper_case_names() {
+        let dir = tree(&[("FILE.TXT", "x"), ("docs/Notes.Txt", "x"), ("main.rs", "x")]);
+        let config = Config {
+            exclude: vec!["*.txt".to_string()],
+            ..config_for(dir.path())
+        };
+        assert_eq!(order(&config), ["FILE.TXT", "docs/Notes.Txt", "main.rs"]);
+        let config = Config {
+            ignore_case: true,
+            ..config
+        };
+        assert_eq!(order(&config), ["main.rs"]);
+
+        // --ext matches any case either way.
+        let config = Config {
+            ext: vec!["txt".to_string()],
+            ..config_for(dir.path())
+        };
+        assert_eq!(order(&config), ["FILE.TXT", "docs/Notes.Txt"]);
+    }
+
+    #[test]
+    fn leading_slash_anchors_at_the_root() {
+        let dir = tree(&[
+            ("Cargo.toml", "x"),
+            ("crates/foo/Cargo.toml", "x"),
+            ("src/main.rs", "x"),
+        ]);
+        let excluding = |pattern: &str| Config {
+            exclude: vec![pattern.to_string()],
+            ..config_for(dir.path())
+        };
+        assert_eq!(
+            order(&excluding("/Cargo.toml")),
+            ["crates/foo/Cargo.toml", "src/main.rs"]
+        );
+        assert_eq!(order(&excluding("Cargo.toml")), ["src/main.rs"]);
+
+        // The same inside an archive, matched before unpacking.
+        let config = excluding("/Cargo.toml");
+        let wanted = archive_filter(&config).unwrap();
+        assert!(!wanted(Path::new("Cargo.toml")));
+        assert!(wanted(Path::new("crates/foo/Cargo.toml")));
+
+        let config = Config {
+            include: vec!["/Cargo.toml".to_string()],
+            ..excluding("*.toml")
+        };
+        assert_eq!(order(&config), ["Cargo.toml", "src/main.rs"]);
+    }
+
+    #[test]
+    fn include_beats_default_excludes() {
+        let dir = tree(&[
+            ("dist/manifest.json", "{}"),
+            ("dist/app.js", "x"),
+            ("target/release/build.log", "x"),
+            ("node_modules/dep/package.json", "{}"),
+            ("src/main.rs", "x"),
+        ]);
+        assert_eq!(order(&config_for(dir.path())), ["src/main.rs"]);
+        let config = Config {
+            include: vec![
+                "**/dist/manifest.json".to_string(),
+                "target/release/*.log".to_string(),
+            ],
+            ..config_for(dir.path())
+        };
+        assert_eq!(
+            order(&config),
+            [
+                "dist/manifest.json",
+                "src/main.rs",
+                "target/release/build.log"
+            ]
+        );
+        let config = Config {
+            no_default_excludes: true,
+            ..config_for(dir.path())
+        };
+        assert_eq!(order(&config).len(), 5);
+    }
+
+    #[test]
+    fn token_counts_round_into_the_next_unit() {
+        let cases = [
+            (999, "~999"),
+            (1_000, "~1.0k"),
+            (1_234, "~1.2k"),
+            (999_949, "~999.9k"),
+            (999_950, "~1.0M"),
+            (1_250_000, "~1.3M"),
+            (12_345_678, "~12.3M"),
+        ];
+        for (tokens, expected) in cases {
+            assert_eq!(format_tokens(tokens), expected, "{tokens}");
+        }
+    }
+
+    #[cfg(feature = "tokenizer")]
+    #[test]
+    fn document_tokens_use_the_tokenizer() {
+        let line = "fn main() { println!(\"日本語のテキスト\"); }\n";
+        let mut writer = CountingWriter::new(
+            Vec::new(),
+            &Config {
+                tokenizer: Some(Tokenizer::Cl100kBase),
+                ..Config::default()
+            },
+        );
+        for _ in 0..100 {
+            // Split mid-line and mid-character, as a buffered writer may.
+            let (a, b) = line.as_bytes().split_at(23);
+            writer.write_all(a).unwrap();
+            writer.write_all(b).unwrap();
+        }
+        let per_line = tokens::count(line, Tokenizer::Cl100kBase);
+        assert_eq!(writer.document_tokens(), 100 * per_line);
+        assert_ne!(per_line, line.chars().count().div_ceil(4));
+    }
+}
@@ -0,0 +1,93 @@
+//! `--locations-from`: dump only the neighbourhoods of `path:line` anchors
+//! taken from tool output (compiler errors, test failures, grep hits).
+//!
+//! ```text
+//!   40 | fn parse(input: &str) {
+//! > 41 |     let n = input.parse().unwrap();
+//!   42 | }
+//! ...
+//! ```
+//!
+//! Anchor lines are marked with `>`; windows that overlap or touch are
+//! merged, and gaps between windows are shown as `...`.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+/// First `path:line` on a line, allowing a trailing `:col` and whatever
+/// message follows (`src/lib.rs:12:5: error[E0308]`, ` --> src/lib.rs:12:5`).
+static LOCATION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"([^\s:]+):(\d+)").expect("location pattern compiles"));
+
+/// Anchor lines (1-based, sorted, deduplicated) per path relative to the
+/// root.
+pub type Locations = BTreeMap<PathBuf, Vec<usize>>;
+
+/// Read anchors from `path`. Lines without a `path:line` are ignored, so
+/// raw tool output can be fed in as-is. `rel` turns a path as written into
+/// one relative to the root.
+pub fn load(path: &Path, rel: impl Fn(&Path) -> PathBuf) -> io::Result<Locations> {
+    let src = std::fs::read_to_string(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+    let mut locations = Locations::new();
+    for line in src.lines() {
+        let Some(caps) = LOCATION.captures(line) else {
+            continue;
+        };
+        let Ok(line_no) = caps[2].parse::<usize>() else {
+            continue;
+        };
+        if line_no == 0 {
+            continue;
+        }
+        let file = Path::new(&caps[1]);
+        let file = file.strip_prefix(".").unwrap_or(file);
+        locations.entry(rel(file)).or_default().push(line_no);
+    }
+    for lines in locations.values_mut() {
+        lines.sort_unstable();
+        lines.dedup();
+    }
+    Ok(locations)
+}
+
+/// The excerpt of `text` showing `window` lines either side of each anchor
+/// in `anchors` (sorted). Anchors past the end of the file are dropped.
+pub fn excerpt(text: &str, anchors: &[usize], window: usize) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &anchor in anchors.iter().filter(|&&a| a <= lines.len()) {
+        let start = anchor.saturating_sub(window).max(1);
+        let end = (anchor + window).min(lines.len());
+        match ranges.last_mut() {
+            Some((_, last_end)) if start <= *last_end + 1 => *last_end = end.max(*last_end),
+            _ => ranges.push((start, end)),
+        }
+    }
+
+    let width = ranges.last().map_or(1, |&(_, end)| end.to_string().len());
+    let mut out = String::new();
+    for (i, &(start, end)) in ranges.iter().enumerate() {
+        if i > 0 {
+            out.push_str("...\n");
+        }
+        for n in start..=end {
+            let mark = if anchors.binary_search(&n).is_ok() {
+                '>'
+            } else {
+                ' '
+            };
+            let line = lines[n - 1];
+            if line.is_empty() {
+                out.push_str(&format!("{mark} {n:>width$} |\n"));
+            } else {
+                out.push_str(&format!("{mark} {n:>width$} | {line}\n"));
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,32 @@
+//! Messages on stderr, by verbosity. Warnings always show; notes (what
+//! was written where) are dropped by `--quiet`; details (each skipped
+//! file, each walk error) need `--verbose`. With `--progress-json`,
+//! warnings are `warning` events and notes and details are left out, so
+//! stderr stays one JSON object per line.
+
+use std::fmt::Display;
+
+use crate::{Config, json};
+
+pub fn warning(config: &Config, message: impl Display) {
+    if config.progress_json {
+        eprintln!(
+            r#"{{"event":"warning","message":{}}}"#,
+            json::string(&message.to_string())
+        );
+    } else {
+        eprintln!("dir2prompt: warning: {message}");
+    }
+}
+
+pub fn note(config: &Config, message: impl Display) {
+    if !config.quiet && !config.progress_json {
+        eprintln!("dir2prompt: {message}");
+    }
+}
+
+pub fn detail(config: &Config, message: impl Display) {
+    if config.verbose && !config.progress_json {
+        eprintln!("dir2prompt: {message}");
+    }
+}
@@ -1,380 +1,105 @@
-use clap::Parser;
-use ignore::WalkBuilder;
-use ignore::overrides::OverrideBuilder;
-use std::fs::File;
+use clap::{CommandFactory, Parser};
+use dir2prompt::{Config, SummaryFormat};
 use std::io::{self, Read};
-use std::path::{Path, PathBuf};
 
 #[derive(Parser, Debug)]
 #[command(
     name = "dir2prompt",
-    about = "Dump a directory as Markdown for LLM prompting (respects .gitignore)."
+    about = "Dump a directory as Markdown for LLM prompting (respects .gitignore).",
+    after_long_help = "Content transforms always run in this order, whatever order the \
+flags are given in: --normalize-eol, --redact, --collapse-blobs, --strip-control-chars, \
+--strip-comments, --only-headers, --trim-trailing, --tabs-to-spaces, --wrap-long-lines, \
+--squeeze-blank."
 )]
 struct Args {
-    /// Root directory to dump
-    #[arg(default_value = ".")]
-    root: PathBuf,
-
-    /// Max bytes to include per file (files are truncated beyond this)
-    #[arg(long, default_value_t = 200_000)]
-    max_bytes: usize,
-
-    /// If set, do NOT respect .gitignore / git excludes / global ignores
-    #[arg(long)]
-    no_gitignore: bool,
-
-    /// If set, exclude hidden files/dirs (dotfiles)
-    #[arg(long)]
-    no_hidden: bool,
-
-    /// If set, include common lockfiles (Cargo.lock, package-lock.json, etc.)
-    #[arg(long)]
-    include_lockfiles: bool,
-
-    /// Additional exclude globs (gitignore-style), may be repeated
-    ///
-    /// Examples:
-    ///   --exclude '**/*.snap'
-    ///   --exclude '**/generated/**'
-    #[arg(long)]
-    exclude: Vec<String>,
-
-    /// Additional include globs (gitignore-style), may be repeated.
-    /// These act as "force include" overrides using `!glob`.
-    ///
-    /// Example:
-    ///   --include '**/Cargo.lock'
-    #[arg(long)]
-    include: Vec<String>,
-
-    /// If set, skip files that are not valid UTF-8 (instead of lossy output)
-    #[arg(long)]
-    strict_utf8: bool,
+    #[command(flatten)]
+    config: Config,
+
+    /// Print a completion script for SHELL to stdout and exit, e.g.
+    /// `dir2prompt --completions zsh > _dir2prompt`
+    #[arg(long, value_name = "SHELL", hide = true)]
+    completions: Option<clap_complete::Shell>,
+
+    /// Serve the text read from stdin on the clipboard until something
+    /// else is copied; what --clipboard starts in the background.
+    #[arg(long, hide = true)]
+    serve_clipboard: bool,
 }
 
-fn main() -> io::Result<()> {
-    let args = Args::parse();
-    let root = normalize_root(&args.root)?;
-
-    let respect_gitignore = !args.no_gitignore;
-
-    let overrides = build_overrides(&root, args.include_lockfiles, &args.exclude, &args.include)
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-
-    let mut walk = WalkBuilder::new(&root);
-    walk.overrides(overrides);
-
-    // Hidden handling: default is to include hidden (dotfiles), unless --no_hidden
-    walk.hidden(args.no_hidden);
-
-    // Respect gitignore & related mechanisms unless --no-gitignore
-    walk.git_ignore(respect_gitignore);
-    walk.git_exclude(respect_gitignore);
-    walk.git_global(respect_gitignore);
-    walk.parents(respect_gitignore);
-
-    // Also respect `.ignore` files (ripgrep style) when honoring ignore rules
-    walk.ignore(respect_gitignore);
-
-    // Don’t follow symlinks by default (safer, avoids cycles)
-    walk.follow_links(false);
-
-    let mut files: Vec<PathBuf> = Vec::new();
-    for result in walk.build() {
-        let entry = match result {
-            Ok(e) => e,
-            Err(err) => {
-                eprintln!("dir2prompt: walk error: {err}");
-                continue;
-            }
-        };
-
-        let ft = match entry.file_type() {
-            Some(t) => t,
-            None => continue,
-        };
-
-        if !ft.is_file() {
-            continue;
-        }
-
-        files.push(entry.into_path());
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("dir2prompt: {err}");
+        std::process::exit(1);
     }
-
-    files.sort();
-
-    println!("# dir2prompt dump");
-    println!();
-    println!("- Root: `{}`", root.display());
-    println!(
-        "- Respect .gitignore: `{}`",
-        if respect_gitignore { "yes" } else { "no" }
-    );
-    println!(
-        "- Hidden files included: `{}`",
-        if args.no_hidden { "no" } else { "yes" }
-    );
-    println!("- Per-file max bytes: `{}`", args.max_bytes);
-    println!();
-    println!("## Included files");
-    for path in &files {
-        let rel = rel_path(&root, path);
-        println!("- `{}`", rel.display());
-    }
-    println!();
-    println!("---");
-    println!();
-
-    let mut printed = 0usize;
-    let mut skipped_binary = 0usize;
-    let mut skipped_utf8 = 0usize;
-
-    for path in &files {
-        let rel = rel_path(&root, path);
-        let lang = language_tag(path);
-
-        match read_file_limited(path, args.max_bytes) {
-            Ok(ReadResult { bytes, truncated }) => {
-                if looks_binary(&bytes) {
-                    skipped_binary += 1;
-                    println!("## `{}`", rel.display());
-                    println!();
-                    println!("(skipped: looks like a binary file)");
-                    println!();
-                    continue;
-                }
-
-                let (text, utf8_note) = bytes_to_text(&bytes, args.strict_utf8);
-                let Some(text) = text else {
-                    skipped_utf8 += 1;
-                    println!("## `{}`", rel.display());
-                    println!();
-                    println!("(skipped: not valid UTF-8)");
-                    println!();
-                    continue;
-                };
-
-                println!("## `{}`", rel.display());
-                println!();
-
-                if truncated {
-                    println!("(truncated to {} bytes)", args.max_bytes);
-                    println!();
-                }
-                if let Some(note) = utf8_note {
-                    println!("({note})");
-                    println!();
-                }
-
-                println!("```{}", lang);
-                print!("{text}");
-                if !text.ends_with('\n') {
-                    println!();
-                }
-                println!("```");
-                println!();
-
-                printed += 1;
-            }
-            Err(err) => {
-                println!("## `{}`", rel.display());
-                println!();
-                println!("(skipped: failed to read file: {err})");
-                println!();
-            }
-        }
-    }
-
-    eprintln!(
-        "dir2prompt: printed {printed} files, skipped binary {skipped_binary}, \
-skipped utf8 {skipped_utf8}"
-    );
-
-    Ok(())
 }
 
-fn normalize_root(root: &Path) -> io::Result<PathBuf> {
-    let root = if root.as_os_str().is_empty() {
-        PathBuf::from(".")
-    } else {
-        root.to_path_buf()
-    };
-    // Canonicalize if possible; fall back to provided path if it fails.
-    match std::fs::canonicalize(&root) {
-        Ok(p) => Ok(p),
-        Err(_) => Ok(root),
+fn run() -> io::Result<()> {
+    let args = dir2prompt::with_config_files(&Args::command(), std::env::args_os().collect())?;
+    let Args {
+        config,
+        completions,
+        serve_clipboard,
+    } = Args::parse_from(args);
+    if let Some(shell) = completions {
+        clap_complete::generate(shell, &mut Args::command(), "dir2prompt", &mut io::stdout());
+        return Ok(());
     }
-}
-
-fn rel_path<'a>(root: &'a Path, path: &'a Path) -> &'a Path {
-    path.strip_prefix(root).unwrap_or(path)
-}
-
-fn build_overrides(
-    root: &Path,
-    include_lockfiles: bool,
-    excludes: &[String],
-    includes: &[String],
-) -> Result<ignore::overrides::Override, String> {
-    let mut ob = OverrideBuilder::new(root);
-
-    // Always skip VCS dirs (even if someone disables gitignore respecting).
-    // (The walker already has behavior around .git, but this makes it explicit.)
-    add_exclude(&mut ob, "**/.git/**")?;
-    add_exclude(&mut ob, "**/.hg/**")?;
-    add_exclude(&mut ob, "**/.svn/**")?;
-
-    // Common virtualenv / cache / build artifacts
-    add_exclude(&mut ob, "**/.venv/**")?;
-    add_exclude(&mut ob, "**/venv/**")?;
-    add_exclude(&mut ob, "**/__pycache__/**")?;
-    add_exclude(&mut ob, "**/.mypy_cache/**")?;
-    add_exclude(&mut ob, "**/.pytest_cache/**")?;
-    add_exclude(&mut ob, "**/.ruff_cache/**")?;
-    add_exclude(&mut ob, "**/.tox/**")?;
-
-    // Common dependency/build output dirs
-    add_exclude(&mut ob, "**/node_modules/**")?;
-    add_exclude(&mut ob, "**/target/**")?;
-    add_exclude(&mut ob, "**/dist/**")?;
-    add_exclude(&mut ob, "**/build/**")?;
-    add_exclude(&mut ob, "**/.next/**")?;
-    add_exclude(&mut ob, "**/.nuxt/**")?;
-    add_exclude(&mut ob, "**/.svelte-kit/**")?;
-
-    // OS/editor noise
-    add_exclude(&mut ob, "**/.DS_Store")?;
-    add_exclude(&mut ob, "**/Thumbs.db")?;
-
-    // “Package files” / lockfiles (skip by default; can be re-enabled)
-    if !include_lockfiles {
-        add_exclude(&mut ob, "**/Cargo.lock")?;
-        add_exclude(&mut ob, "**/package-lock.json")?;
-        add_exclude(&mut ob, "**/yarn.lock")?;
-        add_exclude(&mut ob, "**/pnpm-lock.yaml")?;
-        add_exclude(&mut ob, "**/composer.lock")?;
-        add_exclude(&mut ob, "**/Gemfile.lock")?;
-        add_exclude(&mut ob, "**/poetry.lock")?;
-        add_exclude(&mut ob, "**/Pipfile.lock")?;
-    }
-
-    for ex in excludes {
-        add_exclude(&mut ob, ex)?;
+    if serve_clipboard {
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text)?;
+        return dir2prompt::serve_clipboard(text);
     }
-
-    // Includes are "negated" patterns in override syntax.
-    for inc in includes {
-        let line = if inc.starts_with('!') {
-            inc.clone()
-        } else {
-            format!("!{inc}")
+    if let Some(input) = &config.unbundle {
+        let [target] = config.roots.as_slice() else {
+            return Err(io::Error::other(
+                "--unbundle takes a single target directory",
+            ));
         };
-        add_exclude(&mut ob, &line)?;
+        return dir2prompt::unbundle(input, target);
     }
 
-    ob.build().map_err(|e| e.to_string())
-}
-
-fn add_exclude(ob: &mut OverrideBuilder, pattern: &str) -> Result<(), String> {
-    let p = pattern.trim();
-    let line = if p.starts_with('!') {
-        p.to_string()
-    } else {
-        format!("!{p}")
-    };
-    ob.add(&line)
-        .map_err(|e| format!("bad override '{line}': {e}"))?;
-    Ok(())
-}
-
-fn add_include(ob: &mut OverrideBuilder, pattern: &str) -> Result<(), String> {
-    let p = pattern.trim();
-    let line = p.strip_prefix('!').unwrap_or(p);
-    ob.add(line)
-        .map_err(|e| format!("bad override '{line}': {e}"))?;
-    Ok(())
-}
-
-struct ReadResult {
-    bytes: Vec<u8>,
-    truncated: bool,
-}
-
-fn read_file_limited(path: &Path, max_bytes: usize) -> io::Result<ReadResult> {
-    let f = File::open(path)?;
-    let mut buf = Vec::with_capacity(std::cmp::min(max_bytes, 64 * 1024));
-
-    let mut limited = f.take((max_bytes as u64) + 1);
-    limited.read_to_end(&mut buf)?;
-
-    let truncated = buf.len() > max_bytes;
-    if truncated {
-        buf.truncate(max_bytes);
+    let summary = dir2prompt::run_with_copied(&config, keep_serving)?;
+    match config.summary_format {
+        _ if config.stats_only || config.dry_run || config.tree_only || config.quiet => {}
+        SummaryFormat::Text if !config.progress_json => eprintln!("{}", summary.report(&config)),
+        SummaryFormat::Json => eprintln!("{}", summary.to_json()),
+        _ => {}
     }
-
-    Ok(ReadResult {
-        bytes: buf,
-        truncated,
-    })
-}
-
-fn looks_binary(bytes: &[u8]) -> bool {
-    // Heuristic: if the first chunk contains a NUL byte, treat as binary.
-    let n = std::cmp::min(bytes.len(), 8 * 1024);
-    bytes[..n].iter().any(|&b| b == 0)
+    Ok(())
 }
 
-fn bytes_to_text(bytes: &[u8], strict_utf8: bool) -> (Option<String>, Option<&'static str>) {
-    match std::str::from_utf8(bytes) {
-        Ok(s) => (Some(s.to_string()), None),
-        Err(_) if strict_utf8 => (None, None),
-        Err(_) => (
-            Some(String::from_utf8_lossy(bytes).to_string()),
-            Some("note: contained invalid UTF-8; printed with lossless replacement"),
-        ),
-    }
+/// Keep copied `text` available after this process exits: hand it to a
+/// detached copy of this binary that serves it until it is replaced.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+))]
+fn keep_serving(text: String) -> io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+
+    // In a process group of its own, so Ctrl-C in the terminal doesn't end
+    // the copy.
+    let mut child = Command::new(std::env::current_exe()?)
+        .args(["--no-config", "--serve-clipboard"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(text.as_bytes())
 }
 
-fn language_tag(path: &Path) -> &'static str {
-    let ext = path
-        .extension()
-        .and_then(|s| s.to_str())
-        .unwrap_or("")
-        .to_ascii_lowercase();
-
-    match ext.as_str() {
-        "rs" => "rust",
-        "toml" => "toml",
-        "md" => "markdown",
-        "txt" => "text",
-        "json" => "json",
-        "yml" | "yaml" => "yaml",
-        "js" => "javascript",
-        "ts" => "ts",
-        "jsx" => "jsx",
-        "tsx" => "tsx",
-        "py" => "python",
-        "sh" => "bash",
-        "zsh" => "zsh",
-        "fish" => "fish",
-        "go" => "go",
-        "java" => "java",
-        "kt" | "kts" => "kotlin",
-        "c" => "c",
-        "h" => "c",
-        "cpp" | "cc" | "cxx" => "cpp",
-        "hpp" | "hh" | "hxx" => "cpp",
-        "cs" => "csharp",
-        "swift" => "swift",
-        "rb" => "ruby",
-        "php" => "php",
-        "sql" => "sql",
-        "html" => "html",
-        "css" => "css",
-        "scss" => "scss",
-        "proto" => "proto",
-        "ini" => "ini",
-        "env" => "bash",
-        _ => "text",
-    }
+/// Elsewhere the system keeps copied text itself.
+#[cfg(not(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+)))]
+fn keep_serving(_text: String) -> io::Result<()> {
+    Ok(())
 }
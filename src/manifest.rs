@@ -0,0 +1,40 @@
+//! `--manifest`: every included file with its SHA-256 and size, plus one
+//! checksum over the lot, to tell whether two dumps' inputs are the same
+//! without comparing the dumps.
+//!
+//! ```text
+//! 3b4c...e1f0  1423  src/lib.rs
+//! 9a0d...77c2  212  src/main.rs
+//! checksum 5e8f...a903
+//! ```
+//!
+//! Lines are sorted by path whatever the output order, and nothing in the
+//! manifest depends on when or where it was written, so unchanged inputs
+//! always give the same bytes.
+
+use std::io::{self, Write};
+
+use crate::sha256;
+
+/// One included file: its displayed path, content hash and size.
+pub struct Entry {
+    pub path: String,
+    pub sha256: String,
+    pub bytes: u64,
+}
+
+/// Write `entries` sorted by path, then the checksum line: the SHA-256 of
+/// all the lines before it.
+pub fn write(out: &mut impl Write, mut entries: Vec<Entry>) -> io::Result<()> {
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    let mut body = String::new();
+    for entry in &entries {
+        body.push_str(&format!(
+            "{}  {}  {}\n",
+            entry.sha256, entry.bytes, entry.path
+        ));
+    }
+    let checksum = sha256::hex_digest(body.as_bytes())?;
+    out.write_all(body.as_bytes())?;
+    writeln!(out, "checksum {checksum}")
+}
@@ -0,0 +1,39 @@
+//! `--model`: approximate context windows of common models, for an
+//! advisory warning when a dump won't fit.
+
+/// Context windows in tokens, by model name. Names match by longest
+/// prefix, so dated variants (`gpt-4o-2024-08-06`) find their family.
+const CONTEXT_WINDOWS: &[(&str, usize)] = &[
+    ("gpt-3.5-turbo", 16_385),
+    ("gpt-4", 8_192),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4o", 128_000),
+    ("gpt-4.1", 1_047_576),
+    ("o1", 200_000),
+    ("o3", 200_000),
+    ("o4-mini", 200_000),
+    ("claude-3", 200_000),
+    ("claude-3-5", 200_000),
+    ("claude-3-7", 200_000),
+    ("claude-sonnet-4", 200_000),
+    ("claude-opus-4", 200_000),
+    ("gemini-1.5-flash", 1_048_576),
+    ("gemini-1.5-pro", 2_097_152),
+    ("gemini-2", 1_048_576),
+    ("llama-3", 128_000),
+    ("mistral-large", 128_000),
+];
+
+/// Assumed for names not in the table: what most current models offer.
+pub const FALLBACK_WINDOW: usize = 128_000;
+
+/// The context window for `model` (case-insensitive), or `None` if it
+/// isn't known.
+pub fn context_window(model: &str) -> Option<usize> {
+    let model = model.trim().to_ascii_lowercase();
+    CONTEXT_WINDOWS
+        .iter()
+        .filter(|(name, _)| model.starts_with(name))
+        .max_by_key(|(name, _)| name.len())
+        .map(|&(_, window)| window)
+}
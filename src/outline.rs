@@ -0,0 +1,265 @@
+//! `--only-headers`: cut a file down to its declarations, per language
+//! family, for an overview of a codebase's shape.
+//!
+//! Like `comments`, this is line-based pattern matching, not a parser.
+//! Function bodies become `{ … }` (`…` in Python); type definitions
+//! (structs, enums, interfaces, ...) are kept whole, since their fields
+//! are the shape; `impl`, `trait`, `class` and `mod` bodies are outlined
+//! in turn. Everything else is dropped. Braces are counted without regard
+//! to strings or comments, so run `--strip-comments` first on code that
+//! has braces in either.
+
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+/// Declaration patterns for a brace-delimited language, each matched
+/// against a line with its indentation removed.
+struct Braces {
+    /// Declarations whose bodies are outlined in turn.
+    container: Option<Regex>,
+    /// Declarations kept whole, body included.
+    whole: Regex,
+    /// Functions, whose bodies are replaced.
+    callable: Regex,
+    /// Functions that only count directly inside a container (methods).
+    member: Option<Regex>,
+}
+
+fn re(pattern: &str) -> Regex {
+    Regex::new(pattern).expect("outline pattern compiles")
+}
+
+const RUST_VIS: &str = r"(?:pub(?:\([^)]*\))?\s+)?";
+
+static RUST: LazyLock<Braces> = LazyLock::new(|| Braces {
+    container: Some(re(&format!(
+        r"^{RUST_VIS}(?:unsafe\s+)?(?:impl\b|trait\s|mod\s)"
+    ))),
+    whole: re(&format!(r"^{RUST_VIS}(?:struct|enum|union|type)\s")),
+    callable: re(&format!(
+        r#"^{RUST_VIS}(?:default\s+)?(?:(?:const|async|unsafe|extern\s+"[^"]*")\s+)*fn\s"#
+    )),
+    member: None,
+});
+
+static JS: LazyLock<Braces> = LazyLock::new(|| Braces {
+    container: Some(re(r"^(?:export\s+)?(?:default\s+)?(?:abstract\s+)?class\b")),
+    whole: re(r"^(?:export\s+)?(?:declare\s+)?(?:interface|type|enum|const\s+enum)\s"),
+    callable: re(
+        r"^(?:export\s+)?(?:default\s+)?(?:async\s+)?function\b|^(?:export\s+)?(?:const|let|var)\s+[\w$]+\s*(?::[^=]+)?=\s*(?:async\s+)?(?:\([^)]*\)|[\w$]+)\s*(?::[^=]+)?=>",
+    ),
+    member: Some(re(
+        r"^(?:(?:public|private|protected|static|async|readonly|override|abstract|get|set)\s+)*\*?(?:constructor|[A-Za-z_$][\w$]*)\s*(?:<[^>]*>)?\s*\(",
+    )),
+});
+
+static GO: LazyLock<Braces> = LazyLock::new(|| Braces {
+    container: None,
+    whole: re(r"^type\s"),
+    callable: re(r"^func\s"),
+    member: None,
+});
+
+/// Control-flow keywords that look like method calls to `member`.
+const KEYWORDS: &[&str] = &[
+    "if", "for", "while", "switch", "catch", "return", "with", "super", "function",
+];
+
+/// `text` outlined, or `None` when `lang` has no declaration patterns.
+pub fn outline(text: &str, lang: &str) -> Option<String> {
+    match lang {
+        "rust" => Some(outline_braces(text, &RUST)),
+        "javascript" | "ts" | "jsx" | "tsx" => Some(outline_braces(text, &JS)),
+        "go" => Some(outline_braces(text, &GO)),
+        "python" => Some(outline_python(text)),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Container,
+    Whole,
+    Callable,
+}
+
+/// What kind of declaration `trimmed` starts, if any. Methods only count
+/// directly `in_container`.
+fn declaration(trimmed: &str, in_container: bool, syntax: &Braces) -> Option<Kind> {
+    if syntax.callable.is_match(trimmed) {
+        Some(Kind::Callable)
+    } else if syntax.whole.is_match(trimmed) {
+        Some(Kind::Whole)
+    } else if syntax
+        .container
+        .as_ref()
+        .is_some_and(|c| c.is_match(trimmed))
+    {
+        Some(Kind::Container)
+    } else if in_container
+        && let Some(member) = &syntax.member
+        && member.is_match(trimmed)
+        && !KEYWORDS.iter().any(|k| {
+            trimmed
+                .strip_prefix(k)
+                .is_some_and(|rest| !rest.starts_with(|c: char| c == '_' || c.is_alphanumeric()))
+        })
+    {
+        Some(Kind::Callable)
+    } else {
+        None
+    }
+}
+
+fn outline_braces(text: &str, syntax: &Braces) -> String {
+    let mut out = String::new();
+    let mut depth = 0usize;
+    // Depths of the open containers' bodies.
+    let mut containers: Vec<usize> = Vec::new();
+    // Inside a body being skipped (or, for `Whole`, copied) until the
+    // depth is back to the one given.
+    let mut body: Option<(usize, bool)> = None;
+    // A declaration whose signature continues on the next line, with its
+    // open bracket count so far.
+    let mut pending: Option<(Kind, i64)> = None;
+
+    for line in text.lines() {
+        let opens = line.matches('{').count();
+        let closes = line.matches('}').count();
+        let after = (depth + opens).saturating_sub(closes);
+
+        if let Some((until, keep)) = body {
+            if keep {
+                out.push_str(line);
+                out.push('\n');
+            }
+            depth = after;
+            if depth <= until {
+                body = None;
+            }
+            continue;
+        }
+
+        let trimmed = line.trim_start();
+        let in_container = containers.last() == Some(&depth);
+        let (kind, open) = match pending.take() {
+            Some((kind, open)) => (Some(kind), open),
+            None => (declaration(trimmed, in_container, syntax), 0),
+        };
+
+        let Some(kind) = kind else {
+            // The closing brace of a container is kept.
+            if containers.last().is_some_and(|&open| after < open) {
+                containers.pop();
+                if trimmed.starts_with('}') {
+                    out.push_str(line);
+                    out.push('\n');
+                }
+            }
+            depth = after;
+            continue;
+        };
+
+        let Some(brace) = line.find('{') else {
+            out.push_str(line);
+            out.push('\n');
+            let open = open + brackets(trimmed);
+            let end = trimmed.trim_end();
+            // `fn f();`, `mod m;`, `type T = u32`, `const f = x => x + 1`
+            // end here; `fn f(\n`, `impl T\nwhere ...` go on.
+            let continues = open > 0
+                || end.ends_with(',')
+                || end.ends_with("where")
+                || kind != Kind::Whole && !end.ends_with(';') && !end.contains("=>");
+            if continues {
+                pending = Some((kind, open));
+            }
+            continue;
+        };
+        match kind {
+            Kind::Callable => {
+                out.push_str(line[..brace].trim_end());
+                out.push_str(" { … }\n");
+                if after > depth {
+                    body = Some((depth, false));
+                }
+            }
+            Kind::Whole => {
+                out.push_str(line);
+                out.push('\n');
+                if after > depth {
+                    body = Some((depth, true));
+                }
+            }
+            Kind::Container => {
+                out.push_str(line);
+                out.push('\n');
+                if after > depth {
+                    containers.push(after);
+                }
+            }
+        }
+        depth = after;
+    }
+    out
+}
+
+static PY_DEF: LazyLock<Regex> = LazyLock::new(|| re(r"^(?:async\s+)?def\s"));
+static PY_CLASS: LazyLock<Regex> = LazyLock::new(|| re(r"^class\s"));
+/// A line ending a block header: `:` then at most a comment.
+static PY_HEADER_END: LazyLock<Regex> = LazyLock::new(|| re(r":\s*(?:#.*)?$"));
+
+fn outline_python(text: &str) -> String {
+    let mut out = String::new();
+    // Inside a function body: lines indented deeper than this are dropped.
+    let mut body_indent: Option<usize> = None;
+    // A `def` signature continuing on the next line: its indentation and
+    // open bracket count.
+    let mut signature: Option<(usize, i64)> = None;
+
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+        if let Some(body) = body_indent {
+            if trimmed.is_empty() || indent > body {
+                continue;
+            }
+            body_indent = None;
+        }
+
+        let (def_indent, open) = match signature.take() {
+            Some(sig) => sig,
+            None if trimmed.starts_with('@') || PY_CLASS.is_match(trimmed) => {
+                out.push_str(line);
+                out.push('\n');
+                continue;
+            }
+            None if PY_DEF.is_match(trimmed) => (indent, 0),
+            None => continue,
+        };
+        out.push_str(line);
+        out.push('\n');
+        let open = open + brackets(trimmed);
+        if open > 0 {
+            signature = Some((def_indent, open));
+        } else if PY_HEADER_END.is_match(trimmed) {
+            out.push_str(&" ".repeat(def_indent + 4));
+            out.push_str("…\n");
+            body_indent = Some(def_indent);
+        }
+        // Otherwise a one-line `def f(): return x`.
+    }
+    out
+}
+
+/// Opening minus closing brackets in `line`.
+fn brackets(line: &str) -> i64 {
+    line.chars()
+        .map(|c| match c {
+            '(' | '[' | '{' => 1,
+            ')' | ']' | '}' => -1,
+            _ => 0,
+        })
+        .sum()
+}
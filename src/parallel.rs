@@ -0,0 +1,104 @@
+//! Concurrent file reading for `--jobs`, and the `--max-open-files` cap.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+/// `f` over `items` on up to `jobs` threads. Results come back in input
+/// order, however the work was scheduled.
+pub fn map<I: Sync, T: Send>(items: &[I], jobs: usize, f: impl Fn(&I) -> T + Sync) -> Vec<T> {
+    let jobs = jobs.clamp(1, items.len().max(1));
+    if jobs == 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, T)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(i) else {
+                            break done;
+                        };
+                        done.push((i, f(item)));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().expect("reader thread panicked"))
+            .collect()
+    });
+    results.sort_unstable_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, r)| r).collect()
+}
+
+/// The `--max-open-files` cap for one run: a counting semaphore of the
+/// files that may be open for reading at once.
+pub struct OpenFiles {
+    free: Mutex<usize>,
+    released: Condvar,
+}
+
+/// Held while a file is open; gives its slot back on drop.
+pub struct Permit<'a>(&'a OpenFiles);
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        *self.0.free.lock().expect("semaphore poisoned") += 1;
+        self.0.released.notify_one();
+    }
+}
+
+impl OpenFiles {
+    /// Allow at most `n` files open for reading at once.
+    pub fn new(n: usize) -> OpenFiles {
+        OpenFiles {
+            free: Mutex::new(n.max(1)),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Wait for a free open-file slot.
+    pub fn permit(&self) -> Permit<'_> {
+        let mut free = self.free.lock().expect("semaphore poisoned");
+        while *free == 0 {
+            free = self.released.wait(free).expect("semaphore poisoned");
+        }
+        *free -= 1;
+        Permit(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    /// The most permits `open_files` had out at once over a busy `map`.
+    fn most_held(open_files: &OpenFiles) -> usize {
+        let (held, most) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        map(&[(); 32], 8, |_| {
+            let _permit = open_files.permit();
+            let now = held.fetch_add(1, Ordering::SeqCst) + 1;
+            most.fetch_max(now, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(2));
+            held.fetch_sub(1, Ordering::SeqCst);
+        });
+        most.into_inner()
+    }
+
+    #[test]
+    fn each_cap_holds_on_its_own() {
+        // Caps are per run: a later, looser one doesn't replace the first.
+        let (tight, loose) = (OpenFiles::new(2), OpenFiles::new(6));
+        assert!(most_held(&loose) <= 6);
+        assert!(most_held(&tight) <= 2);
+        assert_eq!(most_held(&OpenFiles::new(1)), 1);
+    }
+}
@@ -0,0 +1,103 @@
+//! Secret detection shared by `--fail-on-secrets` and `--redact`.
+
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+/// One detected secret; `line` is 1-based.
+pub struct Finding {
+    pub kind: &'static str,
+    pub line: usize,
+}
+
+/// `(kind, pattern)`. Capture group 1, when present, is the sensitive part;
+/// otherwise the whole match is. Deliberately conservative: over-matching
+/// is preferable to leaking a key.
+static PATTERNS: LazyLock<Vec<(&'static str, Regex)>> = LazyLock::new(|| {
+    [
+        (
+            "private key",
+            r"(?s)-----BEGIN [A-Z ]*PRIVATE KEY-----.*?-----END [A-Z ]*PRIVATE KEY-----",
+        ),
+        ("AWS access key", r"\b(AKIA[0-9A-Z]{16})\b"),
+        ("GitHub token", r"\b(gh[pousr]_[A-Za-z0-9]{36,})\b"),
+        ("Slack token", r"\b(xox[abprs]-[A-Za-z0-9-]{10,})\b"),
+        (
+            "credential assignment",
+            r#"(?i)[\w.-]*(?:api[_-]?key|secret|token|passwd|password|access[_-]?key)[\w.-]*["']?\s*[:=]\s*["']([^"'\s]{8,})["']"#,
+        ),
+        ("base64 secret", r"([A-Za-z0-9+/]{40,}={0,2})"),
+    ]
+    .into_iter()
+    .map(|(kind, re)| (kind, Regex::new(re).expect("secret pattern compiles")))
+    .collect()
+});
+
+/// Replaces each secret in `--redact` output.
+pub const REDACTED: &str = "«REDACTED»";
+
+/// Byte ranges of secrets in `text` with their kinds, sorted by position,
+/// without overlaps.
+fn spans(text: &str) -> Vec<(usize, usize, &'static str)> {
+    let mut spans: Vec<(usize, usize, &'static str)> = Vec::new();
+    for (kind, re) in PATTERNS.iter() {
+        for caps in re.captures_iter(text) {
+            let m = caps.get(1).unwrap_or_else(|| caps.get(0).expect("match"));
+            if *kind == "base64 secret" && !looks_random(m.as_str()) {
+                continue;
+            }
+            // Already redacted (e.g. `api_key = "«REDACTED»"`).
+            if m.as_str() == REDACTED {
+                continue;
+            }
+            spans.push((m.start(), m.end(), kind));
+        }
+    }
+    spans.sort();
+
+    let mut last_end = 0;
+    spans.retain(|&(start, end, _)| {
+        let keep = start >= last_end;
+        if keep {
+            last_end = end;
+        }
+        keep
+    });
+    spans
+}
+
+/// Find secrets in `text`, sorted by position, without overlaps.
+pub fn scan(text: &str) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut line = 1;
+    let mut counted = 0;
+    for (start, _, kind) in spans(text) {
+        line += text[counted..start].matches('\n').count();
+        counted = start;
+        findings.push(Finding { kind, line });
+    }
+    findings
+}
+
+/// `text` with the sensitive part of every secret replaced by
+/// [`REDACTED`], and how many were replaced.
+pub fn redact(text: &str) -> (String, usize) {
+    let spans = spans(text);
+    let mut out = String::with_capacity(text.len());
+    let mut copied = 0;
+    for &(start, end, _) in &spans {
+        out.push_str(&text[copied..start]);
+        out.push_str(REDACTED);
+        copied = end;
+    }
+    out.push_str(&text[copied..]);
+    (out, spans.len())
+}
+
+/// Mixed-case-plus-digit strings look like keys; hex digests, long
+/// identifiers and words don't.
+fn looks_random(s: &str) -> bool {
+    s.bytes().any(|b| b.is_ascii_uppercase())
+        && s.bytes().any(|b| b.is_ascii_lowercase())
+        && s.bytes().any(|b| b.is_ascii_digit())
+}
@@ -0,0 +1,372 @@
+//! Defaults from config files: `$XDG_CONFIG_HOME/dir2prompt/config.toml`
+//! for every run, then `.dir2prompt.toml` in each root being dumped.
+//!
+//! Keys are the long flag names, with `_` or `-`:
+//!
+//! ```toml
+//! max_bytes = 50000
+//! sort = "size"
+//! exclude = ["**/*.snap", "**/generated/**"]
+//! no_hidden = true
+//! ```
+//!
+//! Settings are turned back into flags and put in front of the command
+//! line, so they go through the same parsing and validation as typed
+//! flags. A flag given on the command line wins over the files (so does
+//! one that conflicts with a setting, like `-v` with `quiet = true`), and a
+//! root's file wins over the global one (and over earlier roots' files).
+//! List settings (`exclude`, `include`, `ext`, ...) are layered instead:
+//! global values, then the roots', then the command line's
+//! (`--exclude-reset` keeps only the command line's excludes and
+//! `--exclude-from` files).
+//!
+//! A root's file comes with the code being dumped, so it may only set what
+//! shapes the dump's content ([`ROOT_SETTABLE`]): limits, filters,
+//! transforms and layout. Anything that writes files, runs git, reads
+//! other files or reaches the clipboard (`output`, `cache`, `diff_stat`,
+//! `header`, ...) is an error there and can only come from the global file
+//! or the command line.
+
+use std::ffi::OsString;
+use std::io;
+use std::path::PathBuf;
+
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+/// Settings that can't come from a file.
+const NOT_SETTABLE: &[&str] = &["roots", "no_config", "completions"];
+
+/// Settings a root's `.dir2prompt.toml` may set.
+const ROOT_SETTABLE: &[&str] = &[
+    "max_bytes",
+    "max_bytes_ext",
+    "soft_max_bytes",
+    "truncate",
+    "max_lines",
+    "max_file_size",
+    "no_gitignore",
+    "no_hidden",
+    "include_lockfiles",
+    "include_generated",
+    "sniff_lockfiles",
+    "include_empty",
+    "skip_minified",
+    "exclude",
+    "ext",
+    "lang_map",
+    "include",
+    "no_default_excludes",
+    "ignore_case",
+    "exclude_content",
+    "encoding",
+    "normalize_eol",
+    "redact",
+    "collapse_blobs",
+    "strip_control_chars",
+    "strip_comments",
+    "only_headers",
+    "readmes_first",
+    "elide_path",
+    "manifests_first",
+    "sort",
+    "reverse",
+    "trim_trailing",
+    "tabs_to_spaces",
+    "squeeze_blank",
+    "wrap_long_lines",
+    "depth",
+    "since",
+    "content_max_depth",
+    "hide_skipped",
+    "no_inline_skips",
+    "collapsible",
+    "dedup",
+    "note_symlinks",
+    "schemas_first",
+    "priority",
+    "tree",
+    "group_by_dir",
+    "line_numbers",
+    "heading_stats",
+    "show_mtime",
+    "show_hash",
+    "listing_stats",
+    "toc",
+];
+
+/// Where a config file comes from, which decides what it may set.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Origin {
+    /// The user's own `config.toml`.
+    Global,
+    /// A `.dir2prompt.toml` in a root being dumped.
+    Root,
+}
+
+/// `args` (program name first) with the config files' settings inserted
+/// after the program name. Returns `args` unchanged if they don't parse
+/// (the real parse reports the error) or with `--no-config`.
+pub fn with_config_files(command: &Command, args: Vec<OsString>) -> io::Result<Vec<OsString>> {
+    let Ok(matches) = command.clone().try_get_matches_from(&args) else {
+        return Ok(args);
+    };
+    if matches.get_flag("no_config") {
+        return Ok(args);
+    }
+    let roots: Vec<PathBuf> = matches
+        .get_many::<PathBuf>("roots")
+        .map_or_else(|| vec![PathBuf::from(".")], |r| r.cloned().collect());
+
+    let mut files: Vec<(PathBuf, Origin)> = crate::config_dir()
+        .map(|dir| (dir.join("config.toml"), Origin::Global))
+        .into_iter()
+        .collect();
+    files.extend(
+        roots
+            .iter()
+            .map(|root| (root.join(".dir2prompt.toml"), Origin::Root)),
+    );
+    merge_files(command, &matches, args, &files)
+}
+
+/// `args` with the settings from those of `files` that exist, lowest
+/// precedence first. `matches` is `args` parsed on its own.
+fn merge_files(
+    command: &Command,
+    matches: &ArgMatches,
+    args: Vec<OsString>,
+    files: &[(PathBuf, Origin)],
+) -> io::Result<Vec<OsString>> {
+    // A setting gives way to command-line flags it conflicts with (say
+    // `quiet = true` and `-v`), as it does to its own flag.
+    let on_command_line: Vec<&Arg> = command
+        .get_arguments()
+        .filter(|a| matches.value_source(a.get_id().as_str()) == Some(ValueSource::CommandLine))
+        .collect();
+    let overridden = |arg: &Arg| {
+        on_command_line.iter().any(|cli| {
+            command
+                .get_arg_conflicts_with(arg)
+                .iter()
+                .any(|c| c.get_id() == cli.get_id())
+                || command
+                    .get_arg_conflicts_with(cli)
+                    .iter()
+                    .any(|c| c.get_id() == arg.get_id())
+        })
+    };
+
+    // Scalars: the last file to set one wins. Lists: every file appends.
+    let mut scalars: Vec<(String, Vec<OsString>)> = Vec::new();
+    let mut lists: Vec<OsString> = Vec::new();
+    for (path, origin) in files.iter().filter(|(p, _)| p.is_file()) {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+        let table: toml::Table = text
+            .parse()
+            .map_err(|e| io::Error::other(format!("{}: {e}", path.display())))?;
+        for (key, value) in table {
+            let id = key.replace('-', "_");
+            let flags = to_flags(command, &id, &value, *origin)
+                .map_err(|e| io::Error::other(format!("{}: `{key}`: {e}", path.display())))?;
+            let arg = command
+                .get_arguments()
+                .find(|a| a.get_id() == id.as_str())
+                .expect("to_flags found it");
+            if overridden(arg) {
+                continue;
+            }
+            if matches!(arg.get_action(), ArgAction::Append) {
+                if (id == "exclude" || id == "exclude_from") && matches.get_flag("exclude_reset") {
+                    continue;
+                }
+                lists.extend(flags);
+            } else if matches.value_source(&id) != Some(ValueSource::CommandLine) {
+                scalars.retain(|(k, _)| *k != id);
+                scalars.push((id, flags));
+            }
+        }
+    }
+
+    let mut merged = Vec::with_capacity(args.len() + lists.len());
+    let mut args = args.into_iter();
+    merged.extend(args.next());
+    merged.extend(scalars.into_iter().flat_map(|(_, flags)| flags));
+    merged.extend(lists);
+    merged.extend(args);
+    Ok(merged)
+}
+
+/// The flags that set argument `id` to `value` from a file of `origin`.
+fn to_flags(
+    command: &Command,
+    id: &str,
+    value: &toml::Value,
+    origin: Origin,
+) -> Result<Vec<OsString>, String> {
+    if NOT_SETTABLE.contains(&id) {
+        return Err("can only be given on the command line".to_string());
+    }
+    let Some(arg) = command.get_arguments().find(|a| a.get_id() == id) else {
+        return Err("unknown setting".to_string());
+    };
+    if origin == Origin::Root && !ROOT_SETTABLE.contains(&id) {
+        return Err(
+            "can't be set in a root's .dir2prompt.toml, only in the global config or on \
+             the command line"
+                .to_string(),
+        );
+    }
+    let Some(long) = arg.get_long() else {
+        return Err("unknown setting".to_string());
+    };
+    let flag = format!("--{long}");
+    let takes_value = !matches!(arg.get_action(), ArgAction::SetTrue);
+    let optional_value = arg.get_num_args().is_some_and(|n| n.min_values() == 0);
+
+    let scalar = |v: &toml::Value| -> Result<OsString, String> {
+        match v {
+            toml::Value::String(s) => Ok(format!("{flag}={s}").into()),
+            toml::Value::Integer(n) => Ok(format!("{flag}={n}").into()),
+            toml::Value::Float(n) => Ok(format!("{flag}={n}").into()),
+            _ => Err(format!(
+                "expected a string or number, found {}",
+                v.type_str()
+            )),
+        }
+    };
+    match value {
+        // A bare `--git-diff` would take the next argument as its value, so
+        // the value it means alone (`HEAD`) is spelled out.
+        toml::Value::Boolean(true) if optional_value => {
+            let alone = command
+                .clone()
+                .try_get_matches_from([command.get_name(), &flag])
+                .ok()
+                .and_then(|m| Some(m.get_raw(id)?.next()?.to_string_lossy().into_owned()));
+            Ok(vec![match alone {
+                Some(value) => format!("{flag}={value}").into(),
+                None => flag.into(),
+            }])
+        }
+        toml::Value::Boolean(true) if !takes_value => Ok(vec![flag.into()]),
+        toml::Value::Boolean(false) if !takes_value || optional_value => Ok(Vec::new()),
+        toml::Value::Boolean(_) => Err("expected a value, not a boolean".to_string()),
+        _ if !takes_value => Err(format!("expected a boolean, found {}", value.type_str())),
+        toml::Value::Array(items) if matches!(arg.get_action(), ArgAction::Append) => {
+            items.iter().map(scalar).collect()
+        }
+        toml::Value::Array(_) => Err("expected a single value, not a list".to_string()),
+        v => scalar(v).map(|f| vec![f]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{Args, FromArgMatches};
+
+    use super::*;
+    use crate::Config;
+
+    fn command() -> Command {
+        Config::augment_args(Command::new("dir2prompt"))
+    }
+
+    /// Parse `args` (without the program name) with `files` (each a
+    /// global config file's text, lowest precedence first) as config files.
+    fn parse(files: &[&str], args: &[&str]) -> Config {
+        let files: Vec<_> = files.iter().map(|&text| (text, Origin::Global)).collect();
+        try_parse(&files, args).unwrap()
+    }
+
+    fn try_parse(files: &[(&str, Origin)], args: &[&str]) -> io::Result<Config> {
+        let dir = tempfile::tempdir().unwrap();
+        let paths: Vec<(PathBuf, Origin)> = files
+            .iter()
+            .enumerate()
+            .map(|(i, (text, origin))| {
+                let path = dir.path().join(format!("{i}.toml"));
+                std::fs::write(&path, text).unwrap();
+                (path, *origin)
+            })
+            .collect();
+        let args: Vec<OsString> = std::iter::once("dir2prompt")
+            .chain(args.iter().copied())
+            .map(OsString::from)
+            .collect();
+        let command = command();
+        let matches = command.clone().try_get_matches_from(&args).unwrap();
+        let merged = merge_files(&command, &matches, args, &paths)?;
+        let matches = command.try_get_matches_from(merged).unwrap();
+        Ok(Config::from_arg_matches(&matches).unwrap())
+    }
+
+    #[test]
+    fn root_files_only_shape_content() {
+        let root = |text| try_parse(&[(text, Origin::Root)], &[]);
+        for text in [
+            "output = \"/tmp/out.md\"\n",
+            "cache = \"/tmp/cache\"\n",
+            "diff_stat = true\n",
+            "header = \"@/etc/passwd\"\n",
+            "clipboard = true\n",
+        ] {
+            let err = root(text).expect_err(text).to_string();
+            assert!(err.contains("can't be set in a root's"), "{err}");
+        }
+        let config = root("max_bytes = 10\nexclude = [\"*.md\"]\n").unwrap();
+        assert_eq!(
+            (config.max_bytes, config.exclude),
+            (10, vec!["*.md".into()])
+        );
+
+        let config = try_parse(&[("output = \"out.md\"\n", Origin::Global)], &[]).unwrap();
+        assert_eq!(config.output, Some(PathBuf::from("out.md")));
+    }
+
+    #[test]
+    fn conflicting_command_line_flags_win() {
+        let config = parse(
+            &["max_lines = 5\nquiet = true\n"],
+            &["--truncate", "head-tail", "-v"],
+        );
+        assert_eq!(config.max_lines, None);
+        assert!(!config.quiet);
+        assert!(config.verbose);
+    }
+
+    #[test]
+    fn optional_value_setting_leaves_roots_alone() {
+        let config = parse(&["diff_stat = true\n"], &["cfg/proj"]);
+        assert_eq!(config.diff_stat.as_deref(), Some("HEAD"));
+        assert_eq!(config.roots, [PathBuf::from("cfg/proj")]);
+    }
+
+    #[test]
+    fn lists_append_unless_reset() {
+        fn paths(p: &[PathBuf]) -> Vec<String> {
+            p.iter().map(|p| p.display().to_string()).collect()
+        }
+        type Get = fn(&Config) -> Vec<String>;
+        let fields: [(&str, Get); 6] = [
+            ("exclude", |c| c.exclude.clone()),
+            ("exclude-from", |c| paths(&c.exclude_from)),
+            ("include", |c| c.include.clone()),
+            ("include-from", |c| paths(&c.include_from)),
+            ("ext", |c| c.ext.clone()),
+            ("priority", |c| c.priority.clone()),
+        ];
+        for (key, get) in fields {
+            let file = format!("{key} = [\"file\"]\n");
+            let flag = format!("--{key}=cli");
+            let config = parse(&[&file, &file], &[&flag]);
+            assert_eq!(get(&config), ["file", "file", "cli"], "{key}");
+
+            let config = parse(&[&file], &[&flag, "--exclude-reset"]);
+            let reset = key.starts_with("exclude");
+            let expected: &[&str] = if reset { &["cli"] } else { &["file", "cli"] };
+            assert_eq!(get(&config), expected, "{key} with --exclude-reset");
+        }
+    }
+}
@@ -0,0 +1,212 @@
+//! `--split-bytes`: spread a Markdown dump over numbered chunk files
+//! (`prefix.001.md`, `prefix.002.md`, ...) plus a `prefix.index.json`
+//! saying which chunk holds which file. With `--gzip` each chunk is
+//! compressed on its own (`prefix.001.md.gz`); the index isn't, and its
+//! offsets are into the uncompressed chunks.
+//!
+//! The dump is rendered as usual into a shared buffer and cut into pieces
+//! at file boundaries; pieces are then packed into chunks in order, so a
+//! file's block is never split. A piece bigger than the chunk size gets a
+//! chunk of its own.
+
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+use crate::json;
+
+/// A `Write` sink that keeps everything in memory until `take`n.
+#[derive(Clone, Default)]
+pub struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+impl Buffer {
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut self.0.borrow_mut())
+    }
+}
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A rendered run of the dump; `path` is set for a file's block and unset
+/// for the document header and footer.
+struct Piece {
+    path: Option<String>,
+    bytes: Vec<u8>,
+}
+
+pub struct Splitter {
+    buffer: Buffer,
+    pieces: Vec<Piece>,
+    gzip: bool,
+}
+
+impl Splitter {
+    pub fn new(gzip: bool) -> Self {
+        Splitter {
+            buffer: Buffer::default(),
+            pieces: Vec::new(),
+            gzip,
+        }
+    }
+
+    /// The sink to render into.
+    pub fn sink(&self) -> Buffer {
+        self.buffer.clone()
+    }
+
+    /// End the current piece. Call after flushing the writer on top of the
+    /// sink; nothing happens if nothing was written since the last cut.
+    pub fn cut(&mut self, path: Option<&Path>) {
+        let bytes = self.buffer.take();
+        if !bytes.is_empty() {
+            self.pieces.push(Piece {
+                path: path.map(|p| p.display().to_string()),
+                bytes,
+            });
+        }
+    }
+
+    /// Pack the pieces into chunks of at most `max_bytes` (including the
+    /// part header) and write them and the index next to `prefix`.
+    /// Returns the number of chunks written and their uncompressed size.
+    pub fn write(mut self, prefix: &Path, max_bytes: usize) -> io::Result<(usize, usize)> {
+        self.cut(None);
+        let pieces = self.pieces;
+
+        // Size the part header for the worst case so packing can't be
+        // pushed over the limit by the final chunk count.
+        let reserve = part_header(pieces.len(), pieces.len()).len();
+        let mut chunks: Vec<Vec<&Piece>> = Vec::new();
+        let mut size = 0usize;
+        for piece in &pieces {
+            match chunks.last_mut() {
+                Some(chunk) if size + piece.bytes.len() <= max_bytes => chunk.push(piece),
+                _ => {
+                    chunks.push(vec![piece]);
+                    size = reserve;
+                }
+            }
+            size += piece.bytes.len();
+        }
+
+        let total = chunks.len();
+        let mut index = vec![format!(r#"{{"chunk_count":{total},"chunks":["#)];
+        let mut written = 0usize;
+        for (i, chunk) in chunks.iter().enumerate() {
+            let path = chunk_path(prefix, i + 1, self.gzip);
+            let mut out = part_header(i + 1, total).into_bytes();
+            let mut files = Vec::new();
+            for piece in chunk {
+                if let Some(file) = &piece.path {
+                    files.push(format!(
+                        r#"{{"path":{},"start":{},"end":{}}}"#,
+                        json::string(file),
+                        out.len(),
+                        out.len() + piece.bytes.len()
+                    ));
+                }
+                out.extend_from_slice(&piece.bytes);
+            }
+            let bytes = out.len();
+            written += bytes;
+            if self.gzip {
+                out = gzip(&out)?;
+            }
+            fs::write(&path, &out)
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            index.push(format!(
+                r#"{{"chunk":{},"path":{},"bytes":{},"files":[{}]}}{}"#,
+                i + 1,
+                json::string(&name),
+                bytes,
+                files.join(","),
+                if i + 1 == total { "" } else { "," }
+            ));
+        }
+        index.push("]}".to_string());
+
+        // Drop leftovers from an earlier run that needed more chunks.
+        let mut stale = total + 1;
+        while fs::remove_file(chunk_path(prefix, stale, self.gzip)).is_ok() {
+            stale += 1;
+        }
+
+        let index_path = sibling(prefix, "index.json");
+        fs::write(&index_path, index.join("\n") + "\n")
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", index_path.display())))?;
+        Ok((total, written))
+    }
+}
+
+fn gzip(bytes: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes)?;
+    encoder.finish()
+}
+
+fn part_header(part: usize, total: usize) -> String {
+    format!("> dir2prompt dump: part {part} of {total}\n\n")
+}
+
+/// `prefix.NNN.md` (`.md.gz` with `gzip`), with any `.md` or `.md.gz`
+/// already on the prefix dropped.
+pub fn chunk_path(prefix: &Path, part: usize, gzip: bool) -> PathBuf {
+    let ext = if gzip { "md.gz" } else { "md" };
+    sibling(prefix, &format!("{part:03}.{ext}"))
+}
+
+fn sibling(prefix: &Path, suffix: &str) -> PathBuf {
+    let mut prefix = prefix.to_path_buf();
+    for ext in ["gz", "md"] {
+        if prefix.extension().is_some_and(|e| e == ext) {
+            prefix = prefix.with_extension("");
+        }
+    }
+    let mut name = prefix.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    prefix.with_file_name(name)
+}
+
+/// True for chunk and index files a previous run wrote for `prefix`, so
+/// they aren't dumped back in.
+pub fn is_output(prefix: &Path, path: &Path) -> bool {
+    let index = sibling(prefix, "index.json");
+    let Some(stem) = index
+        .file_name()
+        .and_then(|n| n.to_str())
+        .and_then(|n| n.strip_suffix("index.json"))
+    else {
+        return false;
+    };
+    if path.parent() != index.parent() {
+        return false;
+    }
+    let Some(rest) = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .and_then(|n| n.strip_prefix(stem))
+    else {
+        return false;
+    };
+    let rest = rest.strip_suffix(".gz").unwrap_or(rest);
+    rest == "index.json"
+        || rest
+            .strip_suffix(".md")
+            .is_some_and(|n| n.len() >= 3 && n.bytes().all(|b| b.is_ascii_digit()))
+}
@@ -0,0 +1,122 @@
+//! `--stats-only` and `--dry-run`: reports of what a dump would contain,
+//! without reading any content.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::{format_size, format_tokens};
+
+/// How many files the "Largest files" table lists.
+const LARGEST: usize = 10;
+
+/// One walked file: its displayed path, language tag, size on disk and
+/// the `--max-bytes` that applies to it.
+pub struct Entry<'a> {
+    pub label: &'a str,
+    pub language: &'static str,
+    pub bytes: u64,
+    pub max_bytes: u64,
+}
+
+/// Write the report as Markdown tables: totals, then files and bytes per
+/// language (biggest first), then the largest files.
+pub fn write_report(out: &mut impl Write, root: &str, entries: &[Entry]) -> io::Result<()> {
+    let total: u64 = entries.iter().map(|e| e.bytes).sum();
+    writeln!(out, "# dir2prompt stats")?;
+    writeln!(out)?;
+    writeln!(out, "- Root: `{root}`")?;
+    writeln!(out, "- Files: {}", entries.len())?;
+    writeln!(out, "- Total size: {} ({total} bytes)", format_size(total))?;
+    writeln!(out)?;
+
+    let mut languages: HashMap<&str, (usize, u64)> = HashMap::new();
+    for entry in entries {
+        let (files, bytes) = languages.entry(entry.language).or_default();
+        *files += 1;
+        *bytes += entry.bytes;
+    }
+    let mut languages: Vec<_> = languages.into_iter().collect();
+    languages.sort_by(|a, b| b.1.1.cmp(&a.1.1).then(a.0.cmp(b.0)));
+
+    writeln!(out, "## By language")?;
+    writeln!(out)?;
+    writeln!(out, "| Language | Files | Size | Bytes |")?;
+    writeln!(out, "|---|---:|---:|---:|")?;
+    for (language, (files, bytes)) in &languages {
+        writeln!(
+            out,
+            "| {language} | {files} | {} | {bytes} |",
+            format_size(*bytes)
+        )?;
+    }
+    writeln!(out)?;
+
+    // Stable, so equal sizes keep the dump order.
+    let mut largest: Vec<&Entry> = entries.iter().collect();
+    largest.sort_by_key(|e| std::cmp::Reverse(e.bytes));
+    largest.truncate(LARGEST);
+
+    writeln!(out, "## Largest files")?;
+    writeln!(out)?;
+    writeln!(out, "| File | Language | Size | Bytes |")?;
+    writeln!(out, "|---|---|---:|---:|")?;
+    for entry in largest {
+        writeln!(
+            out,
+            "| `{}` | {} | {} | {} |",
+            entry.label,
+            entry.language,
+            format_size(entry.bytes),
+            entry.bytes
+        )?;
+    }
+    Ok(())
+}
+
+/// Write the `--dry-run` plan: every file in output order with the size it
+/// would contribute (capped at its `max_bytes`) and its estimated tokens, then
+/// the projected total. `omitted` files were dropped by `--max-files`.
+pub fn write_dry_run(
+    out: &mut impl Write,
+    root: &str,
+    entries: &[Entry],
+    omitted: usize,
+) -> io::Result<()> {
+    let sizes: Vec<u64> = entries.iter().map(|e| e.bytes.min(e.max_bytes)).collect();
+    let total: u64 = sizes.iter().sum();
+    // Bytes stand in for characters, which is exact for ASCII.
+    let tokens = |bytes: u64| format_tokens(bytes.div_ceil(4) as usize);
+    writeln!(out, "# dir2prompt dry run")?;
+    writeln!(out)?;
+    writeln!(out, "- Root: `{root}`")?;
+    writeln!(out, "- Files: {}", entries.len())?;
+    if omitted > 0 {
+        writeln!(out, "- Omitted by max-files: {omitted}")?;
+    }
+    writeln!(
+        out,
+        "- Projected size: {} ({total} bytes), {} tokens (estimated)",
+        format_size(total),
+        tokens(total)
+    )?;
+    writeln!(out)?;
+
+    writeln!(out, "| # | File | Size | Tokens (est.) |")?;
+    writeln!(out, "|---:|---|---:|---:|")?;
+    for (i, (entry, size)) in entries.iter().zip(&sizes).enumerate() {
+        let truncated = if entry.bytes > entry.max_bytes {
+            " (truncated)"
+        } else {
+            ""
+        };
+        writeln!(
+            out,
+            "| {} | `{}` | {}{truncated} | {} |",
+            i + 1,
+            entry.label,
+            format_size(*size),
+            tokens(*size)
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,175 @@
+//! Large files written straight from disk into the Markdown dump, instead
+//! of being read into a `String` first, so memory per file stays bounded
+//! however high `--max-bytes` is.
+//!
+//! A file is scanned once up front (on a reader thread) for what the
+//! section needs before its content: that it is plain UTF-8 text, the fence
+//! and where the cut falls. Its bytes are then copied through in chunks
+//! when the section is written. Anything unusual (a BOM, invalid UTF-8, a
+//! binary sample) is left to the in-memory path, as is every option that
+//! needs the whole text (transforms, budgets, `--dedup`, ...), so the
+//! output is the same either way.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::parallel::OpenFiles;
+use crate::{Config, Format, TruncateMode, Truncation};
+
+/// Files smaller than this are read into memory as usual.
+const MIN_BYTES: u64 = 1024 * 1024;
+
+/// Read and copy size.
+const CHUNK: usize = 64 * 1024;
+
+/// A file to be copied into its section when it is written.
+pub struct Streamed {
+    pub path: PathBuf,
+    /// Bytes of the file that are emitted, from the start.
+    pub len: u64,
+    pub fence: String,
+    pub truncated: Option<Truncation>,
+    /// The emitted bytes end with a newline.
+    pub ends_with_newline: bool,
+}
+
+/// True if nothing in `config` needs a file's whole text in memory, for
+/// a dump written as Markdown (without a layout).
+pub fn allowed(config: &Config) -> bool {
+    config.format == Format::Markdown
+        && config.layout_file.is_none()
+        && config.truncate == TruncateMode::Head
+        && config.max_lines.is_none()
+        && config.locations_from.is_none()
+        && config.exclude_content.is_empty()
+        && !config.normalize_eol
+        && !config.redact
+        && !config.collapse_blobs
+        && !config.strip_control_chars
+        && !config.strip_comments
+        && !config.only_headers
+        && !config.trim_trailing
+        && config.tabs_to_spaces.is_none()
+        && !config.squeeze_blank
+        && config.wrap_long_lines.is_none()
+        && !config.dedup
+        && !config.sniff_lockfiles
+        && !config.skip_minified
+        && !config.line_numbers
+        && !config.heading_stats
+        && !config.listing_stats
+        && !config.estimate_tokens
+        && config.max_total_tokens.is_none()
+        && config.max_total_bytes.is_none()
+        && !config.fail_on_secrets
+}
+
+/// Scan `path` for streaming; `None` if it is small or anything but plain
+/// UTF-8 text, for the in-memory path to handle.
+pub fn probe(path: &Path, config: &Config, open_files: &OpenFiles) -> Option<Streamed> {
+    let _permit = open_files.permit();
+    let mut file = File::open(path).ok()?;
+    let size = file.metadata().ok()?.len();
+    if size < MIN_BYTES {
+        return None;
+    }
+    let max = crate::max_bytes_for(path, config) as u64;
+    let limit = size.min(max);
+
+    let mut buf = vec![0u8; CHUNK];
+    let mut read = 0u64;
+    // Bytes of a character split across chunks.
+    let mut carry: Vec<u8> = Vec::new();
+    let (mut run, mut longest) = (0usize, 0usize);
+    let mut last_newline = None;
+    let mut longest_at_newline = 0usize;
+    while read < limit {
+        let n = (limit - read).min(CHUNK as u64) as usize;
+        // Fails if the file shrank meanwhile.
+        file.read_exact(&mut buf[..n]).ok()?;
+        let chunk = &buf[..n];
+        if read == 0 && (chunk.starts_with(b"\xEF\xBB\xBF") || has_control_bytes(chunk)) {
+            return None;
+        }
+        carry.extend_from_slice(chunk);
+        let valid = match std::str::from_utf8(&carry) {
+            Ok(_) => carry.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => return None,
+        };
+        for (i, &b) in carry[..valid].iter().enumerate() {
+            match b {
+                b'`' => {
+                    run += 1;
+                    longest = longest.max(run);
+                }
+                b'\n' => {
+                    run = 0;
+                    // `carry` starts at `read - (carry.len() - n)` in the file.
+                    last_newline = Some(read + n as u64 - carry.len() as u64 + i as u64);
+                    longest_at_newline = longest;
+                }
+                _ => run = 0,
+            }
+        }
+        carry.drain(..valid);
+        read += n as u64;
+    }
+    if !carry.is_empty() {
+        // Cut mid-character.
+        return None;
+    }
+
+    let mut len = limit;
+    if size > max
+        && config.soft_max_bytes
+        && let Some(nl) = last_newline
+    {
+        len = nl + 1;
+        longest = longest_at_newline;
+    }
+    let ends_with_newline = last_newline.is_some_and(|nl| nl + 1 == len);
+    let truncated = (size > max).then(|| Truncation::Bytes {
+        limit: max,
+        original: size,
+        omitted: size - len,
+    });
+    Some(Streamed {
+        path: path.to_path_buf(),
+        len,
+        fence: "`".repeat((longest + 1).max(3)),
+        truncated,
+        ends_with_newline,
+    })
+}
+
+/// True if the first 8 KiB (the sample `looks_binary` checks) has a NUL
+/// or control byte. Without any, a file is text to every decoding path
+/// however it is cut, so only plain UTF-8 remains to be checked.
+fn has_control_bytes(chunk: &[u8]) -> bool {
+    chunk[..chunk.len().min(8 * 1024)].iter().any(|&b| {
+        (b < 0x20 || b == 0x7f) && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0b | 0x0c | 0x08 | 0x1b)
+    })
+}
+
+/// Copy the emitted bytes of `streamed` into `out`, followed by a newline
+/// if they don't end with one.
+pub fn copy(streamed: &Streamed, out: &mut impl Write, open_files: &OpenFiles) -> io::Result<()> {
+    let _permit = open_files.permit();
+    let file = File::open(&streamed.path)?;
+    let copied = io::copy(&mut file.take(streamed.len), out)?;
+    if copied < streamed.len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!(
+                "{}: file shrank while being dumped",
+                streamed.path.display()
+            ),
+        ));
+    }
+    if !streamed.ends_with_newline {
+        writeln!(out)?;
+    }
+    Ok(())
+}
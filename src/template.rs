@@ -0,0 +1,136 @@
+//! A tiny `{{placeholder}}` substitution engine and the `--layout-file`
+//! document layout built on it.
+//!
+//! A layout file has up to three sections, each introduced by a marker on
+//! its own line:
+//!
+//! ```text
+//! [[header]]
+//! <documents root="{{root}}">
+//! [[file]]
+//! <file path="{{path}}">{{content}}</file>
+//! [[footer]]
+//! </documents>
+//! ```
+//!
+//! A marker can also be written `{{header}}`, `{{file}}` or `{{footer}}`.
+//! Text before the first marker is ignored, so a layout can start with a
+//! comment. Placeholders are checked when the layout is loaded, so a typo
+//! fails the run before anything is written.
+
+use std::io;
+use std::path::Path;
+
+/// Placeholders available in the header and footer.
+pub const DOCUMENT_VARS: &[&str] = &["root", "file_count", "files"];
+
+/// Placeholders available per file.
+pub const FILE_VARS: &[&str] = &[
+    "path",
+    "lang",
+    "content",
+    "note",
+    "truncated_note",
+    "fence",
+    "size",
+    "lines",
+];
+
+enum Part {
+    Lit(String),
+    Var(String),
+}
+
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    /// Parse `src`, rejecting placeholders not in `allowed`.
+    pub fn parse(src: &str, allowed: &[&str]) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut rest = src;
+        while let Some(open) = rest.find("{{") {
+            let Some(close) = rest[open..].find("}}") else {
+                return Err(format!("unclosed `{{{{` in `{}`", &rest[open..]));
+            };
+            if open > 0 {
+                parts.push(Part::Lit(rest[..open].to_string()));
+            }
+            let name = rest[open + 2..open + close].trim();
+            if !allowed.contains(&name) {
+                return Err(format!(
+                    "unknown placeholder `{{{{{name}}}}}` (expected one of: {})",
+                    allowed.join(", ")
+                ));
+            }
+            parts.push(Part::Var(name.to_string()));
+            rest = &rest[open + close + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Lit(rest.to_string()));
+        }
+        Ok(Template { parts })
+    }
+
+    /// Render, asking `value` for each placeholder.
+    pub fn render(&self, value: impl Fn(&str) -> String) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Lit(s) => out.push_str(s),
+                Part::Var(name) => out.push_str(&value(name)),
+            }
+        }
+        out
+    }
+}
+
+pub struct Layout {
+    pub header: Template,
+    pub file: Template,
+    pub footer: Template,
+}
+
+impl Layout {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let src = std::fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+        let bad = |e: String| io::Error::other(format!("{}: {e}", path.display()));
+
+        let mut sections: [(&str, String); 3] = [
+            ("header", String::new()),
+            ("file", String::new()),
+            ("footer", String::new()),
+        ];
+        let mut current: Option<usize> = None;
+        for line in src.split_inclusive('\n') {
+            let marker = line.trim_end();
+            if let Some(name) = marker.strip_prefix("[[").and_then(|m| m.strip_suffix("]]")) {
+                let Some(i) = sections.iter().position(|(n, _)| *n == name) else {
+                    return Err(bad(format!("unknown section `[[{name}]]`")));
+                };
+                current = Some(i);
+                continue;
+            }
+            if let Some(i) = marker
+                .strip_prefix("{{")
+                .and_then(|m| m.strip_suffix("}}"))
+                .and_then(|name| sections.iter().position(|(n, _)| *n == name.trim()))
+            {
+                current = Some(i);
+                continue;
+            }
+            if let Some(i) = current {
+                sections[i].1.push_str(line);
+            }
+        }
+
+        let [(_, header), (_, file), (_, footer)] = sections;
+        Ok(Layout {
+            header: Template::parse(&header, DOCUMENT_VARS).map_err(bad)?,
+            file: Template::parse(&file, FILE_VARS).map_err(bad)?,
+            footer: Template::parse(&footer, DOCUMENT_VARS).map_err(bad)?,
+        })
+    }
+}
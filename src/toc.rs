@@ -0,0 +1,36 @@
+//! `--toc`: GitHub-style heading anchors, so the included files list can
+//! link to each file's section.
+
+use std::collections::HashMap;
+
+/// Hands out anchors the way GitHub does (github-slugger): lowercase, drop
+/// punctuation other than `-` and `_`, spaces to `-`, and `-1`, `-2`, ...
+/// for repeats. Feed it every heading in document order.
+#[derive(Default)]
+pub struct Slugger {
+    occurrences: HashMap<String, usize>,
+}
+
+impl Slugger {
+    /// The anchor for a heading with this text (Markdown as written).
+    pub fn slug(&mut self, heading: &str) -> String {
+        let base: String = heading
+            .to_lowercase()
+            .chars()
+            .filter_map(|c| match c {
+                ' ' => Some('-'),
+                '-' | '_' => Some(c),
+                c if c.is_alphanumeric() => Some(c),
+                _ => None,
+            })
+            .collect();
+        let mut slug = base.clone();
+        while self.occurrences.contains_key(&slug) {
+            let n = self.occurrences.get_mut(&base).expect("base slug was seen");
+            *n += 1;
+            slug = format!("{base}-{n}");
+        }
+        self.occurrences.insert(slug.clone(), 0);
+        slug
+    }
+}
@@ -0,0 +1,32 @@
+//! `--tokenizer`: exact token counts from a BPE tokenizer. Only built with
+//! the `tokenizer` feature, which pulls in `tiktoken-rs` and its
+//! vocabularies; otherwise asking for one is an error.
+
+use std::io;
+
+use crate::Tokenizer;
+
+/// Fail unless `tokenizer` can be used in this build.
+pub fn ensure_available(tokenizer: Option<Tokenizer>) -> io::Result<()> {
+    if tokenizer.is_some() && !cfg!(feature = "tokenizer") {
+        return Err(io::Error::other(
+            "--tokenizer needs dir2prompt built with the `tokenizer` feature",
+        ));
+    }
+    Ok(())
+}
+
+/// Tokens in `text`, as the model family using `tokenizer` would see it.
+#[cfg(feature = "tokenizer")]
+pub fn count(text: &str, tokenizer: Tokenizer) -> usize {
+    let bpe = match tokenizer {
+        Tokenizer::Cl100kBase => tiktoken_rs::cl100k_base_singleton(),
+        Tokenizer::O200kBase => tiktoken_rs::o200k_base_singleton(),
+    };
+    bpe.encode_ordinary(text).len()
+}
+
+#[cfg(not(feature = "tokenizer"))]
+pub fn count(_text: &str, _tokenizer: Tokenizer) -> usize {
+    unreachable!("rejected by `ensure_available`")
+}
@@ -0,0 +1,369 @@
+//! Text transforms applied to decoded file content before it is emitted.
+//!
+//! Enabled transforms run as a pipeline in a fixed order, whatever order
+//! their flags were given in:
+//!
+//! 1. `--normalize-eol`
+//! 2. `--redact`
+//! 3. `--collapse-blobs`
+//! 4. `--strip-control-chars`
+//! 5. `--strip-comments`
+//! 6. `--only-headers`
+//! 7. `--trim-trailing`
+//! 8. `--tabs-to-spaces`
+//! 9. `--wrap-long-lines`
+//! 10. `--squeeze-blank`
+//!
+//! Each stage is a plain `fn(&str, &Context) -> String`, so stages can be
+//! tested and composed on their own.
+
+use std::cell::Cell;
+
+/// Per-file inputs to the stages, plus counters they report into.
+pub struct Context<'a> {
+    pub tab_width: usize,
+    pub wrap_width: usize,
+    /// The file's `language_tag`, for language-aware stages.
+    pub language: &'static str,
+    pub stats: &'a Stats,
+}
+
+/// Counters accumulated by the stages (and `--cache`), per file or across
+/// a run.
+#[derive(Default)]
+pub struct Stats {
+    pub control_chars_removed: Cell<usize>,
+    pub redactions: Cell<usize>,
+    pub comment_bytes_removed: Cell<usize>,
+    pub blobs_collapsed: Cell<usize>,
+    /// Files whose line endings were rewritten to `\n`.
+    pub eol_normalized: Cell<usize>,
+    /// Files `--only-headers` has no patterns for.
+    pub not_outlined: Cell<usize>,
+    /// Files whose content came from `--cache`, and files it didn't have.
+    pub cache_hits: Cell<usize>,
+    pub cache_misses: Cell<usize>,
+}
+
+impl Stats {
+    /// Fold another file's counters into these.
+    pub fn add(&self, other: &Stats) {
+        bump(
+            &self.control_chars_removed,
+            other.control_chars_removed.get(),
+        );
+        bump(&self.redactions, other.redactions.get());
+        bump(
+            &self.comment_bytes_removed,
+            other.comment_bytes_removed.get(),
+        );
+        bump(&self.blobs_collapsed, other.blobs_collapsed.get());
+        bump(&self.eol_normalized, other.eol_normalized.get());
+        bump(&self.not_outlined, other.not_outlined.get());
+        bump(&self.cache_hits, other.cache_hits.get());
+        bump(&self.cache_misses, other.cache_misses.get());
+    }
+}
+
+fn bump(counter: &Cell<usize>, n: usize) {
+    counter.set(counter.get() + n);
+}
+
+/// A transform stage. Variant order is pipeline order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    NormalizeEol,
+    Redact,
+    CollapseBlobs,
+    StripControlChars,
+    StripComments,
+    OnlyHeaders,
+    TrimTrailing,
+    TabsToSpaces,
+    WrapLongLines,
+    SqueezeBlank,
+}
+
+impl Stage {
+    fn func(self) -> fn(&str, &Context) -> String {
+        match self {
+            Stage::NormalizeEol => normalize_eol_stage,
+            Stage::Redact => redact_stage,
+            Stage::CollapseBlobs => collapse_blobs_stage,
+            Stage::StripControlChars => strip_control_chars_stage,
+            Stage::StripComments => strip_comments_stage,
+            Stage::OnlyHeaders => only_headers_stage,
+            Stage::TrimTrailing => trim_trailing,
+            Stage::TabsToSpaces => tabs_to_spaces,
+            Stage::WrapLongLines => wrap_long_lines,
+            Stage::SqueezeBlank => |text, _| squeeze_blank_lines(text),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Pipeline {
+    stages: Vec<Stage>,
+}
+
+impl Pipeline {
+    pub fn new(mut stages: Vec<Stage>) -> Self {
+        stages.sort();
+        stages.dedup();
+        Pipeline { stages }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    pub fn apply(&self, text: &str, ctx: &Context) -> String {
+        let mut text = text.to_string();
+        for stage in &self.stages {
+            text = stage.func()(&text, ctx);
+        }
+        text
+    }
+}
+
+fn normalize_eol_stage(text: &str, ctx: &Context) -> String {
+    if !text.contains('\r') {
+        return text.to_string();
+    }
+    bump(&ctx.stats.eol_normalized, 1);
+    normalize_line_endings(text)
+}
+
+fn redact_stage(text: &str, ctx: &Context) -> String {
+    let (clean, redacted) = crate::secrets::redact(text);
+    bump(&ctx.stats.redactions, redacted);
+    clean
+}
+
+fn collapse_blobs_stage(text: &str, ctx: &Context) -> String {
+    let (clean, collapsed) = collapse_blobs(text);
+    bump(&ctx.stats.blobs_collapsed, collapsed);
+    clean
+}
+
+fn strip_control_chars_stage(text: &str, ctx: &Context) -> String {
+    let (clean, removed) = strip_control_chars(text);
+    bump(&ctx.stats.control_chars_removed, removed);
+    clean
+}
+
+fn strip_comments_stage(text: &str, ctx: &Context) -> String {
+    let clean = crate::comments::strip(text, ctx.language);
+    bump(
+        &ctx.stats.comment_bytes_removed,
+        text.len().saturating_sub(clean.len()),
+    );
+    clean
+}
+
+fn only_headers_stage(text: &str, ctx: &Context) -> String {
+    match crate::outline::outline(text, ctx.language) {
+        Some(outline) => outline,
+        None => {
+            bump(&ctx.stats.not_outlined, 1);
+            text.to_string()
+        }
+    }
+}
+
+/// Remove trailing spaces and tabs from every line, keeping line endings.
+pub fn trim_trailing(text: &str, _ctx: &Context) -> String {
+    let mut out = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        let (body, eol) = split_eol(line);
+        out.push_str(body.trim_end_matches([' ', '\t']));
+        out.push_str(eol);
+    }
+    out
+}
+
+/// Expand tabs to spaces, aligning to multiples of `ctx.tab_width`.
+pub fn tabs_to_spaces(text: &str, ctx: &Context) -> String {
+    let width = ctx.tab_width.max(1);
+    let mut out = String::with_capacity(text.len());
+    let mut col = 0usize;
+    for c in text.chars() {
+        match c {
+            '\t' => {
+                let n = width - col % width;
+                out.extend(std::iter::repeat_n(' ', n));
+                col += n;
+            }
+            '\n' => {
+                out.push(c);
+                col = 0;
+            }
+            c => {
+                out.push(c);
+                col += 1;
+            }
+        }
+    }
+    out
+}
+
+/// Break every line longer than `ctx.wrap_width` characters into lines of
+/// that many, the last one keeping the original line ending.
+pub fn wrap_long_lines(text: &str, ctx: &Context) -> String {
+    let mut out = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        let pieces = wrap_line(line, ctx.wrap_width);
+        let last = pieces.len() - 1;
+        for (i, piece) in pieces.into_iter().enumerate() {
+            out.push_str(piece);
+            if i < last {
+                out.push('\n');
+            }
+        }
+    }
+    out
+}
+
+/// `line` cut into pieces of `width` characters (at least one), not
+/// counting its line ending, which stays on the last piece.
+pub fn wrap_line(line: &str, width: usize) -> Vec<&str> {
+    let body_len = split_eol(line).0.len();
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let mut chars = 0;
+    for (i, _) in line[..body_len].char_indices() {
+        if chars == width.max(1) {
+            pieces.push(&line[start..i]);
+            start = i;
+            chars = 0;
+        }
+        chars += 1;
+    }
+    pieces.push(&line[start..]);
+    pieces
+}
+
+/// Collapse runs of empty lines into one, like `cat -s`. Lines holding
+/// only whitespace aren't empty and are kept as they are.
+pub fn squeeze_blank_lines(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut previous_empty = false;
+    for line in text.split_inclusive('\n') {
+        let empty = split_eol(line).0.is_empty();
+        if !(empty && previous_empty) {
+            out.push_str(line);
+        }
+        previous_empty = empty;
+    }
+    out
+}
+
+/// Turn `\r\n` and lone `\r` line endings into `\n`. Text that already
+/// uses `\n` comes back unchanged.
+pub fn normalize_line_endings(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+/// Runs of base64 characters at least this long are collapsed.
+pub const BLOB_MIN_CHARS: usize = 512;
+
+/// Replace each run of at least [`BLOB_MIN_CHARS`] base64 characters (with
+/// any `=` padding) by `«base64 blob, N bytes omitted»`. A run must mix
+/// upper case, lower case and digits, so long hex strings and identifiers
+/// are left alone. Returns the text and how many runs were collapsed.
+pub fn collapse_blobs(text: &str) -> (String, usize) {
+    let is_base64 = |b: u8| b.is_ascii_alphanumeric() || b == b'+' || b == b'/';
+    let bytes = text.as_bytes();
+    let mut out = String::with_capacity(text.len());
+    let mut collapsed = 0;
+    let (mut copied, mut i) = (0, 0);
+    while i < bytes.len() {
+        if !is_base64(bytes[i]) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < bytes.len() && is_base64(bytes[i]) {
+            i += 1;
+        }
+        let digits = i;
+        while i < bytes.len() && i - digits < 2 && bytes[i] == b'=' {
+            i += 1;
+        }
+        let run = &bytes[start..i];
+        if run.len() >= BLOB_MIN_CHARS
+            && run.iter().any(u8::is_ascii_uppercase)
+            && run.iter().any(u8::is_ascii_lowercase)
+            && run.iter().any(u8::is_ascii_digit)
+        {
+            out.push_str(&text[copied..start]);
+            out.push_str(&format!("«base64 blob, {} bytes omitted»", run.len()));
+            copied = i;
+            collapsed += 1;
+        }
+    }
+    out.push_str(&text[copied..]);
+    (out, collapsed)
+}
+
+/// Split a line into its body and its `\n` / `\r\n` terminator.
+fn split_eol(line: &str) -> (&str, &str) {
+    if let Some(body) = line.strip_suffix("\r\n") {
+        (body, "\r\n")
+    } else if let Some(body) = line.strip_suffix('\n') {
+        (body, "\n")
+    } else {
+        (line, "")
+    }
+}
+
+/// Remove ANSI escape sequences and control characters other than tab,
+/// newline and carriage return. Returns the cleaned text and how many
+/// characters or escape sequences were removed.
+pub fn strip_control_chars(text: &str) -> (String, usize) {
+    let mut out = String::with_capacity(text.len());
+    let mut removed = 0usize;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\t' | '\n' | '\r' => out.push(c),
+            '\u{1b}' => {
+                removed += 1;
+                match chars.peek() {
+                    // CSI: ESC [ params... final byte in @..~
+                    Some('[') => {
+                        chars.next();
+                        for c in chars.by_ref() {
+                            if ('@'..='~').contains(&c) {
+                                break;
+                            }
+                        }
+                    }
+                    // OSC: ESC ] ... terminated by BEL or ESC \
+                    Some(']') => {
+                        chars.next();
+                        while let Some(c) = chars.next() {
+                            if c == '\u{7}' {
+                                break;
+                            }
+                            if c == '\u{1b}' && chars.peek() == Some(&'\\') {
+                                chars.next();
+                                break;
+                            }
+                        }
+                    }
+                    // Two-character escapes (ESC c, ESC 7, ...)
+                    Some(_) => {
+                        chars.next();
+                    }
+                    None => {}
+                }
+            }
+            c if c.is_control() => removed += 1,
+            c => out.push(c),
+        }
+    }
+
+    (out, removed)
+}
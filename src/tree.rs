@@ -0,0 +1,59 @@
+//! The `--tree` overview: included files drawn as a `tree`-style hierarchy.
+
+use std::path::{Component, Path};
+
+#[derive(Default)]
+struct Node {
+    /// Children in first-seen order, so the tree follows the dump order.
+    children: Vec<(String, Node)>,
+}
+
+impl Node {
+    fn child(&mut self, name: &str) -> &mut Node {
+        let i = match self.children.iter().position(|(n, _)| n == name) {
+            Some(i) => i,
+            None => {
+                self.children.push((name.to_string(), Node::default()));
+                self.children.len() - 1
+            }
+        };
+        &mut self.children[i].1
+    }
+}
+
+/// Draw `paths` (relative to the root) under a first line reading `root`.
+/// Only directories holding at least one of the paths appear.
+pub fn render<'a>(root: &str, paths: impl IntoIterator<Item = &'a Path>) -> String {
+    let mut top = Node::default();
+    for path in paths {
+        let mut node = &mut top;
+        for c in path.components() {
+            if let Component::Normal(name) = c {
+                node = node.child(&name.to_string_lossy());
+            }
+        }
+    }
+
+    let mut out = format!("{root}\n");
+    draw(&top, "", &mut out);
+    out
+}
+
+fn draw(node: &Node, prefix: &str, out: &mut String) {
+    for (i, (name, child)) in node.children.iter().enumerate() {
+        let last = i + 1 == node.children.len();
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        out.push_str(prefix);
+        out.push_str(branch);
+        out.push_str(name);
+        if !child.children.is_empty() {
+            out.push('/');
+        }
+        out.push('\n');
+        draw(child, &format!("{prefix}{indent}"), out);
+    }
+}
@@ -0,0 +1,297 @@
+//! Reconstruct files from a dump: either the `bundle` format or a regular
+//! Markdown dump with ``## `path` `` headings and fenced blocks.
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use crate::bundle;
+
+struct Entry {
+    path: String,
+    content: String,
+    truncated: bool,
+}
+
+/// Read a dump from `input` (`-` for stdin) and write its files under
+/// `target`. Truncated files and paths escaping `target` (or going
+/// through a symlink in it) are reported and left alone.
+pub fn run(input: &Path, target: &Path) -> io::Result<()> {
+    let mut dump = String::new();
+    if input == Path::new("-") {
+        io::stdin().read_to_string(&mut dump)?;
+    } else {
+        dump = fs::read_to_string(input)?;
+    }
+
+    let is_bundle = dump.lines().find(|l| !l.trim().is_empty()) == Some(bundle::BEGIN_BUNDLE);
+    let entries = if is_bundle {
+        parse_bundle(&dump)?
+    } else {
+        parse_markdown(&dump)
+    };
+
+    fs::create_dir_all(target)?;
+    let target = fs::canonicalize(target)?;
+
+    let mut written = 0usize;
+    let mut refused = 0usize;
+    for entry in &entries {
+        if entry.truncated {
+            eprintln!(
+                "dir2prompt: skipped `{}`: content was truncated",
+                entry.path
+            );
+            refused += 1;
+            continue;
+        }
+        let Some(dest) = safe_join(&target, &entry.path)? else {
+            eprintln!(
+                "dir2prompt: refused `{}`: path escapes target or goes through a symlink",
+                entry.path
+            );
+            refused += 1;
+            continue;
+        };
+        write_new(&dest, &entry.content)?;
+        eprintln!(
+            "dir2prompt: wrote `{}` ({} bytes)",
+            entry.path,
+            entry.content.len()
+        );
+        written += 1;
+    }
+
+    eprintln!("dir2prompt: wrote {written} files, refused {refused}");
+    Ok(())
+}
+
+/// Resolve `rel` under `target`, creating parent directories. Returns
+/// `None` for absolute paths, `..` components, and paths through (or to)
+/// a symlink or anything else that isn't a plain directory (or file, for
+/// the last component). Each component is checked before anything is
+/// created in it, so nothing is ever made outside `target`.
+fn safe_join(target: &Path, rel: &str) -> io::Result<Option<PathBuf>> {
+    let rel = Path::new(rel);
+    let mut parts = Vec::new();
+    for c in rel.components() {
+        match c {
+            Component::Normal(part) => parts.push(part),
+            Component::CurDir => {}
+            _ => return Ok(None),
+        }
+    }
+    let Some((name, dirs)) = parts.split_last() else {
+        return Ok(None);
+    };
+
+    let mut dest = target.to_path_buf();
+    for dir in dirs {
+        dest.push(dir);
+        match fs::symlink_metadata(&dest) {
+            Ok(meta) if meta.is_dir() => {}
+            Ok(_) => return Ok(None),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => fs::create_dir(&dest)?,
+            Err(err) => return Err(err),
+        }
+    }
+    dest.push(name);
+    match fs::symlink_metadata(&dest) {
+        Ok(meta) if !meta.is_file() => Ok(None),
+        Ok(_) => Ok(Some(dest)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Some(dest)),
+        Err(err) => Err(err),
+    }
+}
+
+/// Write `content` to a fresh file at `dest`, replacing a regular file
+/// there. `create_new` won't follow a symlink put in its place meanwhile.
+fn write_new(dest: &Path, content: &str) -> io::Result<()> {
+    match fs::remove_file(dest) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dest)?;
+    file.write_all(content.as_bytes())
+}
+
+fn parse_bundle(dump: &str) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut current: Option<Entry> = None;
+
+    for line in dump.split_inclusive('\n') {
+        let bare = line.trim_end_matches(['\n', '\r']);
+
+        let Some(entry) = current.as_mut() else {
+            if let Some(path) = bare.strip_prefix(bundle::BEGIN_FILE) {
+                current = Some(Entry {
+                    path: path.to_string(),
+                    content: String::new(),
+                    truncated: false,
+                });
+            }
+            // Begin/End Bundle, Skipped File and stray text between files are ignored.
+            continue;
+        };
+
+        if bare == bundle::END_FILE {
+            entries.extend(current.take());
+        } else if bare == bundle::NO_EOL {
+            if entry.content.ends_with('\n') {
+                entry.content.pop();
+            }
+        } else if bare.starts_with(bundle::TRUNCATED) {
+            entry.truncated = true;
+        } else if bare.starts_with(bundle::NOTE) {
+            // Informational only.
+        } else if bare.starts_with("***") {
+            return Err(io::Error::other(format!(
+                "bad bundle: unexpected marker `{bare}` in `{}`",
+                entry.path
+            )));
+        } else if bundle::needs_escape(line) {
+            entry.content.push_str(&line[1..]);
+        } else {
+            entry.content.push_str(line);
+        }
+    }
+
+    if let Some(entry) = current {
+        return Err(io::Error::other(format!(
+            "bad bundle: `{}` is missing `{}`",
+            entry.path,
+            bundle::END_FILE
+        )));
+    }
+    Ok(entries)
+}
+
+/// Best-effort reader for the Markdown format. Markdown dumps always end
+/// content with a newline, so a missing final newline can't be restored.
+fn parse_markdown(dump: &str) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut heading: Option<(String, bool)> = None;
+    let mut fence: Option<(String, Entry)> = None;
+
+    for line in dump.split_inclusive('\n') {
+        let bare = line.trim_end_matches(['\n', '\r']);
+
+        if let Some((close, entry)) = fence.as_mut() {
+            if bare == close.as_str() {
+                entries.extend(fence.take().map(|(_, e)| e));
+            } else {
+                entry.content.push_str(line);
+            }
+            continue;
+        }
+
+        // Headings may carry a suffix after the path, e.g. `--heading-stats`.
+        if let Some((path, _)) = bare
+            .strip_prefix("## `")
+            .and_then(|rest| rest.split_once('`'))
+        {
+            heading = Some((path.to_string(), false));
+        } else if bare.starts_with("(truncated") {
+            if let Some((_, truncated)) = heading.as_mut() {
+                *truncated = true;
+            }
+        } else if bare.starts_with("```") {
+            let Some((path, truncated)) = heading.take() else {
+                continue;
+            };
+            let ticks = bare.len() - bare.trim_start_matches('`').len();
+            fence = Some((
+                "`".repeat(ticks),
+                Entry {
+                    path,
+                    content: String::new(),
+                    truncated,
+                },
+            ));
+        }
+    }
+
+    entries
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use super::*;
+
+    /// Unbundle a Markdown dump of `files` into `target`.
+    fn unbundle(dir: &Path, target: &Path, files: &[(&str, &str)]) {
+        let mut dump = String::new();
+        for (path, content) in files {
+            dump.push_str(&format!("## `{path}`\n\n```\n{content}\n```\n\n"));
+        }
+        let input = dir.join("dump.md");
+        fs::write(&input, dump).unwrap();
+        run(&input, target).unwrap();
+    }
+
+    #[test]
+    fn refuses_directories_through_a_symlink() {
+        let dir = tempfile::tempdir().unwrap();
+        let (target, outside) = (dir.path().join("target"), dir.path().join("outside"));
+        fs::create_dir_all(&target).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        symlink(&outside, target.join("link")).unwrap();
+
+        unbundle(dir.path(), &target, &[("link/newdir/file.txt", "x")]);
+        assert!(!outside.join("newdir").exists());
+    }
+
+    #[test]
+    fn refuses_writing_through_a_symlinked_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let (target, outside) = (dir.path().join("target"), dir.path().join("outside"));
+        fs::create_dir_all(&target).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(outside.join("victim.txt"), "safe").unwrap();
+        symlink(outside.join("victim.txt"), target.join("evil")).unwrap();
+
+        unbundle(dir.path(), &target, &[("evil", "pwned")]);
+        assert_eq!(
+            fs::read_to_string(outside.join("victim.txt")).unwrap(),
+            "safe"
+        );
+    }
+
+    #[test]
+    fn refuses_parent_and_absolute_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("target");
+        let escape = dir.path().join("escaped.txt");
+        unbundle(
+            dir.path(),
+            &target,
+            &[("../escaped.txt", "x"), (escape.to_str().unwrap(), "x")],
+        );
+        assert!(!escape.exists());
+    }
+
+    #[test]
+    fn writes_and_replaces_plain_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("target");
+        fs::create_dir_all(target.join("src")).unwrap();
+        fs::write(target.join("src/main.rs"), "old").unwrap();
+
+        unbundle(
+            dir.path(),
+            &target,
+            &[("src/main.rs", "new"), ("a/b/c.txt", "c")],
+        );
+        assert_eq!(
+            fs::read_to_string(target.join("src/main.rs")).unwrap(),
+            "new\n"
+        );
+        assert_eq!(fs::read_to_string(target.join("a/b/c.txt")).unwrap(), "c\n");
+    }
+}
@@ -0,0 +1,99 @@
+//! `--format xml`: files as `<file>` elements inside a `<documents>` root,
+//! the shape Claude-style prompts use.
+//!
+//! ```text
+//! <documents root="/repo">
+//! <file path="src/main.rs" lang="rust">
+//! fn main() {}
+//! </file>
+//! <file path="logo.png" lang="text" skipped="looks like a binary file"/>
+//! </documents>
+//! ```
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::FileContent;
+
+/// `s` with `&`, `<` and `>` escaped, plus `"` when `in_attribute`.
+/// Characters XML 1.0 doesn't allow even as references (control
+/// characters other than tab, LF and CR, U+FFFE and U+FFFF) become U+FFFD.
+pub fn escape(s: &str, in_attribute: bool) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' if in_attribute => out.push_str("&quot;"),
+            '\t' | '\n' | '\r' => out.push(c),
+            '\0'..='\u{1F}' | '\u{FFFE}' | '\u{FFFF}' => out.push('\u{FFFD}'),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+pub fn write_document_header(out: &mut impl Write, root: &str) -> io::Result<()> {
+    writeln!(out, r#"<documents root="{}">"#, escape(root, true))
+}
+
+pub fn write_document_footer(out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "</documents>")
+}
+
+/// One file as a `<file>` element. Truncation and transcoding are noted as
+/// `truncated="true"` and `encoding="..."`; a skipped file is an empty
+/// element with a `skipped` reason.
+pub fn write_file(
+    out: &mut impl Write,
+    rel: &Path,
+    lang: &str,
+    content: &FileContent,
+) -> io::Result<()> {
+    let mut attrs = format!(
+        r#"path="{}" lang="{}""#,
+        escape(&rel.display().to_string(), true),
+        escape(lang, true)
+    );
+    let FileContent::Text {
+        text,
+        truncated,
+        encoding_note,
+    } = content
+    else {
+        let reason = content.skip_reason().unwrap_or_default();
+        return writeln!(
+            out,
+            r#"<file {attrs} skipped="{}"/>"#,
+            escape(&reason, true)
+        );
+    };
+
+    if truncated.is_some() {
+        attrs.push_str(r#" truncated="true""#);
+    }
+    if let Some(note) = encoding_note {
+        attrs.push_str(&format!(r#" encoding="{}""#, escape(note, true)));
+    }
+    writeln!(out, "<file {attrs}>")?;
+    write!(out, "{}", escape(text, false))?;
+    if !text.is_empty() && !text.ends_with('\n') {
+        writeln!(out)?;
+    }
+    writeln!(out, "</file>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn characters_xml_forbids_are_replaced() {
+        assert_eq!(
+            escape("a\0b\x07c\td\r\ne\u{1B}[0m\u{FFFF}<&>", false),
+            "a\u{FFFD}b\u{FFFD}c\td\r\ne\u{FFFD}[0m\u{FFFD}&lt;&amp;&gt;"
+        );
+        assert_eq!(escape("\"\x01", true), "&quot;\u{FFFD}");
+    }
+}
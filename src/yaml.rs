@@ -0,0 +1,64 @@
+//! `--front-matter`: a `---`-delimited YAML block ahead of the Markdown
+//! dump, for static-site generators and other front-matter readers.
+
+use std::io::{self, Write};
+
+/// What the front matter records about a dump.
+pub struct FrontMatter<'a> {
+    /// The displayed roots; one is written as `root`, several as `roots`.
+    pub roots: &'a [String],
+    /// RFC 3339 time the dump was made.
+    pub generated: &'a str,
+    pub files: usize,
+    /// Size on disk of the included files.
+    pub total_bytes: u64,
+}
+
+/// `s` as a double-quoted YAML scalar. Double quotes are the only style
+/// that can hold any string (leading `-`, `: `, `#`, line breaks, ...).
+pub fn string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            // Line breaks YAML would otherwise fold or reject.
+            '\u{85}' => out.push_str("\\N"),
+            '\u{2028}' => out.push_str("\\L"),
+            '\u{2029}' => out.push_str("\\P"),
+            '\u{feff}' => out.push_str("\\uFEFF"),
+            c if c.is_control() => out.push_str(&format!("\\x{:02X}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// The whole block, closing `---` and a blank line included.
+pub fn write_front_matter(out: &mut impl Write, meta: &FrontMatter) -> io::Result<()> {
+    writeln!(out, "---")?;
+    match meta.roots {
+        [root] => writeln!(out, "root: {}", string(root))?,
+        roots => {
+            writeln!(out, "roots:")?;
+            for root in roots {
+                writeln!(out, "  - {}", string(root))?;
+            }
+        }
+    }
+    writeln!(out, "generated: {}", string(meta.generated))?;
+    writeln!(out, "file_count: {}", meta.files)?;
+    writeln!(out, "total_bytes: {}", meta.total_bytes)?;
+    writeln!(
+        out,
+        "tool: {}",
+        string(concat!("dir2prompt ", env!("CARGO_PKG_VERSION")))
+    )?;
+    writeln!(out, "---")?;
+    writeln!(out)
+}
@@ -0,0 +1,47 @@
+//! The binary's exit status and error output.
+
+use std::process::Command;
+
+#[test]
+fn errors_are_reported_with_display_and_exit_1() {
+    let dir = tempfile::tempdir().unwrap();
+    let missing = dir.path().join("missing-ignore");
+    let output = Command::new(env!("CARGO_BIN_EXE_dir2prompt"))
+        .arg("--no-config")
+        .arg("--global-excludes")
+        .arg(&missing)
+        .arg(dir.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.starts_with(&format!("dir2prompt: {}: ", missing.display())),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("Custom"), "{stderr}");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn clipboard_helper_fails_without_a_display() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_dir2prompt"))
+        .args(["--no-config", "--serve-clipboard"])
+        .env_remove("DISPLAY")
+        .env_remove("WAYLAND_DISPLAY")
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"copied").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.starts_with("dir2prompt: no clipboard available"),
+        "{stderr}"
+    );
+}
@@ -0,0 +1,284 @@
+//! End-to-end dumps of small temporary trees through the library API.
+
+use std::fs;
+use std::path::Path;
+
+use dir2prompt::{Config, SortMode};
+
+/// Write `files` (path, content) under `dir`, creating parents.
+fn write_tree(dir: &Path, files: &[(&str, &[u8])]) {
+    for (path, content) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+}
+
+/// The Markdown dump of `dir` with the flags `configure` sets.
+fn dump(dir: &Path, configure: impl FnOnce(&mut Config)) -> String {
+    let mut config = Config {
+        roots: vec![dir.to_path_buf()],
+        quiet: true,
+        ..Config::default()
+    };
+    configure(&mut config);
+    let mut out = Vec::new();
+    dir2prompt::dump(&config, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn hidden_skips_are_still_listed_at_the_end() {
+    let dir = tempfile::tempdir().unwrap();
+    write_tree(
+        dir.path(),
+        &[
+            ("main.rs", b"fn main() {}\n"),
+            ("logo.png", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+            ("empty.py", b""),
+        ],
+    );
+    let out = dump(dir.path(), |c| c.hide_skipped = true);
+    assert!(!out.contains("## `logo.png`"));
+    assert!(!out.contains("## `empty.py`"));
+    let skipped = &out[out.find("## Skipped files").unwrap()..];
+    assert!(skipped.contains("- `logo.png`"));
+    assert!(skipped.contains("- `empty.py`"));
+}
+
+#[test]
+fn archive_roots_apply_the_walk_filters() {
+    use std::io::Write;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("project.zip");
+    let mut zip = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+    let options = zip::write::SimpleFileOptions::default();
+    for (name, data) in [
+        ("src/main.rs", &b"fn main() {}\n"[..]),
+        ("node_modules/dep/index.js", b"module.exports = 1;\n"),
+        ("big.txt", &[b'x'; 4096]),
+    ] {
+        zip.start_file(name, options).unwrap();
+        zip.write_all(data).unwrap();
+    }
+    zip.finish().unwrap();
+
+    let out = dump(&path, |c| c.max_file_size = Some(1024));
+    assert!(out.contains("## `src/main.rs`"));
+    assert!(!out.contains("node_modules"));
+    assert!(out.contains("## `big.txt`\n\n(skipped: file too large)"));
+}
+
+/// The file sections of a Markdown dump, in order.
+fn sections(out: &str) -> Vec<&str> {
+    out.lines()
+        .filter_map(|l| l.strip_prefix("## `")?.split('`').next())
+        .collect()
+}
+
+#[test]
+fn reverse_inverts_every_sort_and_grouping() {
+    let dir = tempfile::tempdir().unwrap();
+    write_tree(
+        dir.path(),
+        &[
+            ("README.md", b"readme"),
+            ("Cargo.toml", b"[package]"),
+            ("src/main.rs", b"fn main() {}"),
+            ("src/lib.rs", b"pub fn lib() {}\n// longer"),
+            ("src/util/mod.rs", b"x"),
+            ("docs/guide.md", b"guide guide guide guide"),
+            ("Makefile", b"all:"),
+        ],
+    );
+    let sorts = [SortMode::Path, SortMode::Size, SortMode::Extension];
+    for sort in sorts {
+        for (readmes_first, manifests_first) in [(false, false), (true, false), (false, true)] {
+            for group_by_dir in [false, true] {
+                let configure = |c: &mut Config| {
+                    c.sort = sort;
+                    c.readmes_first = readmes_first;
+                    c.manifests_first = manifests_first;
+                    c.group_by_dir = group_by_dir;
+                };
+                let forward = dump(dir.path(), configure);
+                let reversed = dump(dir.path(), |c| {
+                    configure(c);
+                    c.reverse = true;
+                });
+                let mut expected = sections(&forward);
+                assert_eq!(expected.len(), 7);
+                expected.reverse();
+                let case = format!("{sort:?} {readmes_first} {manifests_first} {group_by_dir}");
+                assert_eq!(sections(&reversed), expected, "{case}");
+                if group_by_dir {
+                    // Each file still sits under its own directory's heading.
+                    let mut dir = None;
+                    for line in reversed.lines() {
+                        if let Some(heading) = line.strip_prefix("# Directory: ") {
+                            dir = Some(heading);
+                        } else if let Some(label) = line.strip_prefix("## `") {
+                            let parent = Path::new(label.split('`').next().unwrap()).parent();
+                            let parent = parent.unwrap().to_str().unwrap();
+                            let want = if parent.is_empty() { "(root)" } else { parent };
+                            assert_eq!(dir, Some(want), "{case}: {label}");
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The section for file `label` in a Markdown dump, from its heading up
+/// to the next file's.
+fn section<'a>(out: &'a str, label: &str) -> &'a str {
+    let start = out.find(&format!("## `{label}`")).unwrap();
+    let rest = &out[start..];
+    let end = rest[1..].find("\n## `").map_or(rest.len(), |i| i + 1);
+    &rest[..end]
+}
+
+#[test]
+fn utf16_sources_are_decoded_not_skipped_as_binary() {
+    let source = "fn main() {\n    println!(\"héllo\");\n}\n";
+    let le: Vec<u8> = source.encode_utf16().flat_map(u16::to_le_bytes).collect();
+    let be: Vec<u8> = source.encode_utf16().flat_map(u16::to_be_bytes).collect();
+    let dir = tempfile::tempdir().unwrap();
+    write_tree(
+        dir.path(),
+        &[
+            ("le.rs", &[&[0xFF, 0xFE][..], &le].concat()),
+            ("be.rs", &[&[0xFE, 0xFF][..], &be].concat()),
+            ("le_no_bom.rs", &le),
+        ],
+    );
+    let out = dump(dir.path(), |_| {});
+    assert!(!out.contains("binary"), "{out}");
+    for (name, note) in [
+        ("le.rs", "decoded from UTF-16LE"),
+        ("be.rs", "decoded from UTF-16BE"),
+        ("le_no_bom.rs", "decoded from UTF-16LE (no BOM)"),
+    ] {
+        let section = section(&out, name);
+        assert!(section.contains(source), "{name}: {section}");
+        assert!(section.contains(note), "{name}: {section}");
+    }
+}
+
+#[test]
+fn fenced_content_cannot_close_its_block() {
+    // Over a MiB, so it is streamed from disk rather than read whole.
+    let big = format!("```\n{}", "x\n".repeat(600_000));
+    let dir = tempfile::tempdir().unwrap();
+    write_tree(
+        dir.path(),
+        &[
+            ("block.md", b"```rust\nfn main() {}\n```\n"),
+            ("open.md", b"Run:\n\n```sh\nmake"),
+            ("big.md", big.as_bytes()),
+        ],
+    );
+    let out = dump(dir.path(), |c| c.max_bytes = 2 << 20);
+    assert!(section(&out, "block.md").contains("````markdown\n```rust\nfn main() {}\n```\n````\n"));
+    assert!(section(&out, "open.md").contains("````markdown\nRun:\n\n```sh\nmake\n````\n"));
+    let big_section = section(&out, "big.md");
+    assert!(big_section.contains("````markdown\n```\nx\n"));
+    assert!(big_section.ends_with("x\n````\n"));
+}
+
+#[test]
+fn utf8_bom_is_stripped() {
+    let dir = tempfile::tempdir().unwrap();
+    write_tree(
+        dir.path(),
+        &[
+            ("win.rs", b"\xEF\xBB\xBFfn main() {}\r\n"),
+            ("bom_only.txt", b"\xEF\xBB\xBF"),
+        ],
+    );
+    let out = dump(dir.path(), |c| c.include_empty = true);
+    assert!(!out.contains('\u{FEFF}'));
+    assert!(section(&out, "win.rs").contains("```rust\nfn main() {}\r\n```"));
+    assert!(section(&out, "bom_only.txt").contains("```text\n\n```"));
+}
+
+#[test]
+fn json_document_carries_the_full_summary() {
+    let dir = tempfile::tempdir().unwrap();
+    write_tree(
+        dir.path(),
+        &[("main.rs", b"fn main() {}\n"), ("logo.png", b"\x89PNG\0\0")],
+    );
+    let config = Config {
+        roots: vec![dir.path().to_path_buf()],
+        quiet: true,
+        format: dir2prompt::Format::Json,
+        ..Config::default()
+    };
+    let mut out = Vec::new();
+    let summary = dir2prompt::dump(&config, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let document = out.trim_end().rsplit_once(r#""summary":"#).unwrap().1;
+    let document = document.strip_suffix('}').unwrap();
+    // Every counter but the document's own size.
+    let expected = summary.to_json();
+    let expected = expected.split(r#","bytes_written""#).next().unwrap();
+    assert_eq!(document, format!("{expected}}}"));
+    assert!(document.contains(r#""skipped_binary":1"#));
+    assert!(document.contains(r#""printed_bytes":13"#));
+}
+
+#[test]
+fn bundles_round_trip_empty_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let files: &[(&str, &[u8])] = &[
+        ("pkg/__init__.py", b""),
+        ("pkg/main.py", b"print('hi')\n"),
+        ("empty.py", b""),
+    ];
+    write_tree(dir.path(), files);
+    let config = Config {
+        roots: vec![dir.path().to_path_buf()],
+        quiet: true,
+        format: dir2prompt::Format::Bundle,
+        ..Config::default()
+    };
+    let mut out = Vec::new();
+    let summary = dir2prompt::dump(&config, &mut out).unwrap();
+    assert_eq!((summary.printed, summary.skipped_empty), (3, 0));
+
+    let scratch = tempfile::tempdir().unwrap();
+    let bundle = scratch.path().join("dump.bundle");
+    fs::write(&bundle, out).unwrap();
+    let target = scratch.path().join("out");
+    dir2prompt::unbundle(&bundle, &target).unwrap();
+    for (path, content) in files {
+        assert_eq!(fs::read(target.join(path)).unwrap(), *content, "{path}");
+    }
+}
+
+#[test]
+fn toc_links_only_files_with_sections() {
+    let dir = tempfile::tempdir().unwrap();
+    write_tree(
+        dir.path(),
+        &[("main.rs", b"fn main() {}\n"), ("logo.png", b"\x89PNG\0\0")],
+    );
+    for configure in [
+        |c: &mut Config| c.no_inline_skips = true,
+        |c: &mut Config| c.hide_skipped = true,
+    ] {
+        let out = dump(dir.path(), |c| {
+            c.toc = true;
+            configure(c);
+        });
+        assert!(out.contains("- [`main.rs`](#mainrs)\n"), "{out}");
+        assert!(out.contains("\n## `main.rs`\n"), "{out}");
+        assert!(out.contains("- `logo.png`\n"), "{out}");
+        assert!(!out.contains("(#logopng)"), "{out}");
+    }
+    let out = dump(dir.path(), |c| c.toc = true);
+    assert!(out.contains("- [`logo.png`](#logopng)\n"), "{out}");
+}
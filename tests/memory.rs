@@ -0,0 +1,100 @@
+//! Peak heap use of a `--format jsonl` dump, measured by a counting
+//! allocator. A test binary of its own, so no other test allocates while
+//! it measures.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use dir2prompt::{Config, Format};
+
+struct Counting;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            let now = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(now, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// Discards the dump, checking that no more than one file's record is
+/// ever written without a flush.
+#[derive(Default)]
+struct Sink {
+    written: usize,
+    unflushed: usize,
+    most_unflushed: usize,
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written += buf.len();
+        self.unflushed += buf.len();
+        self.most_unflushed = self.most_unflushed.max(self.unflushed);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.unflushed = 0;
+        Ok(())
+    }
+}
+
+/// Bytes per synthetic file.
+const FILE_BYTES: usize = 16 * 1024;
+
+/// Dump `files` synthetic files as JSON lines; returns the bytes written,
+/// the most written between flushes and the peak heap growth.
+fn dump_jsonl(files: usize) -> (usize, usize, usize) {
+    let dir = tempfile::tempdir().unwrap();
+    let line = "let value = compute(input) + 1; // synthetic\n";
+    let text = line.repeat(FILE_BYTES / line.len());
+    for i in 0..files {
+        let sub = dir.path().join(format!("d{}", i % 32));
+        std::fs::create_dir_all(&sub).unwrap();
+        std::fs::write(sub.join(format!("f{i}.rs")), &text).unwrap();
+    }
+    let config = Config {
+        roots: vec![dir.path().to_path_buf()],
+        quiet: true,
+        format: Format::Jsonl,
+        jobs: Some(2),
+        ..Config::default()
+    };
+
+    let mut sink = Sink::default();
+    let base = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(base, Ordering::Relaxed);
+    dir2prompt::dump(&config, &mut sink).unwrap();
+    let peak = PEAK.load(Ordering::Relaxed) - base;
+    (sink.written, sink.most_unflushed, peak)
+}
+
+#[test]
+fn jsonl_memory_does_not_grow_with_the_repo() {
+    let (small_written, _, small_peak) = dump_jsonl(100);
+    let (written, most_unflushed, peak) = dump_jsonl(2000);
+    assert!(written > 2000 * FILE_BYTES);
+    assert!(written > 10 * small_written);
+    // One record (the file, JSON-escaped) at a time reaches the sink.
+    assert!(most_unflushed < 2 * FILE_BYTES, "{most_unflushed}");
+    // Files are read ahead in a fixed batch, so twenty times the files
+    // costs paths and labels, not content.
+    assert!(peak < 4 * 1024 * 1024, "peak {peak} bytes");
+    assert!(peak < 3 * small_peak, "{peak} vs {small_peak}");
+}